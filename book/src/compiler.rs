@@ -0,0 +1,265 @@
+use common::{Color, Game, GameResult};
+use pgn::{start_position, PgnError, Reader};
+
+use std::collections::HashMap;
+use std::io::BufReader;
+
+use crate::{PolyglotBook, PolyglotEntry};
+
+/// How much each game adds to the weight of the moves played in it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Weighting {
+    /// Two points for a win, one for a draw and none for a loss, the same as polyglot.
+    Result,
+    /// Every game counts the same, so the weight is how popular the move is.
+    Games,
+}
+
+impl Weighting {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        match input {
+            "result" => Ok(Weighting::Result),
+            "games" => Ok(Weighting::Games),
+            _ => Err(format!(
+                "Invalid weighting '{input}', expected result or games"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    /// Moves after this many plies into the game are not added to the book.
+    pub max_ply: usize,
+    /// Moves that were played in fewer games than this are left out of the book.
+    pub min_games: u32,
+    /// Only add the moves played by this side.
+    pub color: Option<Color>,
+    pub weighting: Weighting,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            max_ply: 40,
+            min_games: 1,
+            color: None,
+            weighting: Weighting::Result,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MoveStats {
+    games: u32,
+    score: u64,
+}
+
+/// Builds a book from the games in PGN files. The games are counted up as they are added and the
+/// weights are only worked out when the book is built, so the minimum game count can be applied
+/// over all of the files.
+///
+/// ```
+/// let mut compiler = book::BookCompiler::new(book::CompileOptions::default());
+/// for game in pgn::parse("1. e4 e5 1-0\n\n1. e4 c5 0-1\n\n1. d4 d5 1/2-1/2").unwrap() {
+///     compiler.add_game(&game).unwrap();
+/// }
+///
+/// let book = compiler.build();
+/// let board = common::Board::from_start_position().unwrap();
+/// let weights: Vec<u16> = book.entries[&board.hash()].iter().map(|e| e.weight).collect();
+///
+/// // e4 won one game and lost one, d4 drew
+/// assert_eq!(weights, [2, 1]);
+/// ```
+pub struct BookCompiler {
+    options: CompileOptions,
+    moves: HashMap<u64, HashMap<u16, MoveStats>>,
+}
+
+impl BookCompiler {
+    pub fn new(options: CompileOptions) -> Self {
+        Self {
+            options,
+            moves: HashMap::new(),
+        }
+    }
+
+    pub fn add_game(&mut self, game: &Game) -> Result<(), String> {
+        let mut board = start_position(&game.metadata).map_err(|e| e.to_string())?;
+
+        for (ply, movement) in game.history.iter().enumerate() {
+            if ply >= self.options.max_ply {
+                break;
+            }
+
+            if self.options.color.is_none_or(|color| color == board.turn) {
+                let score = match (self.options.weighting, &game.result, board.turn) {
+                    (Weighting::Games, _, _) => 1,
+                    (Weighting::Result, GameResult::WhiteWin, Color::White) => 2,
+                    (Weighting::Result, GameResult::BlackWin, Color::Black) => 2,
+                    (Weighting::Result, GameResult::Draw, _) => 1,
+                    (Weighting::Result, _, _) => 0,
+                };
+
+                let mv = PolyglotEntry::from_move(movement).mv;
+                let stats = self
+                    .moves
+                    .entry(board.hash())
+                    .or_default()
+                    .entry(mv)
+                    .or_default();
+
+                stats.games += 1;
+                stats.score += score;
+            }
+
+            board.move_piece(*movement);
+        }
+
+        Ok(())
+    }
+
+    /// Adds all the games in a PGN file, any games that can't be parsed are skipped and their
+    /// errors are returned so the caller can report them.
+    pub fn add_pgn_file(&mut self, path: &str) -> Result<Vec<PgnError>, String> {
+        let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+        let mut skipped = Vec::new();
+
+        for game in Reader::new(BufReader::new(file)) {
+            match game {
+                Ok(game) => self.add_game(&game)?,
+                Err(err) => skipped.push(err),
+            }
+        }
+
+        Ok(skipped)
+    }
+
+    /// Builds the book, the weights are the score of each move. If any of the scores are too big
+    /// to fit in a weight they are all scaled down so the biggest one is `u16::MAX`.
+    pub fn build(self) -> PolyglotBook {
+        let max_score = self
+            .moves
+            .values()
+            .flat_map(|moves| moves.values())
+            .map(|stats| stats.score)
+            .max()
+            .unwrap_or(0);
+
+        let scale = |score: u64| match max_score > u16::MAX as u64 {
+            true => (score * u16::MAX as u64 / max_score) as u16,
+            false => score as u16,
+        };
+
+        let mut book = PolyglotBook::default();
+        for (key, moves) in self.moves {
+            let mut moves: Vec<(u16, MoveStats)> = moves
+                .into_iter()
+                .filter(|(_, stats)| stats.games >= self.options.min_games)
+                .collect();
+
+            // Keep the order stable, the best moves first
+            moves.sort_by(|(a_mv, a), (b_mv, b)| b.score.cmp(&a.score).then(a_mv.cmp(b_mv)));
+
+            for (mv, stats) in moves {
+                book.insert(
+                    key,
+                    PolyglotEntry {
+                        mv,
+                        weight: scale(stats.score),
+                        learn: 0,
+                    },
+                );
+            }
+        }
+
+        book
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::Board;
+
+    fn compile(options: CompileOptions, pgn: &str) -> PolyglotBook {
+        let mut compiler = BookCompiler::new(options);
+        for game in pgn::parse(pgn).unwrap() {
+            compiler.add_game(&game).unwrap();
+        }
+
+        compiler.build()
+    }
+
+    fn weights(book: &PolyglotBook, moves: &[&str]) -> Vec<(String, u16)> {
+        let mut board = Board::from_start_position().unwrap();
+        for uci in moves {
+            let movement = common::legal_moves(&board)
+                .into_iter()
+                .find(|m| m.uci() == *uci)
+                .unwrap();
+            board.move_piece(movement);
+        }
+
+        book.probe(&board)
+            .into_iter()
+            .map(|(movement, weight)| (movement.uci(), weight))
+            .collect()
+    }
+
+    const GAMES: &str = "1. e4 e5 2. Nf3 1-0\n\n1. e4 c5 0-1\n\n1. e4 e5 1/2-1/2\n\n1. d4 d5 1-0";
+
+    #[test]
+    fn weights_moves_by_result() {
+        let book = compile(CompileOptions::default(), GAMES);
+
+        assert_eq!(
+            weights(&book, &[]),
+            [("e2e4".to_string(), 3), ("d2d4".to_string(), 2)]
+        );
+        assert_eq!(
+            weights(&book, &["e2e4"]),
+            [("c7c5".to_string(), 2), ("e7e5".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn returns_the_games_it_skips() {
+        let path = std::env::temp_dir().join(format!("ceir-compile-{}.pgn", std::process::id()));
+        std::fs::write(&path, "1. e4 e5 1-0\n\n1. e4 e4 0-1\n\n1. d4 d5 1-0\n").unwrap();
+
+        let mut compiler = BookCompiler::new(CompileOptions::default());
+        let skipped = compiler.add_pgn_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(
+            weights(&compiler.build(), &[]),
+            [("d2d4".to_string(), 2), ("e2e4".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn applies_the_options() {
+        let options = CompileOptions {
+            max_ply: 1,
+            min_games: 2,
+            color: None,
+            weighting: Weighting::Games,
+        };
+
+        let book = compile(options, GAMES);
+        assert_eq!(weights(&book, &[]), [("e2e4".to_string(), 3)]);
+        assert!(weights(&book, &["e2e4"]).is_empty());
+
+        let options = CompileOptions {
+            color: Some(Color::Black),
+            ..Default::default()
+        };
+
+        let book = compile(options, GAMES);
+        assert!(weights(&book, &[]).is_empty());
+        assert_eq!(weights(&book, &["d2d4"]), [("d7d5".to_string(), 0)]);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{PolyglotBook, PolyglotEntry};
+
+/// The result of a game from the point of view of the side that played the book move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+impl Outcome {
+    /// The score in half points, so it can be stored in the learn field without any rounding.
+    fn half_points(&self) -> u32 {
+        match self {
+            Outcome::Win => 2,
+            Outcome::Draw => 1,
+            Outcome::Loss => 0,
+        }
+    }
+}
+
+/// The learn field is not defined by polyglot, we use the top 16 bits for the number of games
+/// the move has been learned from and the bottom 16 bits for the score in half points.
+impl PolyglotEntry {
+    /// The number of games the move has been learned from.
+    pub fn learned_games(&self) -> u32 {
+        self.learn >> 16
+    }
+
+    /// The score of the games the move has been learned from in half points.
+    pub fn learned_score(&self) -> u32 {
+        self.learn & 0xffff
+    }
+
+    /// Adds the outcome of a game to the learn field and moves the weight up for a win or down
+    /// for a loss. The weight changes by an eighth so a move that keeps losing is soon never
+    /// played, moves with a weight of 0 are never picked from the book.
+    fn record(&mut self, outcome: Outcome) {
+        let mut games = self.learned_games() + 1;
+        let mut score = self.learned_score() + outcome.half_points();
+
+        // Forget half of what we have learned when the counts get too big, this also means newer
+        // games count for more
+        if games > 0xffff || score > 0xffff {
+            games /= 2;
+            score /= 2;
+        }
+
+        self.learn = games << 16 | score;
+
+        let step = (self.weight / 8).max(1);
+        self.weight = match outcome {
+            Outcome::Win => self.weight.saturating_add(step),
+            Outcome::Draw => self.weight,
+            Outcome::Loss => self.weight.saturating_sub(step),
+        };
+    }
+}
+
+impl PolyglotBook {
+    /// Learns from the outcome of a game where the move was played from the book. Returns false
+    /// if the move is not in the book.
+    ///
+    /// ```
+    /// use book::{Outcome, PolyglotBook, PolyglotEntry};
+    ///
+    /// let mut book = PolyglotBook::default();
+    /// book.insert(1, PolyglotEntry { mv: 0x031c, weight: 16, learn: 0 });
+    ///
+    /// assert!(book.learn(1, 0x031c, Outcome::Win));
+    /// assert!(book.learn(1, 0x031c, Outcome::Draw));
+    ///
+    /// let entry = &book.entries[&1][0];
+    /// assert_eq!((entry.weight, entry.learned_games(), entry.learned_score()), (18, 2, 3));
+    /// ```
+    pub fn learn(&mut self, key: u64, mv: u16, outcome: Outcome) -> bool {
+        let entry = self
+            .entries
+            .get_mut(&key)
+            .and_then(|entries| entries.iter_mut().find(|entry| entry.mv == mv));
+
+        match entry {
+            Some(entry) => {
+                entry.record(outcome);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn learns_from_results() {
+        let mut book = PolyglotBook::default();
+        book.insert(
+            1,
+            PolyglotEntry {
+                mv: 0x031c,
+                weight: 2,
+                learn: 0,
+            },
+        );
+
+        assert!(!book.learn(2, 0x031c, Outcome::Win));
+        assert!(!book.learn(1, 0x02db, Outcome::Win));
+
+        // The weight always moves by at least one, and stops at 0
+        for _ in 0..3 {
+            book.learn(1, 0x031c, Outcome::Loss);
+        }
+
+        let entry = &book.entries[&1][0];
+        assert_eq!(entry.weight, 0);
+        assert_eq!((entry.learned_games(), entry.learned_score()), (3, 0));
+    }
+
+    #[test]
+    fn halves_the_counts_when_they_are_full() {
+        let mut entry = PolyglotEntry {
+            mv: 0x031c,
+            weight: u16::MAX,
+            learn: 40000 << 16 | 0xfffe,
+        };
+
+        entry.record(Outcome::Win);
+        assert_eq!(entry.weight, u16::MAX);
+        assert_eq!(
+            (entry.learned_games(), entry.learned_score()),
+            (20000, 0x8000)
+        );
+    }
+}
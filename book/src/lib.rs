@@ -0,0 +1,8 @@
+mod compiler;
+pub use crate::compiler::*;
+
+mod learn;
+pub use crate::learn::*;
+
+mod polyglot;
+pub use crate::polyglot::*;
@@ -1,169 +1,216 @@
-use common::{AmbiguousMovement, Board, Color, Game, GameResult, Piece, ResolvedMovement, Square};
-use pgn::parse;
+use book::{BookCompiler, CompileOptions, MergePolicy, PolyglotBook, Weighting};
+use common::{legal_moves, Board, Color, Fen, Movement, ResolvedMovement};
 
 use cli::{deindent, ArgBuilder};
 
-use std::collections::HashMap;
-use std::io::{BufReader, Read};
-
-#[derive(Debug, Clone)]
-pub struct PolyglotEntry {
-    pub mv: u16,
-    pub weight: u16,
-    pub learn: u32,
+use std::str::FromStr;
+
+enum Command {
+    /// Print the moves in the book for a position.
+    Query {
+        fen: Option<String>,
+        moves: Vec<String>,
+    },
+    /// Combine the books into one and write it out.
+    Merge { output: String, policy: MergePolicy },
+    /// Print every entry in the book.
+    Dump,
 }
 
-impl PolyglotEntry {
-    fn from_bytes(bytes: &[u8]) -> Self {
-        let mv = u16::from_be_bytes(bytes[0..2].try_into().unwrap());
-        let weight = u16::from_be_bytes(bytes[2..4].try_into().unwrap());
-        let learn = u32::from_be_bytes(bytes[4..8].try_into().unwrap());
+struct Args {
+    pub command: Command,
+    pub files: Vec<String>,
+    pub min_weight: u16,
+    pub options: CompileOptions,
+}
 
-        PolyglotEntry { mv, weight, learn }
+fn number<T: std::str::FromStr>(
+    args: &ArgBuilder,
+    short: &str,
+    long: &str,
+    default: T,
+) -> Result<T, String> {
+    match args.string(short, long) {
+        Ok(value) => value
+            .parse()
+            .map_err(|_| format!("Invalid value '{value}' for {long}")),
+        Err(_) => Ok(default),
     }
+}
 
-    fn from_move(movement: &ResolvedMovement) -> Self {
-        let promotion = match movement.promotion {
-            Some(Piece::Queen) => 4,
-            Some(Piece::Rook) => 3,
-            Some(Piece::Bishop) => 2,
-            Some(Piece::Knight) => 1,
-            _ => 0,
-        };
+impl Args {
+    fn new() -> Result<Self, String> {
+        let args: Vec<String> = std::env::args().collect();
+        let command = args.get(1).cloned().unwrap_or_default();
+        let args = ArgBuilder::new(args);
+
+        if args.bool("-h", "--help") || command.is_empty() || command.starts_with('-') {
+            return Err(deindent(
+                r#"
+                    Usage: book <COMMAND> [OPTIONS]
+
+                    Commands:
+
+                        query                   Print the moves in the book for a position
+                        merge                   Combine the books into one polyglot book
+                        dump                    Print every entry in the book
+
+                    Options:
+
+                        --help, -h              Display this help message
+                        --file, -f <file>       The polyglot books or PGN files to load
+                        --min-weight <n>        Leave out moves with a weight below this (default 0)
+
+                    Query options:
+
+                        --fen <fen>             The position to query (default the start position)
+                        --moves <moves>         Moves in UCI or SAN to play from the position
 
-        let from = movement.from as usize;
-        let to = movement.to as usize;
-        let mv = (promotion << 12 | from << 6 | to) as u16;
+                    Merge options:
 
-        PolyglotEntry {
-            mv,
-            weight: 0,
-            learn: 0,
+                        --output, -o <file>     The file to write the merged book to
+                        --policy <policy>       How to combine the weights of a move that is in
+                                                more than one book, either sum, max, average or
+                                                first (default sum)
+
+                    PGN options:
+
+                        --max-ply, -p <n>       Only add the moves from this many plies into each game (default 40)
+                        --min-games, -m <n>     Leave out moves played in fewer games than this (default 1)
+                        --color, -c <color>     Only add the moves played by white or black (default both)
+                        --weight-by, -w <by>    Either result or games (default result)
+
+                    When weighting by result a move gets two points for each win and one for
+                    each draw, when weighting by games every game counts as one. Each PGN file is
+                    compiled into its own book before it is merged.
+                "#,
+            ));
         }
-    }
 
-    pub fn ambiguous_move(&self) -> AmbiguousMovement {
-        let from = Square::from_usize((self.mv >> 6 & common::random::SQ_MASK) as usize);
-        let to = Square::from_usize((self.mv & common::random::SQ_MASK) as usize);
-        let promotion = match self.mv >> 12 {
-            0 => None,
-            1 => Some(Piece::Knight),
-            2 => Some(Piece::Bishop),
-            3 => Some(Piece::Rook),
-            4 => Some(Piece::Queen),
-            _ => panic!("Invalid promotion_piece"),
+        let command = match command.as_str() {
+            "query" => Command::Query {
+                fen: args.string("--fen", "--fen").ok(),
+                moves: args.string_list("--moves", "--moves").unwrap_or_default(),
+            },
+            "merge" => Command::Merge {
+                output: args.string("-o", "--output")?,
+                policy: match args.string("--policy", "--policy") {
+                    Ok(policy) => MergePolicy::parse(&policy)?,
+                    Err(_) => MergePolicy::Sum,
+                },
+            },
+            "dump" => Command::Dump,
+            _ => return Err(format!("Unknown command '{command}', see book --help")),
         };
 
-        AmbiguousMovement {
-            file: None,
-            piece: None,
-            from: Some(from),
-            to,
-            capture: None,
-            promotion,
-        }
-    }
-}
+        let color = match args.string("-c", "--color") {
+            Ok(color) => Some(Color::from_str(&color)?),
+            Err(_) => None,
+        };
+
+        let weighting = match args.string("-w", "--weight-by") {
+            Ok(weighting) => Weighting::parse(&weighting)?,
+            Err(_) => Weighting::Result,
+        };
 
-#[derive(Debug, Clone, Default)]
-pub struct PolyglotBook {
-    pub entries: HashMap<u64, Vec<PolyglotEntry>>,
+        let defaults = CompileOptions::default();
+
+        Ok(Args {
+            command,
+            files: args.string_list("-f", "--file")?,
+            min_weight: number(&args, "--min-weight", "--min-weight", 0)?,
+            options: CompileOptions {
+                max_ply: number(&args, "-p", "--max-ply", defaults.max_ply)?,
+                min_games: number(&args, "-m", "--min-games", defaults.min_games)?,
+                color,
+                weighting,
+            },
+        })
+    }
 }
 
-impl PolyglotBook {
-    pub fn insert(&mut self, key: u64, entry: PolyglotEntry) {
-        match self.entries.get_mut(&key) {
-            Some(entries) => {
-                for e in entries.iter_mut() {
-                    if e.mv == entry.mv {
-                        e.weight += entry.weight;
-                        e.learn = entry.learn;
-                        return;
-                    }
-                }
-
-                entries.push(entry);
-            }
-            None => {
-                self.entries.insert(key, vec![entry]);
+fn load_book(file: &str, options: &CompileOptions) -> Result<PolyglotBook, String> {
+    match std::path::Path::new(file).extension() {
+        Some(ext) if ext == "bin" => {
+            let mut book = PolyglotBook::default();
+            book.load_bin_file(file)?;
+            Ok(book)
+        }
+        Some(ext) if ext == "pgn" => {
+            let mut compiler = BookCompiler::new(options.clone());
+            for err in compiler.add_pgn_file(file)? {
+                println!("Skipping invalid game in {file}\n    {err}");
             }
+
+            Ok(compiler.build())
         }
+        Some(ext) => Err(format!("Invalid file extension {}", ext.to_string_lossy())),
+        None => Err(format!("Unable to determine the file type of {file}")),
     }
+}
 
-    pub fn load_bin_file(&mut self, path: &str) -> Result<(), String> {
-        let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+/// Finds the position to query, the moves can be in UCI or SAN.
+fn query_board(fen: &Option<String>, moves: &[String]) -> Result<Board, String> {
+    let mut board = match fen {
+        Some(fen) => {
+            let mut board = Board::default();
+            board.load_fen(&Fen::new_strict(fen)?);
+            board
+        }
+        None => Board::from_start_position()?,
+    };
 
-        let mut reader = BufReader::new(file);
-        let mut buffer = [0u8; 16];
+    for text in moves {
+        let movement = match legal_moves(&board).into_iter().find(|m| m.uci() == *text) {
+            Some(movement) => movement,
+            None => Movement::from_san(text, &board)?,
+        };
 
-        while reader.read_exact(&mut buffer).is_ok() {
-            let key = u64::from_be_bytes(buffer[0..8].try_into().unwrap());
-            let entry = PolyglotEntry::from_bytes(&buffer[8..]);
+        board.move_piece(movement);
+    }
 
-            self.insert(key, entry);
-        }
+    Ok(board)
+}
 
-        Ok(())
+fn query(book: &PolyglotBook, board: &Board) {
+    let moves: Vec<(ResolvedMovement, u16)> = book.probe(board);
+    if moves.is_empty() {
+        println!("No moves in the book for {}", board.to_fen());
+        return;
     }
 
-    pub fn load_png_file(&mut self, path: &str) -> Result<(), String> {
-        let mut file = std::fs::File::open(path).map_err(|e| e.to_string())?;
-        let mut buf = vec![];
-        file.read_to_end(&mut buf).map_err(|e| e.to_string())?;
-
-        let content = String::from_utf8_lossy(&buf).into_owned();
-        let games = parse(&content).map_err(|e| e.to_string())?;
-
-        for game in games {
-            let mut board = Board::from_start_position().unwrap();
-            for item in game.history {
-                let key = board.hash().unwrap();
-                let mut entry = PolyglotEntry::from_move(&item);
-
-                match game.result {
-                    GameResult::WhiteWin => {
-                        if board.turn == Color::White {
-                            entry.weight = 2
-                        }
-                    }
-                    GameResult::BlackWin => {
-                        if board.turn == Color::Black {
-                            entry.weight = 2
-                        }
-                    }
-                    _ => entry.weight = 0,
-                }
-
-                self.insert(key, entry);
-                board.move_piece(item);
-            }
-        }
+    let total: u64 = moves.iter().map(|(_, weight)| *weight as u64).sum();
+    for (movement, weight) in moves {
+        let percent = match total {
+            0 => 0.0,
+            _ => weight as f64 * 100.0 / total as f64,
+        };
 
-        Ok(())
+        println!(
+            "{:<8} {:>6} {:>6.2}%",
+            movement.to_san(board),
+            weight,
+            percent
+        );
     }
 }
 
-struct Args {
-    pub files: Vec<String>,
-}
+fn dump(book: &PolyglotBook) {
+    let mut keys: Vec<&u64> = book.entries.keys().collect();
+    keys.sort();
 
-impl Args {
-    fn new() -> Result<Self, String> {
-        let args = ArgBuilder::new(std::env::args().collect());
-        if args.bool("-h", "--help") {
-            return Err(deindent(
-                r#"
-                    Usage: book [OPTIONS]
+    for key in keys {
+        let mut entries: Vec<_> = book.entries[key].iter().collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.weight));
 
-                        --help, -h         Display this help message
-                        --file, -f <file>  Specify the path to the polyglot book
-                "#,
-            ));
+        for entry in entries {
+            println!(
+                "{key:016x} {:<6} {:>6} {:>10}",
+                entry.uci(),
+                entry.weight,
+                entry.learn
+            );
         }
-
-        Ok(Args {
-            files: args.string_list("-f", "--file")?,
-        })
     }
 }
 
@@ -176,17 +223,10 @@ fn main() {
         }
     };
 
-    let mut book = PolyglotBook::default();
+    let mut books = Vec::new();
     for file in args.files.iter() {
-        let load_result = match std::path::Path::new(file).extension() {
-            Some(ext) if ext == "bin" => book.load_bin_file(file),
-            Some(ext) if ext == "pgn" => book.load_png_file(file),
-            Some(ext) => Err(format!("Invalid file extension {}", ext.to_string_lossy())),
-            None => Err(format!("Unable to determine the file type of {file}")),
-        };
-
-        match load_result {
-            Ok(_) => {}
+        match load_book(file, &args.options) {
+            Ok(book) => books.push(book),
             Err(e) => {
                 println!("Error loading {file}\n    {e}");
                 return;
@@ -194,14 +234,24 @@ fn main() {
         }
     }
 
-    let mut game = Game::default();
-    game.board = Board::from_start_position().unwrap();
+    let policy = match args.command {
+        Command::Merge { policy, .. } => policy,
+        _ => MergePolicy::Sum,
+    };
 
-    let hash = game.board.hash().unwrap();
-    if let Some(entries) = book.entries.get(&hash) {
-        for entry in entries {
-            let movement = entry.ambiguous_move();
-            println!("{}: {:?}", entry.weight, movement);
+    let mut book = PolyglotBook::merge(&books, policy);
+    book.retain_min_weight(args.min_weight);
+
+    match &args.command {
+        Command::Query { fen, moves } => match query_board(fen, moves) {
+            Ok(board) => query(&book, &board),
+            Err(e) => println!("Invalid position\n    {e}"),
+        },
+        Command::Merge { output, .. } => {
+            if let Err(e) = book.write_bin_file(output) {
+                println!("Error writing {output}\n    {e}");
+            }
         }
+        Command::Dump => dump(&book),
     }
 }
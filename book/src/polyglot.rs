@@ -0,0 +1,635 @@
+use common::{bb, legal_moves, Board, Piece, ResolvedMovement, Square};
+use std::collections::HashMap;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+use crate::{BookCompiler, CompileOptions};
+
+/// How a move is picked when there is more than one in the book.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BookMode {
+    /// Always play the move with the highest weight.
+    Best,
+    /// Pick a move at random, moves with a higher weight are more likely to be picked.
+    Weighted,
+}
+
+impl BookMode {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        match input.to_lowercase().as_str() {
+            "best" => Ok(BookMode::Best),
+            "weighted" => Ok(BookMode::Weighted),
+            _ => Err(format!(
+                "Invalid book mode '{input}', expected best or weighted"
+            )),
+        }
+    }
+}
+
+/// How the weights are combined when the same move is in more than one book.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergePolicy {
+    /// Add the weights together.
+    Sum,
+    /// Keep the highest weight.
+    Max,
+    /// The average weight over the books that have the move.
+    Average,
+    /// Keep the weight from the first book that has the move.
+    First,
+}
+
+impl MergePolicy {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        match input.to_lowercase().as_str() {
+            "sum" => Ok(MergePolicy::Sum),
+            "max" => Ok(MergePolicy::Max),
+            "average" => Ok(MergePolicy::Average),
+            "first" => Ok(MergePolicy::First),
+            _ => Err(format!(
+                "Invalid merge policy '{input}', expected sum, max, average or first"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PolyglotEntry {
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl PolyglotEntry {
+    fn to_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0u8; 8];
+        bytes[0..2].copy_from_slice(&self.mv.to_be_bytes());
+        bytes[2..4].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.learn.to_be_bytes());
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mv = u16::from_be_bytes(bytes[0..2].try_into().unwrap());
+        let weight = u16::from_be_bytes(bytes[2..4].try_into().unwrap());
+        let learn = u32::from_be_bytes(bytes[4..8].try_into().unwrap());
+
+        PolyglotEntry { mv, weight, learn }
+    }
+
+    pub fn from_move(movement: &ResolvedMovement) -> Self {
+        PolyglotEntry {
+            mv: encode_move(movement),
+            weight: 0,
+            learn: 0,
+        }
+    }
+
+    /// The move in UCI notation without a board, so castling is written the way polyglot stores
+    /// it with the king taking the rook.
+    pub fn uci(&self) -> String {
+        let (from, to, promotion) = unpack_move(self.mv);
+        match promotion {
+            Some(promotion) => format!("{}{}{}", from.uci(), to.uci(), promotion.to_lower()),
+            None => format!("{}{}", from.uci(), to.uci()),
+        }
+    }
+
+    /// The move for this entry on the board, if it is one of the legal moves.
+    pub fn movement(&self, board: &Board) -> Option<ResolvedMovement> {
+        decode_move(self.mv, board)
+    }
+}
+
+/// Polyglot numbers the squares from a1 to h8 where our squares go from h1 to a8. Both go up the
+/// board one rank at a time so only the file needs to be flipped.
+fn polyglot_square(square: Square) -> u16 {
+    square as u16 ^ 7
+}
+
+fn from_polyglot_square(square: u16) -> Square {
+    Square::from_usize((square ^ 7) as usize)
+}
+
+/// The castling moves as polyglot writes them, with the king taking its own rook, and the square
+/// the king really moves to.
+const CASTLING: [(Square, Square, Square); 4] = [
+    (Square::E1, Square::H1, Square::G1),
+    (Square::E1, Square::A1, Square::C1),
+    (Square::E8, Square::H8, Square::G8),
+    (Square::E8, Square::A8, Square::C8),
+];
+
+/// Packs a move into a polyglot move word. The bits from lowest to highest are the to square, the
+/// from square and the promotion piece, with castling written as the king taking the rook.
+///
+/// ```
+/// use common::{Board, Movement};
+///
+/// let board = Board::from_start_position().unwrap();
+/// let e4 = Movement::from_san("e4", &board).unwrap();
+/// assert_eq!(book::encode_move(&e4), 0x031c);
+/// ```
+pub fn encode_move(movement: &ResolvedMovement) -> u16 {
+    let promotion = match movement.promotion {
+        Some(Piece::Queen) => 4,
+        Some(Piece::Rook) => 3,
+        Some(Piece::Bishop) => 2,
+        Some(Piece::Knight) => 1,
+        _ => 0,
+    };
+
+    let to = match movement.piece {
+        Piece::King => CASTLING
+            .iter()
+            .find(|(from, _, to)| movement.from == *from && movement.to == *to)
+            .map_or(movement.to, |(_, rook, _)| *rook),
+        _ => movement.to,
+    };
+
+    promotion << 12 | polyglot_square(movement.from) << 6 | polyglot_square(to)
+}
+
+/// Splits a move word into its from square, to square and promotion piece.
+fn unpack_move(mv: u16) -> (Square, Square, Option<Piece>) {
+    let promotion = match mv >> 12 & 0x7 {
+        1 => Some(Piece::Knight),
+        2 => Some(Piece::Bishop),
+        3 => Some(Piece::Rook),
+        4 => Some(Piece::Queen),
+        _ => None,
+    };
+
+    (
+        from_polyglot_square(mv >> 6 & 0x3f),
+        from_polyglot_square(mv & 0x3f),
+        promotion,
+    )
+}
+
+/// Unpacks a polyglot move word into one of the legal moves on the board. Returns `None` if the
+/// move is not legal, the book may have an entry with a hash collision.
+pub fn decode_move(mv: u16, board: &Board) -> Option<ResolvedMovement> {
+    let (from, mut to, promotion) = unpack_move(mv);
+
+    // A king can never take its own rook, so this has to be castling
+    let is_king = matches!(board.get_piece_at(&bb!(from)), Some((_, Piece::King)));
+    if is_king {
+        if let Some((_, _, king_to)) = CASTLING
+            .iter()
+            .find(|(king_from, rook, _)| from == *king_from && to == *rook)
+        {
+            to = *king_to;
+        }
+    }
+
+    legal_moves(board)
+        .into_iter()
+        .find(|m| m.from == from && m.to == to && m.promotion == promotion)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PolyglotBook {
+    pub entries: HashMap<u64, Vec<PolyglotEntry>>,
+}
+
+impl PolyglotBook {
+    /// Adds an entry to the book, the weights are added together if the move is already there.
+    /// They stop at `u16::MAX` rather than overflowing when lots of books or games are added.
+    pub fn insert(&mut self, key: u64, entry: PolyglotEntry) {
+        match self.entries.get_mut(&key) {
+            Some(entries) => {
+                for e in entries.iter_mut() {
+                    if e.mv == entry.mv {
+                        e.weight = e.weight.saturating_add(entry.weight);
+                        e.learn = entry.learn;
+                        return;
+                    }
+                }
+
+                entries.push(entry);
+            }
+            None => {
+                self.entries.insert(key, vec![entry]);
+            }
+        }
+    }
+
+    pub fn load_bin_file(&mut self, path: &str) -> Result<(), String> {
+        let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+
+        let mut reader = BufReader::new(file);
+        let mut buffer = [0u8; 16];
+
+        while reader.read_exact(&mut buffer).is_ok() {
+            let key = u64::from_be_bytes(buffer[0..8].try_into().unwrap());
+            let entry = PolyglotEntry::from_bytes(&buffer[8..]);
+
+            self.insert(key, entry);
+        }
+
+        Ok(())
+    }
+
+    /// Adds the moves from the games in a PGN file using the default compile options, see
+    /// [`BookCompiler`] for more control over which moves go in the book.
+    pub fn load_png_file(&mut self, path: &str) -> Result<(), String> {
+        let mut compiler = BookCompiler::new(CompileOptions::default());
+        compiler.add_pgn_file(path)?;
+
+        for (key, entries) in compiler.build().entries {
+            for entry in entries {
+                self.insert(key, entry);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the book in the polyglot format. Each entry is 16 big endian bytes, the key, the
+    /// move, the weight and the learn value. The entries are sorted by key so the book can be
+    /// searched, and then by weight so the best moves come first.
+    pub fn write_bin_file(&self, path: &str) -> Result<(), String> {
+        let file = std::fs::File::create(path).map_err(|e| e.to_string())?;
+        let mut writer = BufWriter::new(file);
+
+        let mut keys: Vec<&u64> = self.entries.keys().collect();
+        keys.sort();
+
+        for key in keys {
+            let mut entries: Vec<&PolyglotEntry> = self.entries[key].iter().collect();
+            entries.sort_by(|a, b| b.weight.cmp(&a.weight).then(a.mv.cmp(&b.mv)));
+
+            for entry in entries {
+                writer
+                    .write_all(&key.to_be_bytes())
+                    .and_then(|_| writer.write_all(&entry.to_bytes()))
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+
+        writer.flush().map_err(|e| e.to_string())
+    }
+
+    /// Combines books into one. The moves are kept in the order they are first seen, and the
+    /// learn value is taken from the first book that has the move.
+    ///
+    /// ```
+    /// use book::{MergePolicy, PolyglotBook, PolyglotEntry};
+    ///
+    /// let entry = |weight| PolyglotEntry { mv: 0x031c, weight, learn: 0 };
+    /// let mut first = PolyglotBook::default();
+    /// first.insert(1, entry(10));
+    /// let mut second = PolyglotBook::default();
+    /// second.insert(1, entry(30));
+    ///
+    /// let merged = PolyglotBook::merge(&[first, second], MergePolicy::Average);
+    /// assert_eq!(merged.entries[&1][0].weight, 20);
+    /// ```
+    pub fn merge(books: &[PolyglotBook], policy: MergePolicy) -> PolyglotBook {
+        // The total weight and the number of books for each move, so the average can be worked
+        // out once all the books have been seen
+        let mut moves: HashMap<u64, Vec<(PolyglotEntry, u64, u64)>> = HashMap::new();
+
+        for book in books {
+            for (key, entries) in &book.entries {
+                let merged = moves.entry(*key).or_default();
+                for entry in entries {
+                    let weight = entry.weight as u64;
+                    match merged.iter_mut().find(|(e, _, _)| e.mv == entry.mv) {
+                        Some((merged, total, count)) => {
+                            merged.weight = match policy {
+                                MergePolicy::Sum => merged.weight.saturating_add(entry.weight),
+                                MergePolicy::Max => merged.weight.max(entry.weight),
+                                MergePolicy::Average | MergePolicy::First => merged.weight,
+                            };
+
+                            *total += weight;
+                            *count += 1;
+                        }
+                        None => merged.push((entry.clone(), weight, 1)),
+                    }
+                }
+            }
+        }
+
+        let mut book = PolyglotBook::default();
+        for (key, merged) in moves {
+            let entries = merged
+                .into_iter()
+                .map(|(entry, total, count)| match policy {
+                    MergePolicy::Average => PolyglotEntry {
+                        weight: (total / count) as u16,
+                        ..entry
+                    },
+                    _ => entry,
+                })
+                .collect();
+
+            book.entries.insert(key, entries);
+        }
+
+        book
+    }
+
+    /// Removes all the moves with a weight below the minimum, and any positions that are left
+    /// without any moves.
+    pub fn retain_min_weight(&mut self, min_weight: u16) {
+        for entries in self.entries.values_mut() {
+            entries.retain(|entry| entry.weight >= min_weight);
+        }
+
+        self.entries.retain(|_, entries| !entries.is_empty());
+    }
+
+    /// The legal moves in the book for the board along with their weights.
+    pub fn probe(&self, board: &Board) -> Vec<(ResolvedMovement, u16)> {
+        let Some(entries) = self.entries.get(&board.hash()) else {
+            return Vec::new();
+        };
+
+        entries
+            .iter()
+            .filter_map(|entry| Some((entry.movement(board)?, entry.weight)))
+            .collect()
+    }
+
+    /// Picks a move from the book for the board. The random number is only used in the weighted
+    /// mode, moves with a weight of 0 are never picked.
+    pub fn choose(&self, board: &Board, mode: BookMode, random: u64) -> Option<ResolvedMovement> {
+        let moves: Vec<(ResolvedMovement, u16)> = self
+            .probe(board)
+            .into_iter()
+            .filter(|(_, weight)| *weight > 0)
+            .collect();
+
+        match mode {
+            BookMode::Best => moves
+                .iter()
+                .max_by_key(|(_, weight)| *weight)
+                .map(|(movement, _)| *movement),
+            BookMode::Weighted => {
+                let total: u64 = moves.iter().map(|(_, weight)| *weight as u64).sum();
+                if total == 0 {
+                    return None;
+                }
+
+                let mut pick = random % total;
+                for (movement, weight) in moves {
+                    if pick < weight as u64 {
+                        return Some(movement);
+                    }
+
+                    pick -= weight as u64;
+                }
+
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(board: &Board, moves: &[(&str, u16)]) -> PolyglotBook {
+        let mut book = PolyglotBook::default();
+        for (uci, weight) in moves {
+            let movement = legal_moves(board)
+                .into_iter()
+                .find(|m| m.uci() == *uci)
+                .unwrap();
+
+            let mut entry = PolyglotEntry::from_move(&movement);
+            entry.weight = *weight;
+            book.insert(board.hash(), entry);
+        }
+
+        book
+    }
+
+    #[test]
+    fn probes_the_legal_moves() {
+        let board = Board::from_start_position().unwrap();
+        let book = book(&board, &[("e2e4", 10), ("d2d4", 5)]);
+
+        let moves: Vec<(String, u16)> = book
+            .probe(&board)
+            .iter()
+            .map(|(movement, weight)| (movement.uci(), *weight))
+            .collect();
+
+        assert_eq!(moves, [("e2e4".to_string(), 10), ("d2d4".to_string(), 5)]);
+
+        let mut board = board;
+        board.move_piece(legal_moves(&board)[0]);
+        assert!(book.probe(&board).is_empty());
+    }
+
+    #[test]
+    fn adds_the_weights_of_the_same_move() {
+        let board = Board::from_start_position().unwrap();
+        let added = book(&board, &[("e2e4", 10), ("e2e4", 5), ("d2d4", u16::MAX)]);
+        assert_eq!(added.entries[&board.hash()][0].weight, 15);
+
+        let full = book(&board, &[("e2e4", u16::MAX - 1), ("e2e4", 5)]);
+        assert_eq!(full.entries[&board.hash()][0].weight, u16::MAX);
+    }
+
+    #[test]
+    fn writes_and_reads_bin_files() {
+        let board = Board::from_start_position().unwrap();
+        let mut book = book(&board, &[("e2e4", 10), ("d2d4", 30)]);
+
+        let mut after_e4 = board;
+        after_e4.move_piece(book.probe(&board)[0].0);
+        book.insert(
+            after_e4.hash(),
+            PolyglotEntry::from_move(&legal_moves(&after_e4)[0]),
+        );
+
+        let path = std::env::temp_dir().join(format!("ceir-book-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        book.write_bin_file(path).unwrap();
+
+        let bytes = std::fs::read(path).unwrap();
+        assert_eq!(bytes.len(), 3 * 16);
+
+        // The keys are sorted and then the best moves first
+        let keys: Vec<u64> = bytes
+            .chunks(16)
+            .map(|chunk| u64::from_be_bytes(chunk[0..8].try_into().unwrap()))
+            .collect();
+        assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let mut loaded = PolyglotBook::default();
+        loaded.load_bin_file(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let moves: Vec<(String, u16)> = loaded
+            .probe(&board)
+            .iter()
+            .map(|(movement, weight)| (movement.uci(), *weight))
+            .collect();
+        assert_eq!(moves, [("d2d4".to_string(), 30), ("e2e4".to_string(), 10)]);
+        assert_eq!(loaded.probe(&after_e4).len(), 1);
+    }
+
+    fn moves(board: &Board) -> Vec<(String, u16)> {
+        legal_moves(board)
+            .iter()
+            .map(|movement| (movement.uci(), encode_move(movement)))
+            .collect()
+    }
+
+    #[test]
+    fn reads_a_reference_book() {
+        // The start position entries for 1. e4, 1. d4, 1. Nf3 and 1. c4 as they are laid out in
+        // a polyglot book.
+        let bytes: [[u8; 16]; 4] = [
+            [
+                0x46, 0x3b, 0x96, 0x18, 0x16, 0x91, 0xfc, 0x9c, 0x03, 0x1c, 0x00, 0x40, 0, 0, 0, 0,
+            ],
+            [
+                0x46, 0x3b, 0x96, 0x18, 0x16, 0x91, 0xfc, 0x9c, 0x02, 0xdb, 0x00, 0x30, 0, 0, 0, 0,
+            ],
+            [
+                0x46, 0x3b, 0x96, 0x18, 0x16, 0x91, 0xfc, 0x9c, 0x01, 0x95, 0x00, 0x20, 0, 0, 0, 0,
+            ],
+            [
+                0x46, 0x3b, 0x96, 0x18, 0x16, 0x91, 0xfc, 0x9c, 0x02, 0x9a, 0x00, 0x10, 0, 0, 0, 0,
+            ],
+        ];
+
+        let path = std::env::temp_dir().join(format!("ceir-reference-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, bytes.concat()).unwrap();
+
+        let mut book = PolyglotBook::default();
+        book.load_bin_file(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let board = Board::from_start_position().unwrap();
+        let moves: Vec<(String, u16)> = book
+            .probe(&board)
+            .iter()
+            .map(|(movement, weight)| (movement.uci(), *weight))
+            .collect();
+
+        assert_eq!(
+            moves,
+            [
+                ("e2e4".to_string(), 64),
+                ("d2d4".to_string(), 48),
+                ("g1f3".to_string(), 32),
+                ("c2c4".to_string(), 16)
+            ]
+        );
+    }
+
+    #[test]
+    fn encodes_castling_as_the_king_taking_the_rook() {
+        let white = Board::from_fen_str("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let black = Board::from_fen_str("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+
+        assert!(moves(&white).contains(&("e1g1".to_string(), 0x0107)));
+        assert!(moves(&white).contains(&("e1c1".to_string(), 0x0100)));
+        assert!(moves(&black).contains(&("e8g8".to_string(), 0x0f3f)));
+        assert!(moves(&black).contains(&("e8c8".to_string(), 0x0f38)));
+
+        assert_eq!(decode_move(0x0107, &white).unwrap().uci(), "e1g1");
+        assert_eq!(decode_move(0x0f38, &black).unwrap().uci(), "e8c8");
+
+        let entry = PolyglotEntry::from_move(&decode_move(0x0107, &white).unwrap());
+        assert_eq!(entry.uci(), "e1h1");
+
+        // A rook on e1 can move to h1 without it being castling
+        let rook = Board::from_fen_str("4k3/8/8/8/8/8/8/K3R3 w - - 0 1").unwrap();
+        let movement = decode_move(0x0107, &rook).unwrap();
+        assert_eq!(
+            (movement.piece, movement.uci()),
+            (Piece::Rook, "e1h1".to_string())
+        );
+    }
+
+    #[test]
+    fn round_trips_every_legal_move() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "4k3/P6p/8/8/8/8/p6P/4K3 b - - 0 1",
+        ];
+
+        for fen in fens {
+            let board = Board::from_fen_str(fen).unwrap();
+            for movement in legal_moves(&board) {
+                assert_eq!(decode_move(encode_move(&movement), &board), Some(movement));
+            }
+        }
+
+        let promotion = Board::from_fen_str("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(moves(&promotion).contains(&("a7a8q".to_string(), 0x4c38)));
+        assert!(decode_move(0x4c38, &Board::from_start_position().unwrap()).is_none());
+    }
+
+    #[test]
+    fn merges_books() {
+        let board = Board::from_start_position().unwrap();
+        let first = book(&board, &[("e2e4", 10), ("d2d4", 40)]);
+        let second = book(&board, &[("e2e4", 30), ("c2c4", 5)]);
+        let books = [first, second];
+
+        let weights = |policy| -> Vec<(String, u16)> {
+            PolyglotBook::merge(&books, policy)
+                .probe(&board)
+                .iter()
+                .map(|(movement, weight)| (movement.uci(), *weight))
+                .collect()
+        };
+
+        let expected = |e4| {
+            vec![
+                ("e2e4".to_string(), e4),
+                ("d2d4".to_string(), 40),
+                ("c2c4".to_string(), 5),
+            ]
+        };
+
+        assert_eq!(weights(MergePolicy::Sum), expected(40));
+        assert_eq!(weights(MergePolicy::Max), expected(30));
+        assert_eq!(weights(MergePolicy::Average), expected(20));
+        assert_eq!(weights(MergePolicy::First), expected(10));
+
+        let mut merged = PolyglotBook::merge(&books, MergePolicy::Sum);
+        merged.retain_min_weight(40);
+        assert_eq!(merged.probe(&board).len(), 2);
+
+        merged.retain_min_weight(41);
+        assert!(merged.entries.is_empty());
+    }
+
+    #[test]
+    fn chooses_a_move() {
+        let board = Board::from_start_position().unwrap();
+        let book = book(&board, &[("e2e4", 10), ("d2d4", 30), ("g1f3", 0)]);
+
+        let best = book.choose(&board, BookMode::Best, 0).unwrap();
+        assert_eq!(best.uci(), "d2d4");
+
+        // The random number is spread over the total weight of 40
+        let pick = |random| {
+            book.choose(&board, BookMode::Weighted, random)
+                .unwrap()
+                .uci()
+        };
+        assert_eq!(pick(0), "e2e4");
+        assert_eq!(pick(9), "e2e4");
+        assert_eq!(pick(10), "d2d4");
+        assert_eq!(pick(39), "d2d4");
+        assert_eq!(pick(40), "e2e4");
+
+        let empty = PolyglotBook::default();
+        assert!(empty.choose(&board, BookMode::Best, 0).is_none());
+    }
+}
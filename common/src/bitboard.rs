@@ -82,6 +82,7 @@ impl BitBoardIterator {
     // bit. Once there are no significant bits left None is returned.
     //
     // https://www.chessprogramming.org/General_Setwise_Operations#TheLeastSignificantOneBitLS1B
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Option<usize> {
         let trailing = self.board.trailing_zeros() as usize;
         if trailing == 64 {
@@ -3,6 +3,7 @@ use crate::hasher;
 use crate::BitBoard;
 use crate::BitBoardable;
 use crate::Color;
+use crate::Error;
 use crate::Fen;
 use crate::Piece;
 use crate::ResolvedMovement;
@@ -90,14 +91,14 @@ impl Board {
         self.turn = fen.turn;
     }
 
-    pub fn from_fen_str(fen: &str) -> Result<Self, String> {
+    pub fn from_fen_str(fen: &str) -> Result<Self, Error> {
         let mut board = Self::default();
         board.load_fen(&Fen::from_str(fen)?);
 
         Ok(board)
     }
 
-    pub fn from_start_position() -> Result<Self, String> {
+    pub fn from_start_position() -> Result<Self, Error> {
         let mut board = Self::default();
         board.load_fen(&Fen::from_start_position()?);
 
@@ -155,6 +156,39 @@ impl Board {
         None
     }
 
+    /// Returns the bitboard for a single piece type of the given color.
+    ///
+    /// ```
+    /// use common::{bb, Board, Color, Piece, Square};
+    ///
+    /// let board = Board::from_start_position().unwrap();
+    /// assert_eq!(board.piece_board(Color::White, Piece::King), bb!(Square::E1));
+    /// ```
+    pub fn piece_board(&self, color: Color, piece: Piece) -> BitBoard {
+        match (color, piece) {
+            (Color::White, Piece::Pawn) => self.white_pawn_board,
+            (Color::White, Piece::Rook) => self.white_rook_board,
+            (Color::White, Piece::Bishop) => self.white_bishop_board,
+            (Color::White, Piece::Knight) => self.white_knight_board,
+            (Color::White, Piece::Queen) => self.white_queen_board,
+            (Color::White, Piece::King) => self.white_king_board,
+            (Color::Black, Piece::Pawn) => self.black_pawn_board,
+            (Color::Black, Piece::Rook) => self.black_rook_board,
+            (Color::Black, Piece::Bishop) => self.black_bishop_board,
+            (Color::Black, Piece::Knight) => self.black_knight_board,
+            (Color::Black, Piece::Queen) => self.black_queen_board,
+            (Color::Black, Piece::King) => self.black_king_board,
+        }
+    }
+
+    /// Returns all of the pieces for a given color.
+    pub fn color_pieces(&self, color: Color) -> BitBoard {
+        match color {
+            Color::White => self.white_pieces(),
+            Color::Black => self.black_pieces(),
+        }
+    }
+
     fn color_board(&mut self, piece: &Piece, color: Color) -> &mut BitBoard {
         match piece {
             Piece::Pawn if color == Color::White => &mut self.white_pawn_board,
@@ -324,7 +358,78 @@ impl Board {
         println!("Its {} to move", self.turn);
     }
 
-    pub fn hash(&self) -> Result<u64, String> {
+    /// Writes the board out as a fen string. The board does not keep track of the move counters
+    /// so they are always written as `0 1`.
+    ///
+    /// ```
+    /// use common::Board;
+    ///
+    /// let fen = "r3k2r/pppq1ppp/2n5/3pP3/8/8/PPP2PPP/R3K2R w KQq d6 0 1";
+    /// assert_eq!(Board::from_fen_str(fen).unwrap().to_fen(), fen);
+    /// ```
+    pub fn to_fen(&self) -> String {
+        const LAST_BIT: u64 = 63;
+
+        let mut fen = String::new();
+        for rank in 0..8 {
+            let mut empty = 0;
+            for file in 0..8 {
+                let mask = 1u64 << (LAST_BIT - (rank * 8) - file);
+                match self.get_piece_at(&mask) {
+                    Some((color, piece)) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+
+                        fen.push(match color {
+                            Color::White => piece.to_lower().to_ascii_uppercase(),
+                            Color::Black => piece.to_lower(),
+                        });
+                    }
+                    None => empty += 1,
+                }
+            }
+
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+
+            if rank < 7 {
+                fen.push('/');
+            }
+        }
+
+        let turn = match self.turn {
+            Color::White => 'w',
+            Color::Black => 'b',
+        };
+
+        let mut castling = String::new();
+        for (allowed, c) in [
+            (self.white_castling_kings_side, 'K'),
+            (self.white_castling_queen_side, 'Q'),
+            (self.black_castling_kings_side, 'k'),
+            (self.black_castling_queen_side, 'q'),
+        ] {
+            if allowed {
+                castling.push(c);
+            }
+        }
+
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let en_passant = match self.en_passant {
+            Some(square) => square.uci(),
+            None => "-".to_string(),
+        };
+
+        format!("{fen} {turn} {castling} {en_passant} 0 1")
+    }
+
+    pub fn hash(&self) -> u64 {
         hasher::hash_board(self)
     }
 }
@@ -1,5 +1,7 @@
 use std::fmt;
 
+use crate::Error;
+
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum Color {
     #[default]
@@ -37,7 +39,7 @@ impl fmt::Display for Color {
 }
 
 impl std::str::FromStr for Color {
-    type Err = String;
+    type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
@@ -45,7 +47,7 @@ impl std::str::FromStr for Color {
             "white" => Ok(Color::White),
             "b" => Ok(Color::Black),
             "w" => Ok(Color::White),
-            _ => Err(format!("Unable to parse '{}' into a color", s)),
+            _ => Err(Error::InvalidColor(s.to_string())),
         }
     }
 }
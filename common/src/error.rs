@@ -0,0 +1,146 @@
+use std::fmt;
+
+use crate::{Color, Square};
+
+/// The space separated fields of a FEN string, in the order they come in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FenField {
+    Pieces,
+    Turn,
+    Castling,
+    EnPassant,
+    HalfMoveClock,
+    FullMoveNumber,
+}
+
+impl fmt::Display for FenField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenField::Pieces => write!(f, "piece placement"),
+            FenField::Turn => write!(f, "side to move"),
+            FenField::Castling => write!(f, "castling rights"),
+            FenField::EnPassant => write!(f, "en passant square"),
+            FenField::HalfMoveClock => write!(f, "half move clock"),
+            FenField::FullMoveNumber => write!(f, "full move number"),
+        }
+    }
+}
+
+/// A reason a position could never come up in a game, see [`crate::Fen::validate`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum PositionError {
+    /// Each side must have exactly one king, this is the number that were found.
+    KingCount(Color, usize),
+    PawnOnBackRank(Square),
+    /// The castling right, as it is in the FEN, without the king and rook on their squares.
+    CastlingRights(char),
+    /// The en passant square is not behind a pawn that has just moved two squares.
+    EnPassant(Square),
+    /// The side that has just moved has left its king in check.
+    OpponentInCheck,
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PositionError::KingCount(color, count) => {
+                write!(f, "{color} has {count} kings")
+            }
+            PositionError::PawnOnBackRank(square) => {
+                write!(f, "there is a pawn on {}", square.uci())
+            }
+            PositionError::CastlingRights(right) => {
+                write!(f, "castling right '{right}' without the king and rook")
+            }
+            PositionError::EnPassant(square) => {
+                write!(f, "invalid en passant square {}", square.uci())
+            }
+            PositionError::OpponentInCheck => write!(f, "the side not to move is in check"),
+        }
+    }
+}
+
+/// All the things that can go wrong in the common crate.
+///
+/// ```
+/// use common::{Board, Error, FenField};
+///
+/// let error = Board::from_fen_str("8/8/8/8/8/8/8/8 w KX - 0 1").err().unwrap();
+/// assert_eq!(
+///     error,
+///     Error::InvalidFenField {
+///         field: FenField::Castling,
+///         position: 1,
+///         value: "X".to_string()
+///     }
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The FEN does not have six fields, this is the number of fields that were found.
+    FenFieldCount(usize),
+    /// The piece placement of a FEN does not have 64 squares, this is the number that were
+    /// found.
+    FenSquareCount(usize),
+    /// A field in a FEN could not be parsed. For the piece placement the position is the index
+    /// of the square starting from a8, for the castling rights it is the index of the character
+    /// in the field and for everything else it is 0.
+    InvalidFenField {
+        field: FenField,
+        position: usize,
+        value: String,
+    },
+    InvalidSquare(String),
+    InvalidColor(String),
+    /// The move could not be parsed.
+    InvalidMove(String),
+    /// The move is not one of the legal moves on the board.
+    IllegalMove(String),
+    /// The move matches more than one of the legal moves on the board.
+    AmbiguousMove(String),
+    /// An `AmbiguousMovement` did not match any of the moves on the board.
+    UnresolvedMove,
+    /// The position can not be reached in a game, with every problem that was found.
+    IllegalPosition(Vec<PositionError>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::FenFieldCount(_) => write!(f, "The fen must have 6 parts"),
+            Error::FenSquareCount(count) => {
+                write!(f, "Invalid fen there must be 64 squares found {count}")
+            }
+            Error::InvalidFenField {
+                field: FenField::Pieces,
+                position,
+                value,
+            } => write!(f, "Unknown piece char '{value}' at position {position}"),
+            Error::InvalidFenField {
+                field: FenField::Castling,
+                value,
+                ..
+            } => write!(f, "Unexpected char '{value}' in castling part"),
+            Error::InvalidFenField { field, value, .. } => write!(f, "Invalid {field} '{value}'"),
+            Error::InvalidSquare(square) => write!(f, "Invalid square '{square}'"),
+            Error::InvalidColor(color) => write!(f, "Unable to parse '{color}' into a color"),
+            Error::InvalidMove(san) => write!(f, "Invalid move '{san}'"),
+            Error::IllegalMove(san) => write!(f, "Illegal move '{san}'"),
+            Error::AmbiguousMove(san) => write!(f, "Ambiguous move '{san}'"),
+            Error::UnresolvedMove => write!(f, "Unable to resolve ambiguous movement"),
+            Error::IllegalPosition(problems) => {
+                let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+                write!(f, "Illegal position, {}", problems.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Lets the binaries that still use `String` errors keep using `?`.
+impl From<Error> for String {
+    fn from(error: Error) -> Self {
+        error.to_string()
+    }
+}
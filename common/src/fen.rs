@@ -1,7 +1,15 @@
 use std::str::FromStr;
 
+use crate::attacked_squares;
+use crate::bb;
+use crate::BitBoard;
+use crate::BitBoardIterator;
+use crate::Board;
 use crate::Color;
+use crate::Error;
+use crate::FenField;
 use crate::Piece;
+use crate::PositionError;
 use crate::Square;
 
 /// Forsyth–Edwards Notation (fen)
@@ -35,31 +43,27 @@ pub struct Fen {
 }
 
 impl Fen {
-    pub fn new(fen_string: &String) -> Result<Self, String> {
-        let mut parts = fen_string.split_whitespace();
-
-        if parts.clone().count() != 6 {
-            return Err("The fen must have 6 parts".to_string());
-        }
+    pub fn new(fen_string: &str) -> Result<Self, Error> {
+        let parts: Vec<&str> = fen_string.split_whitespace().collect();
+        let [pieces, color, castling, en_passant, half_move_clock, full_move_number] = parts[..]
+        else {
+            return Err(Error::FenFieldCount(parts.len()));
+        };
 
-        let mut index = -1;
         let mut squares: Vec<Option<(Color, Piece)>> = Vec::new();
-        for c in parts.next().unwrap().chars() {
+        for c in pieces.chars() {
             if c == '/' {
                 continue;
             }
 
-            if let Ok(n) = c.to_string().parse::<i32>() {
-                for _ in index..(n + index) {
-                    index += 1;
+            if let Some(n) = c.to_digit(10) {
+                for _ in 0..n {
                     squares.push(None);
                 }
 
                 continue;
             }
 
-            index += 1;
-
             match c {
                 'k' => squares.push(Some((Color::Black, Piece::King))),
                 'q' => squares.push(Some((Color::Black, Piece::Queen))),
@@ -75,25 +79,30 @@ impl Fen {
                 'B' => squares.push(Some((Color::White, Piece::Bishop))),
                 'P' => squares.push(Some((Color::White, Piece::Pawn))),
 
-                _ => return Err(format!("Unknown piece char '{c}' at position {index}")),
+                _ => {
+                    return Err(Error::InvalidFenField {
+                        field: FenField::Pieces,
+                        position: squares.len(),
+                        value: c.to_string(),
+                    })
+                }
             }
         }
 
         // Check that we have 64 squares This will panic later when we try and fix each squares
         // into 64 bits.
         if squares.len() != 64 {
-            return Err(format!(
-                "Invalid fen there must be 64 squares found {}",
-                squares.len()
-            ));
+            return Err(Error::FenSquareCount(squares.len()));
         }
 
-        let color = parts.next();
-        let turn = match color {
-            Some(c) => Color::from_str(c)?,
-            None => return Err("Unable to parse color".to_string()),
+        let invalid = |field: FenField, value: &str| Error::InvalidFenField {
+            field,
+            position: 0,
+            value: value.to_string(),
         };
 
+        let turn = Color::from_str(color).map_err(|_| invalid(FenField::Turn, color))?;
+
         let mut fen = Fen {
             squares,
             turn,
@@ -106,56 +115,165 @@ impl Fen {
             black_castling_queen_side: false,
         };
 
-        let castling = parts.next();
-        if castling.is_some() {
-            for c in castling.unwrap().chars() {
-                match c {
-                    'K' => fen.white_castling_kings_side = true,
-                    'Q' => fen.white_castling_queen_side = true,
-                    'k' => fen.black_castling_kings_side = true,
-                    'q' => fen.black_castling_queen_side = true,
-                    '-' => {}
-                    _ => {
-                        return Err(format!("Unexpected char '{c}' in castling part"));
-                    }
+        for (position, c) in castling.chars().enumerate() {
+            match c {
+                'K' => fen.white_castling_kings_side = true,
+                'Q' => fen.white_castling_queen_side = true,
+                'k' => fen.black_castling_kings_side = true,
+                'q' => fen.black_castling_queen_side = true,
+                '-' => {}
+                _ => {
+                    return Err(Error::InvalidFenField {
+                        field: FenField::Castling,
+                        position,
+                        value: c.to_string(),
+                    });
                 }
             }
         }
 
-        fen.en_passant = match parts.next() {
-            Some("-") => None,
-            Some(square) => Some(Square::from_str(square)?),
-            None => return Err("Missing en passant square".to_string()),
+        fen.en_passant = match en_passant {
+            "-" => None,
+            square => {
+                Some(Square::from_str(square).map_err(|_| invalid(FenField::EnPassant, square))?)
+            }
         };
 
-        fen.half_move_clock = match parts.next() {
-            Some(half_move_clock) => half_move_clock
-                .parse()
-                .map_err(|_| format!("Invalid half move clock {half_move_clock}").to_string())?,
-            None => return Err("Missing half move clock".to_string()),
-        };
+        fen.half_move_clock = half_move_clock
+            .parse()
+            .map_err(|_| invalid(FenField::HalfMoveClock, half_move_clock))?;
 
-        fen.full_move_number = match parts.next() {
-            Some(half_move_clock) => half_move_clock
-                .parse()
-                .map_err(|_| format!("Invalid full move number {half_move_clock}").to_string())?,
-            None => return Err("Missing full move number".to_string()),
-        };
+        fen.full_move_number = full_move_number
+            .parse()
+            .map_err(|_| invalid(FenField::FullMoveNumber, full_move_number))?;
+
+        Ok(fen)
+    }
+
+    /// Parses a FEN and then checks the position could come up in a game, see
+    /// [`Fen::validate`].
+    pub fn new_strict(fen_string: &str) -> Result<Self, Error> {
+        let fen = Fen::new(fen_string)?;
+        fen.validate()?;
 
         Ok(fen)
     }
 
-    pub fn from_start_position() -> Result<Self, String> {
+    /// Checks the position could come up in a game. `Fen::new` only checks the FEN can be parsed,
+    /// so it will load boards that break the move generation, like a board without a king. This
+    /// returns an `Error::IllegalPosition` with every problem that was found.
+    ///
+    /// ```
+    /// use common::{Color, Error, Fen, PositionError};
+    ///
+    /// let fen = Fen::new("4k3/8/8/8/8/8/8/4K2P w K - 0 1").unwrap();
+    /// assert_eq!(
+    ///     fen.validate(),
+    ///     Err(Error::IllegalPosition(vec![
+    ///         PositionError::PawnOnBackRank(common::Square::H1),
+    ///         PositionError::CastlingRights('K'),
+    ///     ]))
+    /// );
+    ///
+    /// assert!(Fen::from_start_position().unwrap().validate().is_ok());
+    /// ```
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut board = Board::default();
+        board.load_fen(self);
+
+        let mut problems = Vec::new();
+
+        for color in [Color::White, Color::Black] {
+            let kings = board.piece_board(color, Piece::King).count_ones() as usize;
+            if kings != 1 {
+                problems.push(PositionError::KingCount(color, kings));
+            }
+        }
+
+        let back_ranks: BitBoard = 0xff000000000000ff;
+        let mut pawns =
+            BitBoardIterator::new((board.white_pawn_board | board.black_pawn_board) & back_ranks);
+        while let Some(square) = pawns.next() {
+            problems.push(PositionError::PawnOnBackRank(Square::from_usize(square)));
+        }
+
+        let castling = [
+            (
+                'K',
+                self.white_castling_kings_side,
+                Color::White,
+                Square::E1,
+                Square::H1,
+            ),
+            (
+                'Q',
+                self.white_castling_queen_side,
+                Color::White,
+                Square::E1,
+                Square::A1,
+            ),
+            (
+                'k',
+                self.black_castling_kings_side,
+                Color::Black,
+                Square::E8,
+                Square::H8,
+            ),
+            (
+                'q',
+                self.black_castling_queen_side,
+                Color::Black,
+                Square::E8,
+                Square::A8,
+            ),
+        ];
+
+        for (right, enabled, color, king, rook) in castling {
+            let has_king = board.piece_board(color, Piece::King) & bb!(king) != 0;
+            let has_rook = board.piece_board(color, Piece::Rook) & bb!(rook) != 0;
+            if enabled && !(has_king && has_rook) {
+                problems.push(PositionError::CastlingRights(right));
+            }
+        }
+
+        if let Some(square) = self.en_passant {
+            // The pawn that has just moved two squares is in front of the en passant square, and
+            // the square it moved from is behind it
+            let target: BitBoard = bb!(square);
+            let (rank, pawn, from) = match self.turn {
+                Color::White => ('6', target >> 8, target << 8),
+                Color::Black => ('3', target << 8, target >> 8),
+            };
+
+            let occupied = board.white_pieces() | board.black_pieces();
+            let pawns = board.piece_board(self.turn.opposite(), Piece::Pawn);
+            if square.rank_char() != rank || pawns & pawn == 0 || occupied & (target | from) != 0 {
+                problems.push(PositionError::EnPassant(square));
+            }
+        }
+
+        let opponent_king = board.piece_board(self.turn.opposite(), Piece::King);
+        if attacked_squares(&board, &self.turn) & opponent_king != 0 {
+            problems.push(PositionError::OpponentInCheck);
+        }
+
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(Error::IllegalPosition(problems)),
+        }
+    }
+
+    pub fn from_start_position() -> Result<Self, Error> {
         let fen_string = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
         Fen::from_str(fen_string)
     }
 }
 
 impl FromStr for Fen {
-    type Err = String;
+    type Err = Error;
 
     fn from_str(fen: &str) -> Result<Self, Self::Err> {
-        Fen::new(&String::from(fen))
+        Fen::new(fen)
     }
 }
 
@@ -166,35 +284,99 @@ mod tests {
 
     #[test]
     fn dose_not_have_six_parts() {
-        let message = Fen::from_str("a b").err().unwrap();
-        assert_eq!(message, String::from("The fen must have 6 parts"));
+        let error = Fen::from_str("a b").err().unwrap();
+        assert_eq!(error, Error::FenFieldCount(2));
+        assert_eq!(error.to_string(), "The fen must have 6 parts");
     }
 
     #[test]
     fn invalid_piece() {
         let fen_string = "xnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-        let message = Fen::from_str(fen_string).err().unwrap();
-        assert_eq!(
-            message,
-            String::from("Unknown piece char 'x' at position 0")
-        );
+        let error = Fen::from_str(fen_string).err().unwrap();
+        assert_eq!(error.to_string(), "Unknown piece char 'x' at position 0");
     }
 
     #[test]
     fn invalid_number_of_squares() {
         let fen_string = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPP w KQkq - 0 1";
-        let message = Fen::from_str(fen_string).err().unwrap();
-        assert_eq!(
-            message,
-            String::from("Invalid fen there must be 64 squares found 54")
-        );
+        let error = Fen::from_str(fen_string).err().unwrap();
+        assert_eq!(error, Error::FenSquareCount(54));
     }
 
     #[test]
     fn invalid_color() {
         let fen_string = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1";
-        let message = Fen::from_str(fen_string).err().unwrap();
-        assert_eq!(message, String::from("Unable to parse 'x' into a color"));
+        let error = Fen::from_str(fen_string).err().unwrap();
+        assert_eq!(
+            error,
+            Error::InvalidFenField {
+                field: FenField::Turn,
+                position: 0,
+                value: "x".to_string()
+            }
+        );
+    }
+
+    fn problems(fen: &str) -> Vec<PositionError> {
+        match Fen::new_strict(fen) {
+            Ok(_) => Vec::new(),
+            Err(Error::IllegalPosition(problems)) => problems,
+            Err(error) => panic!("{fen} is not a valid fen, {error}"),
+        }
+    }
+
+    #[test]
+    fn accepts_legal_positions() {
+        assert!(problems("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_empty());
+        assert!(
+            problems("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3").is_empty()
+        );
+        assert!(
+            problems("rnbqkbnr/pppp1ppp/8/3Pp3/8/8/PPP1PPPP/RNBQKBNR w KQkq e6 0 3").is_empty()
+        );
+        // The side to move can be in check
+        assert!(problems("4k3/8/8/8/8/8/8/4K2r w - - 0 1").is_empty());
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        assert_eq!(
+            problems("8/8/8/8/8/8/8/8 w - - 0 1"),
+            [
+                PositionError::KingCount(Color::White, 0),
+                PositionError::KingCount(Color::Black, 0)
+            ]
+        );
+
+        assert_eq!(
+            problems("P3k3/8/8/8/8/8/8/1R2K1pK b Qk - 0 1"),
+            [
+                PositionError::KingCount(Color::White, 2),
+                PositionError::PawnOnBackRank(Square::G1),
+                PositionError::PawnOnBackRank(Square::A8),
+                PositionError::CastlingRights('Q'),
+                PositionError::CastlingRights('k'),
+            ]
+        );
+
+        // The en passant square must be behind a pawn that has just moved two squares
+        assert_eq!(
+            problems("4k3/8/8/3Pp3/8/8/8/4K3 w - d6 0 1"),
+            [PositionError::EnPassant(Square::D6)]
+        );
+        assert_eq!(
+            problems("4k3/8/4p3/3Pp3/8/8/8/4K3 w - e6 0 1"),
+            [PositionError::EnPassant(Square::E6)]
+        );
+        assert_eq!(
+            problems("4k3/8/8/3Pp3/8/8/8/4K3 b - e6 0 1"),
+            [PositionError::EnPassant(Square::E6)]
+        );
+
+        assert_eq!(
+            problems("4k3/8/8/8/8/8/8/4K2r b - - 0 1"),
+            [PositionError::OpponentInCheck]
+        );
     }
 
     macro_rules! assert_position {
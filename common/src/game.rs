@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use crate::{Board, ResolvedMovement};
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub enum GameResult {
     #[default]
     InProgress,
@@ -6,7 +6,7 @@ use crate::Piece;
 use crate::BitBoardable;
 
 /// Zobrist hashing function
-pub fn hash_board(board: &Board) -> Result<u64, String> {
+pub fn hash_board(board: &Board) -> u64 {
     let mut hash: u64 = 0;
 
     for square in 0..64 {
@@ -57,7 +57,7 @@ pub fn hash_board(board: &Board) -> Result<u64, String> {
         hash ^= crate::random::RANDOM_TURN;
     }
 
-    Ok(hash)
+    hash
 }
 
 #[cfg(test)]
@@ -83,7 +83,7 @@ mod tests {
 
         for (fen, hash) in positions {
             let board = Board::from_fen_str(fen).unwrap();
-            assert_eq!(hash, super::hash_board(&board).unwrap());
+            assert_eq!(hash, super::hash_board(&board));
         }
     }
 }
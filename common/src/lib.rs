@@ -2,6 +2,9 @@
 
 pub mod random;
 
+mod error;
+pub use crate::error::*;
+
 mod bitboard;
 pub use crate::bitboard::*;
 
@@ -48,6 +48,7 @@ pub const ROOK_ATTACKS: [BitBoard; 64] = [
 ];
 
 #[rustfmt::skip]
+#[allow(clippy::eq_op)]
 pub const BISHOP_ATTACKS: [BitBoard; 64] = [
     DIAGONAL_BIT_BOARDS[7] | ANTI_DIAGONAL_BIT_BOARDS[0], DIAGONAL_BIT_BOARDS[7 - 1] | ANTI_DIAGONAL_BIT_BOARDS[1], DIAGONAL_BIT_BOARDS[7 - 2] | ANTI_DIAGONAL_BIT_BOARDS[2], DIAGONAL_BIT_BOARDS[7 - 3] | ANTI_DIAGONAL_BIT_BOARDS[3], DIAGONAL_BIT_BOARDS[7 - 4] | ANTI_DIAGONAL_BIT_BOARDS[4], DIAGONAL_BIT_BOARDS[7 - 5] | ANTI_DIAGONAL_BIT_BOARDS[5], DIAGONAL_BIT_BOARDS[7 - 6] | ANTI_DIAGONAL_BIT_BOARDS[6], DIAGONAL_BIT_BOARDS[7 - 7] | ANTI_DIAGONAL_BIT_BOARDS[7],
     DIAGONAL_BIT_BOARDS[7 + 1] | ANTI_DIAGONAL_BIT_BOARDS[1], DIAGONAL_BIT_BOARDS[7 + 1 - 1] | ANTI_DIAGONAL_BIT_BOARDS[1 + 1], DIAGONAL_BIT_BOARDS[7 + 1 - 2] | ANTI_DIAGONAL_BIT_BOARDS[1 + 2], DIAGONAL_BIT_BOARDS[7 + 1 - 3] | ANTI_DIAGONAL_BIT_BOARDS[1 + 3], DIAGONAL_BIT_BOARDS[7 + 1 - 4] | ANTI_DIAGONAL_BIT_BOARDS[1 + 4], DIAGONAL_BIT_BOARDS[7 + 1 - 5] | ANTI_DIAGONAL_BIT_BOARDS[1 + 5], DIAGONAL_BIT_BOARDS[7 + 1 - 6] | ANTI_DIAGONAL_BIT_BOARDS[1 + 6], DIAGONAL_BIT_BOARDS[7 + 1 - 7] | ANTI_DIAGONAL_BIT_BOARDS[1 + 7],
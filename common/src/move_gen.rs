@@ -81,7 +81,7 @@ fn scan_rook(occupancies: BitBoard, square_board: BitBoard, direction: i8) -> Bi
 }
 
 /// Calculates all of the available attacks that a rook can make given that its on a given square.
-fn rook_attacks(square_board: BitBoard, occupancies: BitBoard) -> BitBoard {
+pub fn rook_attacks(square_board: BitBoard, occupancies: BitBoard) -> BitBoard {
     let line_attacks = scan_rook(occupancies, square_board, NORTH)
         | scan_rook(occupancies, square_board, SOUTH)
         | scan_rook(occupancies, square_board, EAST)
@@ -92,7 +92,7 @@ fn rook_attacks(square_board: BitBoard, occupancies: BitBoard) -> BitBoard {
 
 /// Calculates all of the available attacks that a bishop can make given that its on a given
 /// square.
-fn bishop_attacks(square_board: BitBoard, occupancies: BitBoard) -> BitBoard {
+pub fn bishop_attacks(square_board: BitBoard, occupancies: BitBoard) -> BitBoard {
     let line_attacks = scan_bishop(occupancies, square_board, NORTH_EAST)
         | scan_bishop(occupancies, square_board, NORTH_WEST)
         | scan_bishop(occupancies, square_board, SOUTH_EAST)
@@ -103,7 +103,7 @@ fn bishop_attacks(square_board: BitBoard, occupancies: BitBoard) -> BitBoard {
 
 /// Calculate all of the attacks that a queen can make given that its on a given square. This one
 /// is calculated by combining the rook and bishop attacks.
-fn queen_attacks(square_board: BitBoard, occupancies: BitBoard) -> BitBoard {
+pub fn queen_attacks(square_board: BitBoard, occupancies: BitBoard) -> BitBoard {
     rook_attacks(square_board, occupancies) | bishop_attacks(square_board, occupancies)
 }
 
@@ -111,7 +111,7 @@ fn queen_attacks(square_board: BitBoard, occupancies: BitBoard) -> BitBoard {
 /// lookup of pre built bitboards like the rook and bishop attacks.
 ///
 /// https://www.chessprogramming.org/Knight_Pattern
-fn knight_attacks(bb: BitBoard) -> BitBoard {
+pub fn knight_attacks(bb: BitBoard) -> BitBoard {
     (bb >> 6 & !(FILE_BITBOARDS[7] | FILE_BITBOARDS[6]))
         | (bb >> 15 & !FILE_BITBOARDS[7])
         | (bb >> 17 & !FILE_BITBOARDS[0])
@@ -123,7 +123,7 @@ fn knight_attacks(bb: BitBoard) -> BitBoard {
 }
 
 /// https://www.chessprogramming.org/King_Pattern
-fn king_attacks(bb: BitBoard) -> BitBoard {
+pub fn king_attacks(bb: BitBoard) -> BitBoard {
     (bb >> 7 & !FILE_BITBOARDS[7])
         | bb >> 8
         | (bb >> 9 & !FILE_BITBOARDS[0])
@@ -162,6 +162,17 @@ fn pawn_moves(board: &Board, piece_board: BitBoard, occupancies: BitBoard) -> Bi
     one_square | two_square | pawn_attacks(board, piece_board)
 }
 
+/// Calculates all of the squares attacked by a set of pawns for a given color. Unlike the pawn
+/// moves this does not care if there is anything on the square being attacked.
+///
+/// https://www.chessprogramming.org/Pawn_Attacks_(Bitboards)
+pub fn pawn_attack_board(pawns: BitBoard, color: &Color) -> BitBoard {
+    match color {
+        Color::Black => (pawns >> 9 & !FILE_BITBOARDS[0]) | (pawns >> 7 & !FILE_BITBOARDS[7]),
+        Color::White => (pawns << 9 & !FILE_BITBOARDS[7]) | (pawns << 7 & !FILE_BITBOARDS[0]),
+    }
+}
+
 pub fn attacked_squares(board: &Board, color: &Color) -> BitBoard {
     let mut output = 0;
 
@@ -186,17 +197,7 @@ pub fn attacked_squares(board: &Board, color: &Color) -> BitBoard {
                 Piece::Bishop => output |= bishop_attacks(bb!(index), occupancies) & !my_pieces,
                 Piece::Queen => output |= queen_attacks(bb!(index), occupancies) & !my_pieces,
                 Piece::Rook => output |= rook_attacks(bb!(index), occupancies) & !my_pieces,
-                Piece::Pawn => {
-                    output |= match color {
-                        Color::Black => bb!(index) >> 9 & !FILE_BITBOARDS[0],
-                        Color::White => bb!(index) << 9 & !FILE_BITBOARDS[7],
-                    } & !my_pieces;
-
-                    output |= match color {
-                        Color::Black => bb!(index) >> 7 & !FILE_BITBOARDS[7],
-                        Color::White => bb!(index) << 7 & !FILE_BITBOARDS[0],
-                    } & !my_pieces;
-                }
+                Piece::Pawn => output |= pawn_attack_board(bb!(index), color) & !my_pieces,
             };
         }
     }
@@ -322,13 +323,12 @@ pub fn pseudo_moves(board: &Board) -> Vec<ResolvedMovement> {
 pub fn castle_moves(board: &Board, &attackers: &BitBoard) -> Vec<ResolvedMovement> {
     let mut output = Vec::new();
 
-    let my_pieces = match board.turn {
-        Color::Black => board.black_pieces(),
-        Color::White => board.white_pieces(),
-    };
+    // The squares the king moves over must be empty and not attacked, on the queen side the
+    // knight's square must also be empty for the rook to get past
+    let occupancies = board.white_pieces() | board.black_pieces();
 
     if board.turn == Color::White
-        && lookup::CASTLE_WHITE_KING_SIDE & (my_pieces | attackers) == 0
+        && lookup::CASTLE_WHITE_KING_SIDE & (occupancies | attackers) == 0
         && board.white_castling_kings_side
     {
         output.push(ResolvedMovement {
@@ -341,7 +341,8 @@ pub fn castle_moves(board: &Board, &attackers: &BitBoard) -> Vec<ResolvedMovemen
     }
 
     if board.turn == Color::White
-        && lookup::CASTLE_WHITE_QUEEN_SIDE & (my_pieces | attackers) == 0
+        && lookup::CASTLE_WHITE_QUEEN_SIDE & (occupancies | attackers) == 0
+        && bb!(Square::B1) & occupancies == 0
         && board.white_castling_queen_side
     {
         output.push(ResolvedMovement {
@@ -354,7 +355,7 @@ pub fn castle_moves(board: &Board, &attackers: &BitBoard) -> Vec<ResolvedMovemen
     }
 
     if board.turn == Color::Black
-        && lookup::CASTLE_BLACK_KING_SIDE & (my_pieces | attackers) == 0
+        && lookup::CASTLE_BLACK_KING_SIDE & (occupancies | attackers) == 0
         && board.black_castling_kings_side
     {
         output.push(ResolvedMovement {
@@ -367,7 +368,8 @@ pub fn castle_moves(board: &Board, &attackers: &BitBoard) -> Vec<ResolvedMovemen
     }
 
     if board.turn == Color::Black
-        && lookup::CASTLE_BLACK_QUEEN_SIDE & (my_pieces | attackers) == 0
+        && lookup::CASTLE_BLACK_QUEEN_SIDE & (occupancies | attackers) == 0
+        && bb!(Square::B8) & occupancies == 0
         && board.black_castling_queen_side
     {
         output.push(ResolvedMovement {
@@ -382,6 +384,27 @@ pub fn castle_moves(board: &Board, &attackers: &BitBoard) -> Vec<ResolvedMovemen
     output
 }
 
+/// All of the moves for the side to move that don't leave their own king in check. Castling is
+/// not allowed when the king is in check.
+///
+/// ```
+/// use common::{legal_moves, Board};
+///
+/// let board = Board::from_start_position().unwrap();
+/// assert_eq!(legal_moves(&board).len(), 20);
+/// ```
+pub fn legal_moves(board: &Board) -> Vec<ResolvedMovement> {
+    let attackers = attacked_squares(board, &board.turn.opposite());
+
+    let mut moves = pseudo_moves(board);
+    if !is_in_check(board, &attackers) {
+        moves.extend(castle_moves(board, &attackers));
+    }
+
+    moves.retain(|movement| !is_move_to_check(board, *movement));
+    moves
+}
+
 pub fn is_move_to_check(board: &Board, movement: ResolvedMovement) -> bool {
     let mut new_board = *board;
     new_board.move_piece(movement);
@@ -422,6 +445,64 @@ mod check_tests {
         }
     }
 
+    #[test]
+    fn legal_moves_get_out_of_check() {
+        // The rook is checking the king along the first rank so it has to step off it
+        let board = Board::from_fen_str("4k3/8/8/8/8/8/8/r3K3 w - - 0 1").unwrap();
+        let moves = legal_moves(&board);
+
+        assert_eq!(moves.len(), 3);
+        assert!(moves.iter().all(|m| m.to.rank_char() == '2'));
+    }
+
+    #[test]
+    fn legal_moves_do_not_castle_out_of_or_through_check() {
+        let board = Board::from_fen_str("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        let castles = |board: &Board| {
+            legal_moves(board)
+                .iter()
+                .filter(|m| m.piece == Piece::King && (m.to == Square::G1 || m.to == Square::C1))
+                .count()
+        };
+
+        assert_eq!(castles(&board), 2);
+
+        // In check from the rook on e8
+        let board = Board::from_fen_str("4r1k1/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(castles(&board), 0);
+
+        // The bishop is looking at f1 and the knight is in the way on the queen side
+        let board = Board::from_fen_str("4k3/8/8/8/8/8/6b1/RN2K2R w KQ - 0 1").unwrap();
+        assert_eq!(castles(&board), 0);
+    }
+
+    #[test]
+    fn castling_is_blocked_by_any_piece() {
+        let castles = |fen: &str| {
+            let board = Board::from_fen_str(fen).unwrap();
+            let mut squares: Vec<String> = castle_moves(&board, &0)
+                .iter()
+                .map(|m| m.to.uci())
+                .collect();
+
+            squares.sort();
+            squares
+        };
+
+        assert_eq!(
+            castles("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"),
+            ["c1", "g1"]
+        );
+
+        // The pieces of the other side block castling just like our own
+        assert_eq!(castles("r3k2r/8/8/8/8/8/8/R3K1nR w KQkq - 0 1"), ["c1"]);
+        assert_eq!(castles("r3k2r/8/8/8/8/8/8/Rn2K2R w KQkq - 0 1"), ["g1"]);
+
+        // The knight square on the queen side is not crossed by the king but the rook needs it
+        assert_eq!(castles("rn2k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1"), ["g8"]);
+        assert_eq!(castles("r3k2r/8/8/8/8/8/8/RN2K2R w KQkq - 0 1"), ["g1"]);
+    }
+
     #[test]
     fn simple_move_to_check() {
         let board = Board::from_fen_str("4K3/3Q4/8/8/8/8/8/4k3 w - - 0 1").unwrap();
@@ -1,9 +1,12 @@
 use crate::attacked_squares;
 use crate::bb;
 use crate::castle_moves;
+use crate::is_in_check;
 use crate::is_move_to_check;
+use crate::legal_moves;
 use crate::pseudo_moves;
 use crate::Board;
+use crate::Error;
 use crate::Piece;
 use crate::Square;
 
@@ -13,6 +16,131 @@ pub enum Movement {
     Resolved(ResolvedMovement),
 }
 
+impl Movement {
+    /// Parses a move in standard algebraic notation and matches it against the legal moves on the
+    /// board. This handles all the forms you will find in the wild, castling with `O` or `0`, file,
+    /// rank and full square disambiguation, promotions with or without the `=`, and any check or
+    /// annotation suffixes. It is an error if the move is not legal or could be more than one
+    /// move.
+    ///
+    /// ```
+    /// use common::{Board, Movement, Square};
+    ///
+    /// let board = Board::from_start_position().unwrap();
+    /// let movement = Movement::from_san("Nf3", &board).unwrap();
+    ///
+    /// assert_eq!(movement.from, Square::G1);
+    /// assert_eq!(movement.to, Square::F3);
+    /// assert!(Movement::from_san("Nf4", &board).is_err());
+    /// ```
+    pub fn from_san(san: &str, board: &Board) -> Result<ResolvedMovement, Error> {
+        let text = san
+            .trim()
+            .trim_end_matches(['+', '#', '!', '?'])
+            .trim_end_matches("e.p.")
+            .trim_end();
+
+        let moves = legal_moves(board);
+
+        let castle = match text {
+            "O-O" | "0-0" => Some(true),
+            "O-O-O" | "0-0-0" => Some(false),
+            _ => None,
+        };
+
+        if let Some(king_side) = castle {
+            let to_file = if king_side { 'g' } else { 'c' };
+            return moves
+                .into_iter()
+                .find(|m| {
+                    m.piece == Piece::King
+                        && m.from.file_char() == 'e'
+                        && m.to.file_char() == to_file
+                        && m.from.rank_char() == m.to.rank_char()
+                        && (m.from == Square::E1 || m.from == Square::E8)
+                })
+                .ok_or_else(|| Error::IllegalMove(san.to_string()));
+        }
+
+        let mut chars: Vec<char> = text.chars().collect();
+
+        let piece = match chars.first() {
+            Some('K') => Piece::King,
+            Some('Q') => Piece::Queen,
+            Some('R') => Piece::Rook,
+            Some('B') => Piece::Bishop,
+            Some('N') => Piece::Knight,
+            Some(_) => Piece::Pawn,
+            None => return Err(Error::InvalidMove(san.to_string())),
+        };
+
+        if piece != Piece::Pawn {
+            chars.remove(0);
+        }
+
+        let mut promotion = None;
+        if piece == Piece::Pawn {
+            if let Some(last) = chars.last() {
+                promotion = match last {
+                    'Q' => Some(Piece::Queen),
+                    'R' => Some(Piece::Rook),
+                    'B' => Some(Piece::Bishop),
+                    'N' => Some(Piece::Knight),
+                    _ => None,
+                };
+            }
+
+            if promotion.is_some() {
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+            }
+        }
+
+        if chars.len() < 2 {
+            return Err(Error::InvalidMove(san.to_string()));
+        }
+
+        let to_chars: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let to = Square::from_str(&to_chars).map_err(|_| Error::InvalidMove(san.to_string()))?;
+
+        let capture = chars.last().is_some_and(|c| *c == 'x' || *c == ':');
+        if capture {
+            chars.pop();
+        }
+
+        // Whatever is left is the disambiguation, a file, a rank or both
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in chars {
+            match c {
+                'a'..='h' if from_file.is_none() && from_rank.is_none() => from_file = Some(c),
+                '1'..='8' if from_rank.is_none() => from_rank = Some(c),
+                _ => return Err(Error::InvalidMove(san.to_string())),
+            }
+        }
+
+        let candidates: Vec<ResolvedMovement> = moves
+            .into_iter()
+            .filter(|m| {
+                m.piece == piece
+                    && m.to == to
+                    && m.promotion == promotion
+                    && (!capture || m.capture.is_some())
+                    && from_file.is_none_or(|file| m.from.file_char() == file)
+                    && from_rank.is_none_or(|rank| m.from.rank_char() == rank)
+            })
+            .collect();
+
+        match candidates.len() {
+            0 => Err(Error::IllegalMove(san.to_string())),
+            1 => Ok(candidates[0]),
+            _ => Err(Error::AmbiguousMove(san.to_string())),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Default, Clone)]
 pub struct AmbiguousMovement {
     pub file: Option<char>,
@@ -36,7 +164,7 @@ impl AmbiguousMovement {
         None
     }
 
-    pub fn resolve(&self, board: &Board) -> Result<ResolvedMovement, String> {
+    pub fn resolve(&self, board: &Board) -> Result<ResolvedMovement, Error> {
         let moves = [
             pseudo_moves(board),
             castle_moves(board, &attacked_squares(board, &board.turn.opposite())),
@@ -67,11 +195,11 @@ impl AmbiguousMovement {
             }
         }
 
-        Err("Unable to resolve ambiguous movement".to_string())
+        Err(Error::UnresolvedMove)
     }
 }
 
-#[derive(Debug, Copy, Default, Clone)]
+#[derive(Debug, Copy, Default, Clone, PartialEq)]
 pub struct ResolvedMovement {
     pub piece: Piece,
     pub from: Square,
@@ -109,4 +237,281 @@ impl ResolvedMovement {
             format!("{}{}", self.from.uci(), self.to.uci())
         }
     }
+
+    /// Writes the move in standard algebraic notation. The board must be the position before the
+    /// move has been made so we can tell if other pieces can move to the same square, and if the
+    /// move gives check or mate.
+    ///
+    /// ```
+    /// use common::{Board, Piece, ResolvedMovement, Square};
+    ///
+    /// let board = Board::from_start_position().unwrap();
+    /// let movement = ResolvedMovement {
+    ///     piece: Piece::Knight,
+    ///     from: Square::G1,
+    ///     to: Square::F3,
+    ///     capture: None,
+    ///     promotion: None,
+    /// };
+    ///
+    /// assert_eq!(movement.to_san(&board), "Nf3");
+    /// ```
+    pub fn to_san(&self, board: &Board) -> String {
+        let piece_letter = |piece: Piece| piece.to_lower().to_ascii_uppercase();
+
+        let mut output = if self.is_white_king_castle() || self.is_black_king_castle() {
+            "O-O".to_string()
+        } else if self.is_white_queen_castle() || self.is_black_queen_castle() {
+            "O-O-O".to_string()
+        } else if self.piece == Piece::Pawn {
+            let mut output = String::new();
+            if self.capture.is_some() {
+                output.push(self.from.file_char());
+                output.push('x');
+            }
+
+            output.push_str(&self.to.uci());
+            if let Some(promotion) = self.promotion {
+                output.push('=');
+                output.push(piece_letter(promotion));
+            }
+
+            output
+        } else {
+            let mut output = piece_letter(self.piece).to_string();
+
+            // Any other pieces of the same type that could also move to the same square
+            let others: Vec<ResolvedMovement> = legal_moves(board)
+                .into_iter()
+                .filter(|m| m.piece == self.piece && m.to == self.to && m.from != self.from)
+                .collect();
+
+            if !others.is_empty() {
+                let file = self.from.file_char();
+                let rank = self.from.rank_char();
+
+                if others.iter().all(|m| m.from.file_char() != file) {
+                    output.push(file);
+                } else if others.iter().all(|m| m.from.rank_char() != rank) {
+                    output.push(rank);
+                } else {
+                    output.push(file);
+                    output.push(rank);
+                }
+            }
+
+            if self.capture.is_some() {
+                output.push('x');
+            }
+
+            output.push_str(&self.to.uci());
+            output
+        };
+
+        let mut new_board = *board;
+        new_board.move_piece(*self);
+
+        let attackers = attacked_squares(&new_board, &new_board.turn.opposite());
+        if is_in_check(&new_board, &attackers) {
+            match legal_moves(&new_board).is_empty() {
+                true => output.push('#'),
+                false => output.push('+'),
+            }
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san(fen: &str, uci: &str) -> String {
+        let board = Board::from_fen_str(fen).unwrap();
+        let movement = legal_moves(&board)
+            .into_iter()
+            .find(|m| m.uci() == uci)
+            .unwrap_or_else(|| panic!("{uci} is not a legal move in {fen}"));
+
+        movement.to_san(&board)
+    }
+
+    fn parse(fen: &str, san: &str) -> Result<String, Error> {
+        let board = Board::from_fen_str(fen).unwrap();
+        Movement::from_san(san, &board).map(|m| m.uci())
+    }
+
+    #[test]
+    fn parses_simple_moves() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+        assert_eq!(parse(start, "e4"), Ok("e2e4".to_string()));
+        assert_eq!(parse(start, "e3"), Ok("e2e3".to_string()));
+        assert_eq!(parse(start, "Nc3"), Ok("b1c3".to_string()));
+        assert_eq!(parse(start, "Nc3!?"), Ok("b1c3".to_string()));
+        assert_eq!(
+            parse(start, "Nd2"),
+            Err(Error::IllegalMove("Nd2".to_string()))
+        );
+        assert_eq!(
+            parse(start, "e5"),
+            Err(Error::IllegalMove("e5".to_string()))
+        );
+        assert_eq!(
+            parse(start, "Zz9"),
+            Err(Error::InvalidMove("Zz9".to_string()))
+        );
+        assert_eq!(parse(start, ""), Err(Error::InvalidMove(String::new())));
+    }
+
+    #[test]
+    fn parses_captures_and_en_passant() {
+        let fen = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+
+        assert_eq!(parse(fen, "exf6"), Ok("e5f6".to_string()));
+        assert_eq!(parse(fen, "exf6 e.p."), Ok("e5f6".to_string()));
+        assert_eq!(parse(fen, "Bb5+"), Ok("f1b5".to_string()));
+        assert_eq!(
+            parse(fen, "Bxb5"),
+            Err(Error::IllegalMove("Bxb5".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_castling() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(parse(fen, "O-O"), Ok("e1g1".to_string()));
+        assert_eq!(parse(fen, "0-0"), Ok("e1g1".to_string()));
+        assert_eq!(parse(fen, "O-O-O"), Ok("e1c1".to_string()));
+        assert_eq!(parse(fen, "0-0-0+"), Ok("e1c1".to_string()));
+
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b Kk - 0 1";
+        assert_eq!(parse(fen, "O-O"), Ok("e8g8".to_string()));
+        assert_eq!(
+            parse(fen, "O-O-O"),
+            Err(Error::IllegalMove("O-O-O".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_promotions() {
+        let fen = "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+
+        assert_eq!(parse(fen, "a8=Q"), Ok("a7a8q".to_string()));
+        assert_eq!(parse(fen, "a8N"), Ok("a7a8n".to_string()));
+        assert_eq!(parse(fen, "axb8=R"), Ok("a7b8r".to_string()));
+        assert_eq!(parse(fen, "axb8Q+"), Ok("a7b8q".to_string()));
+        assert_eq!(parse(fen, "a8"), Err(Error::IllegalMove("a8".to_string())));
+    }
+
+    #[test]
+    fn parses_disambiguation() {
+        let fen = "8/4Q2Q/8/k7/7Q/8/8/K7 w - - 0 1";
+
+        assert_eq!(parse(fen, "Qh7e4"), Ok("h7e4".to_string()));
+        assert_eq!(
+            parse(fen, "Qh7xe4"),
+            Err(Error::IllegalMove("Qh7xe4".to_string()))
+        );
+        assert_eq!(parse(fen, "Q4e4"), Ok("h4e4".to_string()));
+        assert_eq!(parse(fen, "Qee4"), Ok("e7e4".to_string()));
+        assert_eq!(
+            parse(fen, "Qhe4"),
+            Err(Error::AmbiguousMove("Qhe4".to_string()))
+        );
+        assert_eq!(
+            parse(fen, "Q7e4"),
+            Err(Error::AmbiguousMove("Q7e4".to_string()))
+        );
+        assert_eq!(
+            parse(fen, "Qe4"),
+            Err(Error::AmbiguousMove("Qe4".to_string()))
+        );
+
+        let fen = "4k3/8/8/8/7Q/8/8/K3q3 w - - 0 1";
+        assert_eq!(parse(fen, "Qh4xe1+"), Ok("h4e1".to_string()));
+    }
+
+    #[test]
+    fn round_trips_with_to_san() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/1P6/8/3pP3/8/8/6p1/R3K2R w KQkq d6 0 1",
+            "8/4Q2Q/8/k7/7Q/8/8/K7 w - - 0 1",
+        ];
+
+        for fen in fens {
+            let board = Board::from_fen_str(fen).unwrap();
+            for movement in legal_moves(&board) {
+                let san = movement.to_san(&board);
+                let parsed = Movement::from_san(&san, &board).unwrap();
+                assert_eq!(parsed.uci(), movement.uci(), "{san}");
+            }
+        }
+    }
+
+    #[test]
+    fn writes_simple_moves() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+        assert_eq!(san(start, "e2e4"), "e4");
+        assert_eq!(san(start, "b1c3"), "Nc3");
+    }
+
+    #[test]
+    fn writes_captures_and_en_passant() {
+        let fen = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+
+        assert_eq!(san(fen, "e5f6"), "exf6");
+        assert_eq!(san(fen, "f1b5"), "Bb5+");
+
+        let fen = "rnbqkbnr/ppp2ppp/8/3pp3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 3";
+        assert_eq!(san(fen, "f3e5"), "Nxe5");
+        assert_eq!(san(fen, "e4d5"), "exd5");
+    }
+
+    #[test]
+    fn writes_castling() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(san(fen, "e1g1"), "O-O");
+        assert_eq!(san(fen, "e1c1"), "O-O-O");
+
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1";
+        assert_eq!(san(fen, "e8g8"), "O-O");
+        assert_eq!(san(fen, "e8c8"), "O-O-O");
+    }
+
+    #[test]
+    fn writes_promotions() {
+        let fen = "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+
+        assert_eq!(san(fen, "a7a8q"), "a8=Q");
+        assert_eq!(san(fen, "a7b8n"), "axb8=N");
+        assert_eq!(san(fen, "a7a8r"), "a8=R");
+
+        let fen = "4k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(san(fen, "a7a8q"), "a8=Q+");
+    }
+
+    #[test]
+    fn writes_check_mate() {
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+        assert_eq!(san(fen, "h5f7"), "Qxf7#");
+    }
+
+    #[test]
+    fn disambiguates_by_file_rank_or_both() {
+        // The king is in the way of the other rook so there is no need to say which one
+        assert_eq!(san("4k3/8/8/8/8/8/8/R3K2R w - - 0 1", "a1c1"), "Rc1");
+
+        assert_eq!(san("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", "h1f1"), "Rhf1");
+        assert_eq!(san("4k3/8/8/8/R7/8/8/R3K3 w - - 0 1", "a4a2"), "R4a2");
+
+        // Three queens can get to e4, two on the h file and two on the 7th rank
+        let fen = "8/4Q2Q/8/k7/7Q/8/8/K7 w - - 0 1";
+        assert_eq!(san(fen, "h7e4"), "Qh7e4");
+        assert_eq!(san(fen, "h4e4"), "Q4e4");
+        assert_eq!(san(fen, "e7e4"), "Qee4");
+    }
 }
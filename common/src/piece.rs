@@ -10,6 +10,7 @@ pub enum Piece {
 }
 
 impl Piece {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(c: &str) -> Option<Self> {
         match c {
             "k" => Some(Self::King),
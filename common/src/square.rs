@@ -1,3 +1,5 @@
+use crate::Error;
+
 /// The full deffinistion all all of the squares on a board. They are in the order of the what bits
 /// they index in a Bitboard so we can use then to easlly create bitboards or test posative bits at
 /// a squares while makeing things readable.
@@ -41,15 +43,17 @@ impl Square {
     /// let s = common::Square::from_str("a1").unwrap();
     /// assert_eq!(s, common::Square::A1);
     /// ```
-    pub fn from_str(s: &str) -> Result<Self, String> {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<Self, Error> {
         let mut chars = s.chars();
-        let file = chars.next().ok_or("Invalid square")?;
-        let rank = chars.next().ok_or("Invalid square")?;
+        let (Some(file), Some(rank)) = (chars.next(), chars.next()) else {
+            return Err(Error::InvalidSquare(s.to_string()));
+        };
 
         Self::from_file_and_rank_str(&file.to_string(), &rank.to_string())
     }
 
-    pub fn from_file_and_rank_str(file: &str, rank: &str) -> Result<Self, String> {
+    pub fn from_file_and_rank_str(file: &str, rank: &str) -> Result<Self, Error> {
         let file_usize = match file {
             "a" => 0,
             "b" => 1,
@@ -59,7 +63,7 @@ impl Square {
             "f" => 5,
             "g" => 6,
             "h" => 7,
-            _ => return Err(format!("Invalid file '{}'", file)),
+            _ => return Err(Error::InvalidSquare(format!("{file}{rank}"))),
         };
 
         let rank_usize = match rank {
@@ -71,7 +75,7 @@ impl Square {
             "6" => 5,
             "7" => 6,
             "8" => 7,
-            _ => return Err(format!("Invalid rank '{}'", rank)),
+            _ => return Err(Error::InvalidSquare(format!("{file}{rank}"))),
         };
 
         Ok(Self::from_file_and_rank(file_usize, rank_usize))
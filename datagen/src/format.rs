@@ -0,0 +1,175 @@
+//! The formats the training data can be written in.
+//!
+//! The text format has one position per line with the fen, the score in centipawns from whites
+//! point of view and the result from whites point of view, all separated by a pipe.
+//!
+//! ```text
+//! rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR w KQkq - 0 1 | 32 | 1.0
+//! ```
+//!
+//! The binary format packs each position into 32 bytes, all numbers are little endian.
+//!
+//! | Bytes | Content                                                                         |
+//! |-------|---------------------------------------------------------------------------------|
+//! | 0-7   | The occupancy, one bit for each square with H1 as bit 0 and A8 as bit 63        |
+//! | 8-23  | A nibble for each occupied square in bit order, low nibble first. The nibble is |
+//! |       | `color << 3 \| piece` with white as 0 and the pieces as K, Q, R, B, N, P        |
+//! | 24-25 | The score as an `i16` in centipawns from whites point of view                   |
+//! | 26    | The result, 0 for a black win, 1 for a draw and 2 for a white win               |
+//! | 27    | The side to move, 0 for white and 1 for black                                   |
+//! | 28    | The castling rights, 1 for `K`, 2 for `Q`, 4 for `k` and 8 for `q`              |
+//! | 29    | The en passant square or 64 if there isn't one                                  |
+//! | 30-31 | Reserved, always 0                                                              |
+
+use common::{Color, GameResult};
+
+use std::io::Write;
+
+use crate::selfplay::Sample;
+
+pub const RECORD_SIZE: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Binary,
+}
+
+impl Format {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        match input {
+            "text" => Ok(Format::Text),
+            "binary" => Ok(Format::Binary),
+            _ => Err(format!("Invalid format '{input}', expected text or binary")),
+        }
+    }
+}
+
+fn result_index(result: &GameResult) -> u8 {
+    match result {
+        GameResult::BlackWin => 0,
+        GameResult::WhiteWin => 2,
+        GameResult::Draw | GameResult::InProgress => 1,
+    }
+}
+
+pub fn to_text(sample: &Sample) -> String {
+    let result = match sample.result {
+        GameResult::BlackWin => "0.0",
+        GameResult::WhiteWin => "1.0",
+        GameResult::Draw | GameResult::InProgress => "0.5",
+    };
+
+    format!("{} | {} | {result}", sample.board.to_fen(), sample.score)
+}
+
+pub fn to_binary(sample: &Sample) -> [u8; RECORD_SIZE] {
+    let board = &sample.board;
+    let mut record = [0u8; RECORD_SIZE];
+
+    let occupancy = board.white_pieces() | board.black_pieces();
+    record[0..8].copy_from_slice(&occupancy.to_le_bytes());
+
+    let mut squares = occupancy;
+    let mut index = 0;
+    while squares != 0 {
+        let square = squares.trailing_zeros();
+        squares &= squares - 1;
+
+        let (color, piece) = board.get_piece_at(&(1 << square)).unwrap();
+        let nibble = (color as u8) << 3 | piece as u8;
+
+        record[8 + index / 2] |= nibble << ((index % 2) * 4);
+        index += 1;
+    }
+
+    let score = sample.score.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+    record[24..26].copy_from_slice(&score.to_le_bytes());
+    record[26] = result_index(&sample.result);
+    record[27] = match board.turn {
+        Color::White => 0,
+        Color::Black => 1,
+    };
+
+    record[28] = board.white_castling_kings_side as u8
+        | (board.white_castling_queen_side as u8) << 1
+        | (board.black_castling_kings_side as u8) << 2
+        | (board.black_castling_queen_side as u8) << 3;
+
+    record[29] = match board.en_passant {
+        Some(square) => square as u8,
+        None => 64,
+    };
+
+    record
+}
+
+pub fn write_sample(
+    writer: &mut dyn Write,
+    format: Format,
+    sample: &Sample,
+) -> std::io::Result<()> {
+    match format {
+        Format::Text => writeln!(writer, "{}", to_text(sample)),
+        Format::Binary => writer.write_all(&to_binary(sample)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{Board, Piece, Square};
+
+    fn sample(fen: &str, score: i32, result: GameResult) -> Sample {
+        Sample {
+            board: Board::from_fen_str(fen).unwrap(),
+            score,
+            result,
+        }
+    }
+
+    #[test]
+    fn writes_the_text_format() {
+        let sample = sample("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1", -35, GameResult::WhiteWin);
+
+        assert_eq!(
+            to_text(&sample),
+            "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1 | -35 | 1.0"
+        );
+    }
+
+    #[test]
+    fn packs_the_binary_format() {
+        let sample = sample("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1", 120, GameResult::Draw);
+        let record = to_binary(&sample);
+
+        let occupancy = u64::from_le_bytes(record[0..8].try_into().unwrap());
+        assert_eq!(occupancy.count_ones(), 4);
+        assert_eq!(
+            occupancy,
+            1 << Square::E1 as u64
+                | 1 << Square::E4 as u64
+                | 1 << Square::D4 as u64
+                | 1 << Square::E8 as u64
+        );
+
+        // The pieces are in square order, E1 is the lowest bit and E8 the highest
+        assert_eq!(record[8] & 0xf, Piece::King as u8);
+        assert_eq!(record[8] >> 4, Piece::Pawn as u8);
+        assert_eq!(record[9] & 0xf, 8 | Piece::Pawn as u8);
+        assert_eq!(record[9] >> 4, 8 | Piece::King as u8);
+
+        assert_eq!(i16::from_le_bytes([record[24], record[25]]), 120);
+        assert_eq!(record[26], 1);
+        assert_eq!(record[27], 1);
+        assert_eq!(record[28], 0);
+        assert_eq!(record[29], Square::E3 as u8);
+    }
+
+    #[test]
+    fn parses_the_format() {
+        assert_eq!(Format::parse("text"), Ok(Format::Text));
+        assert_eq!(Format::parse("binary"), Ok(Format::Binary));
+        assert!(Format::parse("csv").is_err());
+    }
+}
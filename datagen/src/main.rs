@@ -0,0 +1,154 @@
+mod format;
+mod selfplay;
+
+use cli::{deindent, ArgBuilder};
+use format::Format;
+use selfplay::{Options, Random};
+
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+struct Args {
+    pub output: String,
+    pub games: usize,
+    pub threads: usize,
+    pub seed: u64,
+    pub format: Format,
+    pub options: Options,
+}
+
+fn number<T: std::str::FromStr>(
+    args: &ArgBuilder,
+    short: &str,
+    long: &str,
+    default: T,
+) -> Result<T, String> {
+    match args.string(short, long) {
+        Ok(value) => value
+            .parse()
+            .map_err(|_| format!("Invalid value '{value}' for {long}")),
+        Err(_) => Ok(default),
+    }
+}
+
+impl Args {
+    fn new() -> Result<Self, String> {
+        let args = ArgBuilder::new(std::env::args().collect());
+        if args.bool("-h", "--help") {
+            return Err(deindent(
+                r#"
+                    Usage: datagen [OPTIONS]
+
+                    Plays the engine against its self and writes out the quiet positions with the
+                    search score and the result of the game.
+
+                        --help, -h                Display this help message
+                        --output, -o <file>       The file to write the positions to
+                        --games, -g <count>       The number of games to play (default 100)
+                        --nodes, -n <count>       The number of nodes to search for each move (default 5000)
+                        --random-plies, -r <n>    The number of random moves to start each game with (default 8)
+                        --max-plies, -m <n>       Call the game a draw after this many moves (default 400)
+                        --threads, -t <count>     The number of games to play at once (default all cores)
+                        --seed, -s <seed>         The seed for the random openings (default the time)
+                        --format, -f <format>     Either text or binary (default text)
+
+                    The text format has a line for each position with the fen, the score from
+                    whites point of view in centipawns and the result, for example
+
+                        4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 | 250 | 1.0
+
+                    The binary format packs each position into 32 bytes, see format.rs for the
+                    layout.
+                "#,
+            ));
+        }
+
+        let threads = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(1);
+
+        let format = match args.string("-f", "--format") {
+            Ok(format) => Format::parse(&format)?,
+            Err(_) => Format::Text,
+        };
+
+        Ok(Args {
+            output: args.string("-o", "--output")?,
+            games: number(&args, "-g", "--games", 100)?,
+            threads: number(&args, "-t", "--threads", threads)?.max(1),
+            seed: number(&args, "-s", "--seed", time)?,
+            format,
+            options: Options {
+                nodes: number(&args, "-n", "--nodes", 5000)?,
+                random_plies: number(&args, "-r", "--random-plies", 8)?,
+                max_plies: number(&args, "-m", "--max-plies", 400)?,
+            },
+        })
+    }
+}
+
+fn main() {
+    let args = match Args::new() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
+    };
+
+    let file = match std::fs::File::create(&args.output) {
+        Ok(file) => file,
+        Err(e) => {
+            println!("Unable to create {}\n    {e}", args.output);
+            return;
+        }
+    };
+
+    let mut writer = BufWriter::new(file);
+    let next_game = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::scope(|scope| {
+        for thread in 0..args.threads {
+            let sender = sender.clone();
+            let next_game = &next_game;
+            let args = &args;
+
+            scope.spawn(move || {
+                let mut random = Random::new(args.seed.wrapping_add(thread as u64));
+                while next_game.fetch_add(1, Ordering::Relaxed) < args.games {
+                    let samples = selfplay::play_game(&mut random, &args.options);
+                    if sender.send(samples).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        // Drop our sender so the receiver stops once all the threads have finished
+        drop(sender);
+
+        let mut positions = 0;
+        for (game, samples) in receiver.iter().enumerate() {
+            for sample in &samples {
+                if let Err(e) = format::write_sample(&mut writer, args.format, sample) {
+                    println!("Unable to write to {}\n    {e}", args.output);
+                    std::process::exit(1);
+                }
+            }
+
+            positions += samples.len();
+            println!("Game {}/{}, {positions} positions", game + 1, args.games);
+        }
+    });
+
+    if let Err(e) = writer.flush() {
+        println!("Unable to write to {}\n    {e}", args.output);
+    }
+}
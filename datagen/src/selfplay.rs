@@ -0,0 +1,196 @@
+use common::ResolvedMovement;
+use common::{attacked_squares, is_in_check, legal_moves, Board, Color, GameResult, Piece};
+use engine::evaluation::endgame::is_insufficient_material;
+use engine::search::Search;
+use engine::transposition_table::TranspositionTable;
+use engine::uci::UciWriter;
+
+/// Positions with a score bigger than this are already decided, they don't teach the evaluation
+/// anything and the mate scores would swamp everything else.
+const MAX_SCORE: i32 = 2000;
+
+/// The depth we let the iterative deepening go up to, in practice the node limit will stop the
+/// search way before this.
+const MAX_DEPTH: usize = 64;
+
+/// A small xorshift generator, we only need it to pick the opening moves so there is no need for
+/// anything fancy.
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed.max(1) }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    pub fn below(&mut self, max: usize) -> usize {
+        (self.next_u64() % max as u64) as usize
+    }
+}
+
+pub struct Options {
+    /// The number of nodes to search for each move.
+    pub nodes: i32,
+    /// The number of random moves played at the start of each game.
+    pub random_plies: usize,
+    /// Games that go on longer than this are called a draw.
+    pub max_plies: usize,
+}
+
+/// A position from a game labelled with what the engine thought of it and how the game ended.
+pub struct Sample {
+    pub board: Board,
+    /// The score of the search in centipawns from whites point of view.
+    pub score: i32,
+    pub result: GameResult,
+}
+
+struct NullWriter;
+
+impl UciWriter for NullWriter {
+    fn writeln(&mut self, _output: &str) {}
+}
+
+fn is_check(board: &Board) -> bool {
+    is_in_check(board, &attacked_squares(board, &board.turn.opposite()))
+}
+
+/// Plays random moves from the start position so each game starts somewhere different. This
+/// will return `None` if the random moves end the game.
+fn random_opening(random: &mut Random, plies: usize) -> Option<Board> {
+    let mut board = Board::from_start_position().unwrap();
+    for _ in 0..plies {
+        let moves = legal_moves(&board);
+        if moves.is_empty() {
+            return None;
+        }
+
+        board.move_piece(moves[random.below(moves.len())]);
+    }
+
+    if legal_moves(&board).is_empty() {
+        return None;
+    }
+
+    Some(board)
+}
+
+fn search(
+    transposition_table: &mut TranspositionTable,
+    board: &Board,
+    nodes: i32,
+) -> Option<(ResolvedMovement, i32)> {
+    let mut writer = NullWriter;
+
+    let best = Search::new(&mut writer, transposition_table, *board, MAX_DEPTH)
+        .with_node_limit(nodes)
+        .best_move();
+
+    transposition_table.clean();
+    best
+}
+
+/// Plays a game of the engine against its self, returning all the quiet positions from the game.
+/// A position is skipped if the side to move is in check, the best move is a capture or a
+/// promotion, or the game is already decided. The transposition table is kept for the whole
+/// game the same as when the engine is playing through UCI.
+pub fn play_game(random: &mut Random, options: &Options) -> Vec<Sample> {
+    let mut board = loop {
+        if let Some(board) = random_opening(random, options.random_plies) {
+            break board;
+        }
+    };
+
+    let mut positions: Vec<(Board, i32)> = Vec::new();
+    let mut history: Vec<u64> = vec![board.hash()];
+    let mut half_move_clock = 0;
+    let mut transposition_table = TranspositionTable::new();
+
+    let result = loop {
+        let moves = legal_moves(&board);
+        if moves.is_empty() {
+            break match (is_check(&board), board.turn) {
+                (true, Color::White) => GameResult::BlackWin,
+                (true, Color::Black) => GameResult::WhiteWin,
+                (false, _) => GameResult::Draw,
+            };
+        }
+
+        let hash = board.hash();
+        let repetitions = history.iter().filter(|h| **h == hash).count();
+        if half_move_clock >= 100
+            || repetitions >= 3
+            || is_insufficient_material(&board)
+            || history.len() > options.max_plies
+        {
+            break GameResult::Draw;
+        }
+
+        // The search always finds a move when there is one, if it doesn't something has gone
+        // wrong and we can't trust any of the scores from this game
+        let Some((movement, score)) = search(&mut transposition_table, &board, options.nodes)
+        else {
+            return Vec::new();
+        };
+
+        let is_quiet = movement.capture.is_none() && movement.promotion.is_none();
+        if is_quiet && !is_check(&board) && score.abs() < MAX_SCORE {
+            let score = match board.turn {
+                Color::White => score,
+                Color::Black => -score,
+            };
+
+            positions.push((board, score));
+        }
+
+        if movement.piece == Piece::Pawn || movement.capture.is_some() {
+            half_move_clock = 0;
+        } else {
+            half_move_clock += 1;
+        }
+
+        board.move_piece(movement);
+        history.push(board.hash());
+    };
+
+    positions
+        .into_iter()
+        .map(|(board, score)| Sample {
+            board,
+            score,
+            result: result.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_a_game_to_the_end() {
+        let mut random = Random::new(42);
+        let options = Options {
+            nodes: 200,
+            random_plies: 8,
+            max_plies: 40,
+        };
+
+        let samples = play_game(&mut random, &options);
+
+        assert!(!samples.is_empty());
+        for sample in samples {
+            assert!(sample.score.abs() < MAX_SCORE);
+            assert!(!is_check(&sample.board));
+            assert!(!matches!(sample.result, GameResult::InProgress));
+        }
+    }
+}
@@ -1,4 +1,17 @@
-use common::{BitBoardIterator, Board, Color, Piece};
+use common::{
+    bb, bishop_attacks, king_attacks, knight_attacks, pawn_attack_board, queen_attacks,
+    rook_attacks, BitBoard, BitBoardIterator, BitBoardable, Board, Color, Piece, FILE_BITBOARDS,
+    RANK_BITBOARDS,
+};
+
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+pub mod endgame;
+pub mod trace;
+
+/// The game phase when all of the pieces are on the board. Knights and bishops count for one,
+/// rooks for two and queens for four.
+const MAX_PHASE: i32 = 24;
 
 #[rustfmt::skip]
 const PAWN_SCORE: [i32; 64] = [
@@ -72,61 +85,556 @@ const KING_SCORE: [i32; 64] = [
      20, 30, 10,  0,  0, 10, 30, 20
 ];
 
-fn material_score(board: &Board) -> i32 {
-    let white_pieces = (board.white_pawn_board.count_ones() as i32 * 100)
-        + (board.white_queen_board.count_ones() as i32 * 9 * 100)
-        + (board.white_rook_board.count_ones() as i32 * 5 * 100)
-        + (board.white_bishop_board.count_ones() as i32 * 3 * 100)
-        + (board.white_knight_board.count_ones() as i32 * 3 * 100);
+/// The value of each piece in centipawns indexed in the K, Q, R, B, N, P order.
+const PIECE_VALUE: [i32; 6] = [0, 900, 500, 300, 300, 100];
 
-    let black_pieces = (board.black_pawn_board.count_ones() as i32 * 100)
-        + (board.black_queen_board.count_ones() as i32 * 9 * 100)
-        + (board.black_rook_board.count_ones() as i32 * 5 * 100)
-        + (board.black_bishop_board.count_ones() as i32 * 3 * 100)
-        + (board.black_knight_board.count_ones() as i32 * 3 * 100);
+pub fn material_score(board: &Board, color: Color, params: &Parameters) -> Score {
+    let mut value = 0;
+    for piece in [
+        Piece::Queen,
+        Piece::Rook,
+        Piece::Bishop,
+        Piece::Knight,
+        Piece::Pawn,
+    ] {
+        value += board.piece_board(color, piece).count_ones() as i32
+            * params.piece_value[piece as usize];
+    }
 
-    let offset = match board.turn {
-        Color::White => 1,
-        Color::Black => -1,
+    Score::new(value, value)
+}
+
+/// A score split into its middle game and end game values. Terms like king safety matter a lot
+/// more while there are pieces on the board, so each term gets a value for both and they are
+/// blended together with the game phase at the end of the evaluation.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Self { mg, eg }
+    }
+
+    /// Blend the middle and end game values together, a phase of `MAX_PHASE` is the opening and
+    /// a phase of zero is a bare king and pawn ending.
+    pub fn taper(&self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+
+    fn add(self, other: Score) -> Score {
+        Score::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+
+    fn sub(self, other: Score) -> Score {
+        Score::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+
+    fn mul(self, factor: i32) -> Score {
+        Score::new(self.mg * factor, self.eg * factor)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score::new(-self.mg, -self.eg)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, other: Score) {
+        *self = *self - other;
+    }
+}
+
+/// The bonus for each safe square a piece can move to above its baseline, indexed by the piece in
+/// the same K, Q, R, B, N, P order as the `MVV_LVA` table.
+const MOBILITY_BONUS: [Score; 6] = [
+    Score::new(0, 0),
+    Score::new(1, 2),
+    Score::new(2, 4),
+    Score::new(5, 5),
+    Score::new(4, 4),
+    Score::new(0, 0),
+];
+
+/// The number of safe squares a piece will have on an average board. Pieces with fewer squares
+/// than this will get penalised.
+const MOBILITY_BASELINE: [i32; 6] = [0, 13, 7, 7, 4, 0];
+
+/// How dangerous each piece is when attacking a square next to the enemy king.
+const KING_ATTACK_WEIGHT: [i32; 6] = [0, 5, 3, 2, 2, 0];
+
+/// The penalty for the accumulated king attack weight. This will only be used if there are at
+/// least two pieces attacking the king zone, one piece on its own is not much of an attack.
+///
+/// See: https://www.chessprogramming.org/King_Safety#Attack_Units
+#[rustfmt::skip]
+const KING_ATTACK_PENALTY: [i32; 24] = [
+      0,   0,   2,   6,  12,  20,  30,  42,
+     56,  72,  90, 110, 132, 156, 182, 210,
+    240, 272, 306, 342, 380, 420, 462, 500,
+];
+
+/// The bonus for each pawn in front of a castled king, one and two ranks in front of it.
+const PAWN_SHIELD: [i32; 2] = [15, 8];
+
+const DOUBLED_PAWN: Score = Score::new(-10, -20);
+const ISOLATED_PAWN: Score = Score::new(-10, -15);
+
+/// The bonus for a passed pawn indexed by its relative rank.
+const PASSED_PAWN: [Score; 8] = [
+    Score::new(0, 0),
+    Score::new(5, 10),
+    Score::new(10, 15),
+    Score::new(15, 25),
+    Score::new(25, 45),
+    Score::new(40, 75),
+    Score::new(60, 120),
+    Score::new(0, 0),
+];
+
+const BISHOP_PAIR: Score = Score::new(30, 50);
+const ROOK_OPEN_FILE: Score = Score::new(25, 10);
+const ROOK_SEMI_OPEN_FILE: Score = Score::new(12, 6);
+const ROOK_ON_SEVENTH: Score = Score::new(10, 25);
+const KNIGHT_OUTPOST: Score = Score::new(20, 10);
+
+/// All of the weights used in the evaluation. The engine always uses `Parameters::DEFAULT` that
+/// is built from the constants above, the tuner builds its own so it can try out new values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parameters {
+    pub piece_value: [i32; 6],
+    /// The piece square tables indexed in the K, Q, R, B, N, P order
+    pub piece_square: [[i32; 64]; 6],
+    pub mobility_bonus: [Score; 6],
+    pub mobility_baseline: [i32; 6],
+    pub king_attack_weight: [i32; 6],
+    pub king_attack_penalty: [i32; 24],
+    pub pawn_shield: [i32; 2],
+    pub doubled_pawn: Score,
+    pub isolated_pawn: Score,
+    pub passed_pawn: [Score; 8],
+    pub bishop_pair: Score,
+    pub rook_open_file: Score,
+    pub rook_semi_open_file: Score,
+    pub rook_on_seventh: Score,
+    pub knight_outpost: Score,
+}
+
+impl Parameters {
+    pub const DEFAULT: Parameters = Parameters {
+        piece_value: PIECE_VALUE,
+        piece_square: [
+            KING_SCORE,
+            QUEEN_SCORE,
+            ROOK_SCORE,
+            BISHOP_SCORE,
+            KNIGHT_SCORE,
+            PAWN_SCORE,
+        ],
+        mobility_bonus: MOBILITY_BONUS,
+        mobility_baseline: MOBILITY_BASELINE,
+        king_attack_weight: KING_ATTACK_WEIGHT,
+        king_attack_penalty: KING_ATTACK_PENALTY,
+        pawn_shield: PAWN_SHIELD,
+        doubled_pawn: DOUBLED_PAWN,
+        isolated_pawn: ISOLATED_PAWN,
+        passed_pawn: PASSED_PAWN,
+        bishop_pair: BISHOP_PAIR,
+        rook_open_file: ROOK_OPEN_FILE,
+        rook_semi_open_file: ROOK_SEMI_OPEN_FILE,
+        rook_on_seventh: ROOK_ON_SEVENTH,
+        knight_outpost: KNIGHT_OUTPOST,
     };
+}
 
-    (white_pieces - black_pieces) * offset
+impl Default for Parameters {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
 }
 
-pub fn evaluate(board: &Board) -> i32 {
-    let mut score = material_score(board);
+/// Calculates the game phase from the pieces that are left on the board.
+///
+/// See: https://www.chessprogramming.org/Tapered_Eval
+pub fn phase(board: &Board) -> i32 {
+    let minors = board.white_knight_board
+        | board.black_knight_board
+        | board.white_bishop_board
+        | board.black_bishop_board;
+    let rooks = board.white_rook_board | board.black_rook_board;
+    let queens = board.white_queen_board | board.black_queen_board;
+
+    let phase =
+        minors.count_ones() as i32 + rooks.count_ones() as i32 * 2 + queens.count_ones() as i32 * 4;
+
+    phase.min(MAX_PHASE)
+}
 
-    for (_, piece, bitboard) in board.white_boards() {
-        let mut it = BitBoardIterator::new(bitboard.reverse_bits());
+/// The rank from the point of view of the color, so the first rank is always the rank the pieces
+/// start on.
+fn relative_rank(color: Color, rank: usize) -> usize {
+    match color {
+        Color::White => rank,
+        Color::Black => 7 - rank,
+    }
+}
+
+fn adjacent_files(file: usize) -> BitBoard {
+    let mut files = 0;
+    if file > 0 {
+        files |= FILE_BITBOARDS[file - 1];
+    }
+
+    if file < 7 {
+        files |= FILE_BITBOARDS[file + 1];
+    }
+
+    files
+}
+
+/// All of the ranks in front of a rank from the point of view of the color.
+fn forward_ranks(color: Color, rank: usize) -> BitBoard {
+    let mut ranks = 0;
+    for (index, rank_board) in RANK_BITBOARDS.iter().enumerate() {
+        let is_forward = match color {
+            Color::White => index > rank,
+            Color::Black => index < rank,
+        };
+
+        if is_forward {
+            ranks |= rank_board;
+        }
+    }
+
+    ranks
+}
+
+fn piece_attacks(piece: Piece, square_board: BitBoard, occupancies: BitBoard) -> BitBoard {
+    match piece {
+        Piece::Knight => knight_attacks(square_board),
+        Piece::Bishop => bishop_attacks(square_board, occupancies),
+        Piece::Rook => rook_attacks(square_board, occupancies),
+        Piece::Queen => queen_attacks(square_board, occupancies),
+        Piece::King => king_attacks(square_board),
+        Piece::Pawn => 0,
+    }
+}
+
+/// Scores the pawn structure, penalising doubled and isolated pawns and rewarding passed pawns
+/// the closer they get to promoting.
+///
+/// See: https://www.chessprogramming.org/Pawn_Structure
+pub fn pawn_score(board: &Board, color: Color, params: &Parameters) -> Score {
+    let pawns = board.piece_board(color, Piece::Pawn);
+    let enemy_pawns = board.piece_board(color.opposite(), Piece::Pawn);
+
+    let mut score = Score::default();
+    for file_board in FILE_BITBOARDS {
+        let count = (pawns & file_board).count_ones() as i32;
+        if count > 1 {
+            score += params.doubled_pawn * (count - 1);
+        }
+    }
+
+    let mut it = BitBoardIterator::new(pawns);
+    while let Some(index) = it.next() {
+        let square: BitBoard = bb!(index);
+        let (file, rank) = square.file_and_rank();
+
+        if pawns & adjacent_files(file) == 0 {
+            score += params.isolated_pawn;
+        }
+
+        let blockers = (FILE_BITBOARDS[file] | adjacent_files(file)) & forward_ranks(color, rank);
+        if enemy_pawns & blockers == 0 {
+            score += params.passed_pawn[relative_rank(color, rank)];
+        }
+    }
+
+    score
+}
+
+/// Scores the pieces on how many squares they can move to. Squares that are occupied by our own
+/// pieces or are attacked by the opponent's pawns are not counted as they are not really
+/// available.
+///
+/// See: https://www.chessprogramming.org/Mobility
+pub fn mobility_score(board: &Board, color: Color, params: &Parameters) -> Score {
+    let occupancies = board.white_pieces() | board.black_pieces();
+    let enemy_pawns = board.piece_board(color.opposite(), Piece::Pawn);
+    let area = !board.color_pieces(color) & !pawn_attack_board(enemy_pawns, &color.opposite());
+
+    let mut score = Score::default();
+    for piece in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight] {
+        let mut it = BitBoardIterator::new(board.piece_board(color, piece));
         while let Some(index) = it.next() {
-            score += match piece {
-                Piece::Pawn => PAWN_SCORE[index],
-                Piece::Rook => ROOK_SCORE[index],
-                Piece::Queen => QUEEN_SCORE[index],
-                Piece::Bishop => BISHOP_SCORE[index],
-                Piece::Knight => KNIGHT_SCORE[index],
-                Piece::King => KING_SCORE[index],
-            }
+            let squares = (piece_attacks(piece, bb!(index), occupancies) & area).count_ones();
+            score += params.mobility_bonus[piece as usize]
+                * (squares as i32 - params.mobility_baseline[piece as usize]);
         }
     }
 
-    for (_, piece, bitboard) in board.black_boards() {
-        let mut it = BitBoardIterator::new(bitboard);
+    score
+}
+
+/// Scores how safe the king is from the number of enemy pieces attacking the squares around it
+/// and the pawns sheltering it. This is only a middle game term, in the end game the king needs
+/// to get out and be active.
+///
+/// See: https://www.chessprogramming.org/King_Safety
+pub fn king_safety_score(board: &Board, color: Color, params: &Parameters) -> Score {
+    let king = board.piece_board(color, Piece::King);
+    if king == 0 {
+        return Score::default();
+    }
+
+    let occupancies = board.white_pieces() | board.black_pieces();
+    let zone = king_attacks(king) | king;
+
+    let mut attackers = 0;
+    let mut attack_weight = 0;
+    for piece in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight] {
+        let mut it = BitBoardIterator::new(board.piece_board(color.opposite(), piece));
         while let Some(index) = it.next() {
-            score -= match piece {
-                Piece::Pawn => PAWN_SCORE[index],
-                Piece::Rook => ROOK_SCORE[index],
-                Piece::Queen => QUEEN_SCORE[index],
-                Piece::Bishop => BISHOP_SCORE[index],
-                Piece::Knight => KNIGHT_SCORE[index],
-                Piece::King => KING_SCORE[index],
+            let hits = piece_attacks(piece, bb!(index), occupancies) & zone;
+            if hits != 0 {
+                attackers += 1;
+                attack_weight +=
+                    params.king_attack_weight[piece as usize] * hits.count_ones() as i32;
             }
         }
     }
 
+    let mut score = Score::default();
+    if attackers >= 2 {
+        let index = (attack_weight as usize).min(params.king_attack_penalty.len() - 1);
+        score.mg -= params.king_attack_penalty[index];
+    }
+
+    let (file, rank) = king.file_and_rank();
+    if relative_rank(color, rank) <= 1 {
+        let pawns =
+            board.piece_board(color, Piece::Pawn) & (FILE_BITBOARDS[file] | adjacent_files(file));
+        for (distance, bonus) in params.pawn_shield.iter().enumerate() {
+            let shield_rank = match color {
+                Color::White => rank + distance + 1,
+                Color::Black => rank - distance - 1,
+            };
+
+            score.mg += bonus * (pawns & RANK_BITBOARDS[shield_rank]).count_ones() as i32;
+        }
+    }
+
+    score
+}
+
+/// Scores the piece specific terms, the bishop pair, rooks on open files and the seventh rank and
+/// knights on outposts.
+pub fn piece_score(board: &Board, color: Color, params: &Parameters) -> Score {
+    let mut score = Score::default();
+
+    let pawns = board.piece_board(color, Piece::Pawn);
+    let enemy_pawns = board.piece_board(color.opposite(), Piece::Pawn);
+    let enemy_king = board.piece_board(color.opposite(), Piece::King);
+
+    if board.piece_board(color, Piece::Bishop).count_ones() >= 2 {
+        score += params.bishop_pair;
+    }
+
+    let (seventh_rank, eighth_rank) = match color {
+        Color::White => (RANK_BITBOARDS[6], RANK_BITBOARDS[7]),
+        Color::Black => (RANK_BITBOARDS[1], RANK_BITBOARDS[0]),
+    };
+
+    let mut it = BitBoardIterator::new(board.piece_board(color, Piece::Rook));
+    while let Some(index) = it.next() {
+        let square: BitBoard = bb!(index);
+        let (file, _) = square.file_and_rank();
+        if FILE_BITBOARDS[file] & (pawns | enemy_pawns) == 0 {
+            score += params.rook_open_file;
+        } else if FILE_BITBOARDS[file] & pawns == 0 {
+            score += params.rook_semi_open_file;
+        }
+
+        if square & seventh_rank != 0
+            && (enemy_king & eighth_rank != 0 || enemy_pawns & seventh_rank != 0)
+        {
+            score += params.rook_on_seventh;
+        }
+    }
+
+    let supported = pawn_attack_board(pawns, &color);
+    let mut it = BitBoardIterator::new(board.piece_board(color, Piece::Knight));
+    while let Some(index) = it.next() {
+        let square: BitBoard = bb!(index);
+        let (file, rank) = square.file_and_rank();
+        let attackers = adjacent_files(file) & forward_ranks(color, rank) & enemy_pawns;
+
+        if (3..=5).contains(&relative_rank(color, rank))
+            && supported & square != 0
+            && attackers == 0
+        {
+            score += params.knight_outpost;
+        }
+    }
+
     score
 }
 
+/// Scores all the pieces of one color from there piece square tables.
+pub fn piece_square_score(board: &Board, color: Color, params: &Parameters) -> Score {
+    let boards = match color {
+        Color::White => board.white_boards(),
+        Color::Black => board.black_boards(),
+    };
+
+    let mut score = 0;
+    for (_, piece, bitboard) in boards {
+        // The tables are from whites point of view, for black we need to look at the squares
+        // from the other side of the board.
+        let mut it = match color {
+            Color::White => BitBoardIterator::new(bitboard.reverse_bits()),
+            Color::Black => BitBoardIterator::new(bitboard),
+        };
+
+        while let Some(index) = it.next() {
+            score += params.piece_square[piece as usize][index];
+        }
+    }
+
+    Score::new(score, score)
+}
+
+/// All of the terms that make up the evaluation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Term {
+    Material,
+    PieceSquares,
+    Pawns,
+    Mobility,
+    KingSafety,
+    Pieces,
+    /// The score from a specialised end game evaluator, none of the other terms are used when
+    /// there is one for the material on the board.
+    Endgame,
+}
+
+impl Term {
+    pub const ALL: [Term; 7] = [
+        Term::Material,
+        Term::PieceSquares,
+        Term::Pawns,
+        Term::Mobility,
+        Term::KingSafety,
+        Term::Pieces,
+        Term::Endgame,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::PieceSquares => "PSTs",
+            Term::Pawns => "Pawns",
+            Term::Mobility => "Mobility",
+            Term::KingSafety => "King safety",
+            Term::Pieces => "Pieces",
+            Term::Endgame => "Endgame",
+        }
+    }
+}
+
+/// Receives each of the terms as the board is evaluated. This lets us see why the engine thinks
+/// a position is good or bad without slowing down the normal evaluation.
+pub trait Tracer {
+    fn record(&mut self, term: Term, color: Color, score: Score);
+}
+
+/// A tracer that throws everything away, this is used for the normal evaluation.
+pub struct NoTrace;
+
+impl Tracer for NoTrace {
+    #[inline(always)]
+    fn record(&mut self, _term: Term, _color: Color, _score: Score) {}
+}
+
+/// Evaluates the board from the point of view of the side to move.
+pub fn evaluate(board: &Board) -> i32 {
+    evaluate_with(board, &mut NoTrace)
+}
+
+/// Evaluates the board from the point of view of the side to move, sending each of the terms for
+/// both sides to the tracer.
+pub fn evaluate_with<T: Tracer + ?Sized>(board: &Board, tracer: &mut T) -> i32 {
+    evaluate_with_parameters(board, &Parameters::DEFAULT, tracer)
+}
+
+/// Evaluates the board with a custom set of weights. This is what the tuner uses to try out new
+/// values without having to rebuild the engine.
+pub fn evaluate_with_parameters<T: Tracer + ?Sized>(
+    board: &Board,
+    params: &Parameters,
+    tracer: &mut T,
+) -> i32 {
+    let endgames = endgame::endgames();
+    if let Some(score) = endgames.probe(board) {
+        tracer.record(Term::Endgame, board.turn, Score::new(score, score));
+        return score;
+    }
+
+    let mut total = Score::default();
+
+    for color in [Color::White, Color::Black] {
+        let terms = [
+            (Term::Material, material_score(board, color, params)),
+            (Term::PieceSquares, piece_square_score(board, color, params)),
+            (Term::Pawns, pawn_score(board, color, params)),
+            (Term::Mobility, mobility_score(board, color, params)),
+            (Term::KingSafety, king_safety_score(board, color, params)),
+            (Term::Pieces, piece_score(board, color, params)),
+        ];
+
+        for (term, score) in terms {
+            tracer.record(term, color, score);
+            total += match color {
+                Color::White => score,
+                Color::Black => -score,
+            };
+        }
+    }
+
+    let offset = match board.turn {
+        Color::White => 1,
+        Color::Black => -1,
+    };
+
+    total.eg = total.eg * endgames.scale_factor(board) / endgame::SCALE_NORMAL;
+    total.taper(phase(board)) * offset
+}
+
 #[cfg(test)]
 mod tests {
     use common::{bb, BitBoard, Square};
@@ -149,6 +657,167 @@ mod tests {
         assert_eq!(0, PAWN_SCORE[bb_index!(Square::F4)]);
     }
 
+    /// Rotates the board and swaps the colors so we can test the evaluation is the same for both
+    /// sides.
+    fn mirror_fen(fen: &str) -> String {
+        let parts: Vec<&str> = fen.split_whitespace().collect();
+        let pieces = parts[0]
+            .split('/')
+            .rev()
+            .map(|rank| {
+                rank.chars()
+                    .rev()
+                    .map(|c| match c.is_uppercase() {
+                        true => c.to_ascii_lowercase(),
+                        false => c.to_ascii_uppercase(),
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let turn = if parts[1] == "w" { "b" } else { "w" };
+        format!("{pieces} {turn} - - 0 1")
+    }
+
+    #[test]
+    fn evaluates_the_start_position_as_equal() {
+        let board = Board::from_start_position().unwrap();
+        assert_eq!(0, evaluate(&board));
+    }
+
+    #[test]
+    fn evaluation_is_the_same_for_both_sides() {
+        let fens = vec![
+            "r1b1qrk1/pp2bpp1/3pnn1p/2p1p3/2P5/1QNPP1P1/PP1N1PBP/R1B2RK1 b - - 0 1",
+            "2kr3r/ppp2pQp/4p3/8/Pb2b3/8/2P2PPP/R1B2RK1 b - - 0 1",
+            "6k1/5ppp/8/3N4/3P4/8/5PPP/R5K1 w - - 0 1",
+        ];
+
+        for fen in fens {
+            let board = Board::from_fen_str(fen).unwrap();
+            let mirrored = Board::from_fen_str(&mirror_fen(fen)).unwrap();
+            assert_eq!(evaluate(&board), evaluate(&mirrored), "{fen}");
+        }
+    }
+
+    #[test]
+    fn pawn_structure() {
+        let doubled = Board::from_fen_str("4k3/8/8/8/8/3P4/3P4/4K3 w - - 0 1").unwrap();
+        let isolated = Board::from_fen_str("4k3/p7/8/8/8/8/7P/4K3 w - - 0 1").unwrap();
+        let passed = Board::from_fen_str("4k3/8/1P6/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+        assert_eq!(
+            pawn_score(&doubled, Color::White, &Parameters::DEFAULT),
+            DOUBLED_PAWN + ISOLATED_PAWN * 2 + PASSED_PAWN[1] + PASSED_PAWN[2]
+        );
+        assert_eq!(
+            pawn_score(&isolated, Color::Black, &Parameters::DEFAULT),
+            ISOLATED_PAWN + PASSED_PAWN[1]
+        );
+        assert_eq!(
+            pawn_score(&passed, Color::White, &Parameters::DEFAULT),
+            ISOLATED_PAWN + PASSED_PAWN[5]
+        );
+    }
+
+    #[test]
+    fn bishop_pair() {
+        let pair = Board::from_fen_str("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1").unwrap();
+        let single = Board::from_fen_str("4k3/8/8/8/8/8/8/2N1KB2 w - - 0 1").unwrap();
+
+        assert_eq!(
+            piece_score(&pair, Color::White, &Parameters::DEFAULT),
+            BISHOP_PAIR
+        );
+        assert_eq!(
+            piece_score(&single, Color::White, &Parameters::DEFAULT),
+            Score::default()
+        );
+    }
+
+    #[test]
+    fn rooks_on_open_and_semi_open_files() {
+        let open = Board::from_fen_str("4k3/p7/8/8/8/8/P7/3RK3 w - - 0 1").unwrap();
+        let semi_open = Board::from_fen_str("3pk3/8/8/8/8/8/8/3RK3 w - - 0 1").unwrap();
+        let closed = Board::from_fen_str("4k3/8/8/8/8/8/3P4/3RK3 w - - 0 1").unwrap();
+
+        assert_eq!(
+            piece_score(&open, Color::White, &Parameters::DEFAULT),
+            ROOK_OPEN_FILE
+        );
+        assert_eq!(
+            piece_score(&semi_open, Color::White, &Parameters::DEFAULT),
+            ROOK_SEMI_OPEN_FILE
+        );
+        assert_eq!(
+            piece_score(&closed, Color::White, &Parameters::DEFAULT),
+            Score::default()
+        );
+    }
+
+    #[test]
+    fn rook_on_the_seventh() {
+        let board = Board::from_fen_str("4k3/1R6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            piece_score(&board, Color::White, &Parameters::DEFAULT),
+            ROOK_OPEN_FILE + ROOK_ON_SEVENTH
+        );
+    }
+
+    #[test]
+    fn knight_outposts() {
+        let outpost = Board::from_fen_str("4k3/8/8/4N3/3P4/8/8/4K3 w - - 0 1").unwrap();
+        let attackable = Board::from_fen_str("4k3/5p2/8/4N3/3P4/8/8/4K3 w - - 0 1").unwrap();
+        let unsupported = Board::from_fen_str("4k3/8/8/4N3/8/8/8/4K3 w - - 0 1").unwrap();
+
+        assert_eq!(
+            piece_score(&outpost, Color::White, &Parameters::DEFAULT),
+            KNIGHT_OUTPOST
+        );
+        assert_eq!(
+            piece_score(&attackable, Color::White, &Parameters::DEFAULT),
+            Score::default()
+        );
+        assert_eq!(
+            piece_score(&unsupported, Color::White, &Parameters::DEFAULT),
+            Score::default()
+        );
+    }
+
+    #[test]
+    fn the_pawn_shield_protects_the_king() {
+        let shielded = Board::from_fen_str("6k1/8/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+        let exposed = Board::from_fen_str("6k1/8/8/8/8/5PPP/8/6K1 w - - 0 1").unwrap();
+
+        assert!(
+            king_safety_score(&shielded, Color::White, &Parameters::DEFAULT).mg
+                > king_safety_score(&exposed, Color::White, &Parameters::DEFAULT).mg
+        );
+    }
+
+    #[test]
+    fn penalises_pieces_attacking_the_king() {
+        let attacked = Board::from_fen_str("6k1/5ppp/8/6NQ/2B5/8/5PPP/6K1 b - - 0 1").unwrap();
+        let quiet = Board::from_fen_str("6k1/5ppp/8/8/8/8/5PPP/1N1Q1BK1 b - - 0 1").unwrap();
+
+        assert!(
+            king_safety_score(&attacked, Color::Black, &Parameters::DEFAULT).mg
+                < king_safety_score(&quiet, Color::Black, &Parameters::DEFAULT).mg
+        );
+    }
+
+    #[test]
+    fn trapped_pieces_have_less_mobility() {
+        let free = Board::from_fen_str("4k3/8/8/8/3B4/8/8/4K3 w - - 0 1").unwrap();
+        let trapped = Board::from_fen_str("4k3/8/8/8/8/8/1P6/B3K3 w - - 0 1").unwrap();
+
+        assert!(
+            mobility_score(&free, Color::White, &Parameters::DEFAULT).mg
+                > mobility_score(&trapped, Color::White, &Parameters::DEFAULT).mg
+        );
+    }
+
     #[test]
     fn will_eval_the_position() {
         assert_eq!(
@@ -0,0 +1,610 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use common::{king_attacks, pawn_attack_board, BitBoard, Board, Color, Piece};
+
+/// The score given to a position that is a known win. It is well above anything the normal
+/// evaluation will produce but well below a mate score so the search will still prefer to find
+/// the mate.
+pub const KNOWN_WIN: i32 = 10000;
+
+/// The scale factor that leaves the end game score as it is. Scale factors are out of 64 so a
+/// factor of 32 will half the end game score.
+pub const SCALE_NORMAL: i32 = 64;
+
+/// Evaluates a position for the strong side, the color passed in is the strong side and the score
+/// is from its point of view.
+pub type EndgameEval = fn(&Board, Color) -> i32;
+
+/// Scales down the end game score for drawish positions. Returns `None` if it does not apply to
+/// the board.
+pub type EndgameScale = fn(&Board) -> Option<i32>;
+
+/// The pieces counted in a material key in the order they are packed, the king is left out as
+/// there is always one of them.
+const MATERIAL_PIECES: [Piece; 5] = [
+    Piece::Queen,
+    Piece::Rook,
+    Piece::Bishop,
+    Piece::Knight,
+    Piece::Pawn,
+];
+
+/// A registry of the specialised end game evaluators keyed by there material signature, like
+/// `KRvK`. The strong side is always on the left of the signature so each one will match for both
+/// white and black. The signatures are turned into material keys when they are added so probing
+/// is only a couple of popcounts and a lookup.
+pub struct Endgames {
+    evaluators: HashMap<u64, EndgameEval>,
+    scales: Vec<EndgameScale>,
+}
+
+impl Default for Endgames {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Endgames {
+    pub fn new() -> Self {
+        let mut endgames = Self {
+            evaluators: HashMap::new(),
+            scales: Vec::new(),
+        };
+
+        for signature in [
+            "KvK", "KNvK", "KBvK", "KNNvK", "KNvKN", "KBvKN", "KBvKB", "KNvKB",
+        ] {
+            endgames.add(signature, draw);
+        }
+
+        endgames.add("KPvK", kpk);
+        endgames.add("KRvK", kxk);
+        endgames.add("KQvK", kxk);
+        endgames.add("KBNvK", kbnk);
+
+        endgames.add_scale(opposite_colored_bishops);
+
+        endgames
+    }
+
+    /// Adds an evaluator for a signature like `KRvK`, this will panic if the signature is not
+    /// valid.
+    pub fn add(&mut self, signature: &str, evaluator: EndgameEval) {
+        let (strong, weak) = signature
+            .split_once('v')
+            .and_then(|(strong, weak)| Some((signature_key(strong)?, signature_key(weak)?)))
+            .unwrap_or_else(|| panic!("Invalid endgame signature '{signature}'"));
+
+        self.evaluators.insert(endgame_key(strong, weak), evaluator);
+    }
+
+    pub fn add_scale(&mut self, scale: EndgameScale) {
+        self.scales.push(scale);
+    }
+
+    /// Looks up the evaluator for the material on the board, the score is returned from the point
+    /// of view of the side to move.
+    pub fn probe(&self, board: &Board) -> Option<i32> {
+        if is_insufficient_material(board) {
+            return Some(0);
+        }
+
+        let white = material_key(board, Color::White);
+        let black = material_key(board, Color::Black);
+
+        let (evaluator, strong_side) = match self.evaluators.get(&endgame_key(white, black)) {
+            Some(evaluator) => (evaluator, Color::White),
+            None => (
+                self.evaluators.get(&endgame_key(black, white))?,
+                Color::Black,
+            ),
+        };
+
+        let score = evaluator(board, strong_side);
+        Some(if board.turn == strong_side {
+            score
+        } else {
+            -score
+        })
+    }
+
+    /// The scale factor for the end game part of the evaluation, this will be `SCALE_NORMAL`
+    /// unless one of the scales match the board.
+    pub fn scale_factor(&self, board: &Board) -> i32 {
+        self.scales
+            .iter()
+            .find_map(|scale| scale(board))
+            .unwrap_or(SCALE_NORMAL)
+    }
+}
+
+/// The global end game registry, it only gets built the first time its used.
+pub fn endgames() -> &'static Endgames {
+    static ENDGAMES: OnceLock<Endgames> = OnceLock::new();
+    ENDGAMES.get_or_init(Endgames::new)
+}
+
+/// The material a side has packed into a number, each piece type gets four bits for its count in
+/// the order of `MATERIAL_PIECES`.
+pub fn material_key(board: &Board, color: Color) -> u32 {
+    MATERIAL_PIECES
+        .iter()
+        .enumerate()
+        .fold(0, |key, (index, piece)| {
+            let count = board.piece_board(color, *piece).count_ones().min(15);
+            key | count << (index * 4)
+        })
+}
+
+/// The material key for one side of a signature like `KRR`, returns `None` if there is anything
+/// other than piece letters after the king.
+fn signature_key(signature: &str) -> Option<u32> {
+    let mut key = 0;
+    for letter in signature.strip_prefix('K')?.chars() {
+        let index = MATERIAL_PIECES
+            .iter()
+            .position(|piece| piece.to_lower().to_ascii_uppercase() == letter)?;
+
+        key += 1 << (index * 4);
+    }
+
+    Some(key)
+}
+
+/// The key for the material of both sides, the strong side is in the top half.
+fn endgame_key(strong: u32, weak: u32) -> u64 {
+    (strong as u64) << 32 | weak as u64
+}
+
+/// Returns true if neither side has enough material left to ever checkmate. This is a king
+/// against a king and a single minor piece, or bishops that are all on the same colored squares.
+///
+/// See: https://www.chessprogramming.org/Draw_Evaluation
+pub fn is_insufficient_material(board: &Board) -> bool {
+    let heavy = board.white_pawn_board
+        | board.black_pawn_board
+        | board.white_rook_board
+        | board.black_rook_board
+        | board.white_queen_board
+        | board.black_queen_board;
+
+    if heavy != 0 {
+        return false;
+    }
+
+    let knights = board.white_knight_board | board.black_knight_board;
+    let bishops = board.white_bishop_board | board.black_bishop_board;
+
+    match (knights.count_ones(), bishops.count_ones()) {
+        (0, 0) | (1, 0) | (0, 1) => true,
+        (0, _) => bishops & DARK_SQUARES == 0 || bishops & !DARK_SQUARES == 0,
+        _ => false,
+    }
+}
+
+/// All of the dark squares on the board, a1 is a dark square.
+const DARK_SQUARES: BitBoard = 0x55AA_55AA_55AA_55AA;
+
+fn file_of(square: usize) -> i32 {
+    7 - (square % 8) as i32
+}
+
+fn rank_of(square: usize) -> i32 {
+    (square / 8) as i32
+}
+
+fn distance(a: usize, b: usize) -> i32 {
+    (file_of(a) - file_of(b))
+        .abs()
+        .max((rank_of(a) - rank_of(b)).abs())
+}
+
+/// How far a square is from the center of the board, zero for the center squares and six for
+/// the corners.
+fn center_distance(square: usize) -> i32 {
+    let file = file_of(square);
+    let rank = rank_of(square);
+
+    (3 - file).max(file - 4) + (3 - rank).max(rank - 4)
+}
+
+fn square_of(board: BitBoard) -> usize {
+    board.trailing_zeros() as usize
+}
+
+fn draw(_board: &Board, _strong_side: Color) -> i32 {
+    0
+}
+
+/// King and a major piece against a bare king. The mate is simple as long as the weak king is
+/// driven to the edge of the board and the strong king is close enough to help.
+fn kxk(board: &Board, strong_side: Color) -> i32 {
+    let strong_king = square_of(board.piece_board(strong_side, Piece::King));
+    let weak_king = square_of(board.piece_board(strong_side.opposite(), Piece::King));
+
+    let material = board.piece_board(strong_side, Piece::Queen).count_ones() as i32 * 900
+        + board.piece_board(strong_side, Piece::Rook).count_ones() as i32 * 500;
+
+    KNOWN_WIN
+        + material
+        + 20 * center_distance(weak_king)
+        + 10 * (7 - distance(strong_king, weak_king))
+}
+
+/// King, bishop and knight against a bare king. The weak king can only be mated in a corner the
+/// same color as the bishop so it needs to be driven to one of those.
+///
+/// See: https://www.chessprogramming.org/KBNK_Endgame
+fn kbnk(board: &Board, strong_side: Color) -> i32 {
+    let strong_king = square_of(board.piece_board(strong_side, Piece::King));
+    let weak_king = square_of(board.piece_board(strong_side.opposite(), Piece::King));
+    let bishop = board.piece_board(strong_side, Piece::Bishop);
+
+    // The corners are a1 and h8 for a dark squared bishop, h1 and a8 for a light one. In our
+    // square order h1 is zero and a8 is 63.
+    let corners = if bishop & DARK_SQUARES != 0 {
+        [7, 56]
+    } else {
+        [0, 63]
+    };
+
+    let corner_distance = corners
+        .iter()
+        .map(|corner| distance(weak_king, *corner))
+        .min()
+        .unwrap_or(0);
+
+    KNOWN_WIN
+        + 600
+        + 10 * center_distance(weak_king)
+        + 20 * (7 - corner_distance)
+        + 10 * (7 - distance(strong_king, weak_king))
+}
+
+/// King and pawn against a bare king, the result comes from the KPK bitbase. A won position
+/// gets a bonus for how far the pawn has advanced so the engine will push it.
+fn kpk(board: &Board, strong_side: Color) -> i32 {
+    let flip = |square: usize| match strong_side {
+        Color::White => square,
+        Color::Black => square ^ 56,
+    };
+
+    let mut strong_king = flip(square_of(board.piece_board(strong_side, Piece::King)));
+    let mut weak_king = flip(square_of(
+        board.piece_board(strong_side.opposite(), Piece::King),
+    ));
+    let mut pawn = flip(square_of(board.piece_board(strong_side, Piece::Pawn)));
+
+    // The bitbase only has the pawn on the a to d files, the other side of the board is a mirror
+    // image of it.
+    if file_of(pawn) > 3 {
+        strong_king ^= 7;
+        weak_king ^= 7;
+        pawn ^= 7;
+    }
+
+    if !kpk_bitbase().is_win(board.turn == strong_side, strong_king, weak_king, pawn) {
+        return 0;
+    }
+
+    KNOWN_WIN + 100 + 20 * rank_of(pawn)
+}
+
+/// Only the pawns and a single bishop each on different colored squares is very drawish, even a
+/// couple of extra pawns is often not enough to win.
+fn opposite_colored_bishops(board: &Board) -> Option<i32> {
+    let pieces = board.white_knight_board
+        | board.black_knight_board
+        | board.white_rook_board
+        | board.black_rook_board
+        | board.white_queen_board
+        | board.black_queen_board;
+
+    if pieces != 0
+        || board.white_bishop_board.count_ones() != 1
+        || board.black_bishop_board.count_ones() != 1
+    {
+        return None;
+    }
+
+    let white_dark = board.white_bishop_board & DARK_SQUARES != 0;
+    let black_dark = board.black_bishop_board & DARK_SQUARES != 0;
+
+    match white_dark == black_dark {
+        true => None,
+        false => Some(SCALE_NORMAL / 2),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum KpkResult {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+/// A bitbase of all the king and pawn against king positions with white having the pawn on the
+/// a to d files. Each position is a single bit that is set if white wins.
+///
+/// See: https://www.chessprogramming.org/KPK
+pub struct KpkBitbase {
+    bits: Vec<u64>,
+}
+
+const KPK_SIZE: usize = 2 * 64 * 64 * 64;
+
+fn kpk_index(white_to_move: bool, white_king: usize, black_king: usize, pawn: usize) -> usize {
+    (white_to_move as usize) << 18 | white_king << 12 | black_king << 6 | pawn
+}
+
+/// The bitbase is generated the first time its probed. It takes a fraction of a second so its
+/// quicker than loading it from a file.
+pub fn kpk_bitbase() -> &'static KpkBitbase {
+    static BITBASE: OnceLock<KpkBitbase> = OnceLock::new();
+    BITBASE.get_or_init(KpkBitbase::generate)
+}
+
+impl KpkBitbase {
+    pub fn is_win(
+        &self,
+        white_to_move: bool,
+        white_king: usize,
+        black_king: usize,
+        pawn: usize,
+    ) -> bool {
+        let index = kpk_index(white_to_move, white_king, black_king, pawn);
+        self.bits[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    /// Generates the bitbase with retrograde analysis. All the positions are classified where the
+    /// result is obvious, then we keep going over the unknown positions working out the result
+    /// from the positions that can be reached until nothing changes. Any positions left over are
+    /// draws.
+    fn generate() -> Self {
+        let mut results: Vec<KpkResult> = (0..KPK_SIZE).map(Self::classify).collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for index in 0..KPK_SIZE {
+                if results[index] == KpkResult::Unknown {
+                    let result = Self::resolve(&results, index);
+                    if result != KpkResult::Unknown {
+                        results[index] = result;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let mut bits = vec![0; KPK_SIZE / 64];
+        for (index, result) in results.iter().enumerate() {
+            if *result == KpkResult::Win {
+                bits[index / 64] |= 1 << (index % 64);
+            }
+        }
+
+        Self { bits }
+    }
+
+    fn decode(index: usize) -> (bool, usize, usize, usize) {
+        (
+            index >> 18 != 0,
+            index >> 12 & 63,
+            index >> 6 & 63,
+            index & 63,
+        )
+    }
+
+    fn classify(index: usize) -> KpkResult {
+        let (white_to_move, white_king, black_king, pawn) = Self::decode(index);
+
+        if file_of(pawn) > 3 || rank_of(pawn) < 1 || rank_of(pawn) > 6 {
+            return KpkResult::Invalid;
+        }
+
+        let white_king_board: BitBoard = 1 << white_king;
+        let black_king_board: BitBoard = 1 << black_king;
+        let pawn_board: BitBoard = 1 << pawn;
+        let pawn_attacks = pawn_attack_board(pawn_board, &Color::White);
+
+        if white_king == black_king
+            || white_king == pawn
+            || black_king == pawn
+            || distance(white_king, black_king) <= 1
+            || (white_to_move && pawn_attacks & black_king_board != 0)
+        {
+            return KpkResult::Invalid;
+        }
+
+        if white_to_move && rank_of(pawn) == 6 {
+            let queen = pawn + 8;
+            if queen != white_king
+                && queen != black_king
+                && (distance(black_king, queen) > 1 || distance(white_king, queen) == 1)
+            {
+                return KpkResult::Win;
+            }
+        }
+
+        if !white_to_move {
+            let defended = king_attacks(white_king_board) | pawn_attacks;
+            let escapes = king_attacks(black_king_board) & !defended;
+
+            if escapes == 0 {
+                return KpkResult::Draw;
+            }
+
+            if escapes & pawn_board != 0 {
+                return KpkResult::Draw;
+            }
+        }
+
+        KpkResult::Unknown
+    }
+
+    fn resolve(results: &[KpkResult], index: usize) -> KpkResult {
+        let (white_to_move, white_king, black_king, pawn) = Self::decode(index);
+        let mut children = Vec::new();
+
+        if white_to_move {
+            let mut moves = king_attacks(1 << white_king);
+            while moves != 0 {
+                let to = square_of(moves);
+                moves &= moves - 1;
+
+                if to != pawn && distance(to, black_king) > 1 {
+                    children.push(kpk_index(false, to, black_king, pawn));
+                }
+            }
+
+            if rank_of(pawn) < 6 {
+                let push = pawn + 8;
+                if push != white_king && push != black_king {
+                    children.push(kpk_index(false, white_king, black_king, push));
+
+                    let double_push = push + 8;
+                    if rank_of(pawn) == 1 && double_push != white_king && double_push != black_king
+                    {
+                        children.push(kpk_index(false, white_king, black_king, double_push));
+                    }
+                }
+            }
+        } else {
+            let defended =
+                king_attacks(1 << white_king) | pawn_attack_board(1 << pawn, &Color::White);
+            let mut moves = king_attacks(1 << black_king) & !defended;
+            while moves != 0 {
+                let to = square_of(moves);
+                moves &= moves - 1;
+
+                if to != pawn {
+                    children.push(kpk_index(true, white_king, to, pawn));
+                }
+            }
+        }
+
+        // With white to move any winning move wins, with black to move any drawing move draws.
+        let (good, bad) = match white_to_move {
+            true => (KpkResult::Win, KpkResult::Draw),
+            false => (KpkResult::Draw, KpkResult::Win),
+        };
+
+        let mut all_bad = true;
+        for child in children {
+            match results[child] {
+                result if result == good => return good,
+                result if result == bad => {}
+                _ => all_bad = false,
+            }
+        }
+
+        if all_bad {
+            bad
+        } else {
+            KpkResult::Unknown
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(fen: &str) -> Option<i32> {
+        endgames().probe(&Board::from_fen_str(fen).unwrap())
+    }
+
+    #[test]
+    fn builds_the_material_key() {
+        let board = Board::from_start_position().unwrap();
+        assert_eq!(material_key(&board, Color::White), 0x8_2221);
+        assert_eq!(signature_key("KQRRBBNNPPPPPPPP"), Some(0x8_2221));
+
+        let board = Board::from_fen_str("4k3/8/8/8/8/8/8/3RK3 w - - 0 1").unwrap();
+        assert_eq!(
+            Some(material_key(&board, Color::White)),
+            signature_key("KR")
+        );
+        assert_eq!(Some(material_key(&board, Color::Black)), signature_key("K"));
+
+        assert_eq!(signature_key("RK"), None);
+        assert_eq!(signature_key("KX"), None);
+    }
+
+    #[test]
+    fn insufficient_material_is_a_draw() {
+        assert_eq!(probe("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), Some(0));
+        assert_eq!(probe("4k3/8/8/8/8/8/8/3NK3 w - - 0 1"), Some(0));
+        assert_eq!(probe("4kb2/8/8/8/8/8/8/4K3 w - - 0 1"), Some(0));
+        assert_eq!(probe("4kb2/8/8/8/8/8/8/2B1K3 b - - 0 1"), Some(0));
+
+        assert!(is_insufficient_material(
+            &Board::from_fen_str("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1").unwrap()
+        ));
+        assert!(!is_insufficient_material(
+            &Board::from_fen_str("4k1b1/8/8/8/8/8/8/2B1K3 w - - 0 1").unwrap()
+        ));
+    }
+
+    #[test]
+    fn does_not_probe_positions_with_no_evaluator() {
+        assert_eq!(
+            probe("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            None
+        );
+    }
+
+    #[test]
+    fn drives_the_king_to_the_edge_with_a_rook() {
+        let center = probe("8/8/8/4k3/8/8/8/R3K3 w - - 0 1").unwrap();
+        let edge = probe("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+
+        assert!(center > KNOWN_WIN);
+        assert!(edge > center);
+
+        // From the side of the bare king the score is negative
+        assert!(probe("4k3/8/8/8/8/8/8/R3K3 b - - 0 1").unwrap() < -KNOWN_WIN);
+        assert!(probe("r3k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap() < -KNOWN_WIN);
+    }
+
+    #[test]
+    fn drives_the_king_to_the_bishops_corner() {
+        // A dark squared bishop on c1 needs the king in a1 or h8
+        let right_corner = probe("7k/8/8/8/8/8/8/2B1KN2 w - - 0 1").unwrap();
+        let wrong_corner = probe("k7/8/8/8/8/8/8/2B1KN2 w - - 0 1").unwrap();
+
+        assert!(right_corner > wrong_corner);
+        assert!(wrong_corner > KNOWN_WIN);
+    }
+
+    #[test]
+    fn kpk_wins_and_draws() {
+        // King in front of the pawn on the sixth rank wins whoever is to move
+        assert!(probe("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1").unwrap() > KNOWN_WIN);
+        assert!(probe("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").unwrap() < -KNOWN_WIN);
+        assert!(probe("6k1/8/6K1/6P1/8/8/8/8 w - - 0 1").unwrap() > KNOWN_WIN);
+
+        // The same for black
+        assert!(probe("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1").unwrap() > KNOWN_WIN);
+        assert!(probe("8/8/8/8/4p3/4k3/8/4K3 w - - 0 1").unwrap() < -KNOWN_WIN);
+
+        // Stalemate tricks and the rook pawn are draws
+        assert_eq!(probe("8/8/8/4k3/8/8/4P3/4K3 w - - 0 1"), Some(0));
+        assert_eq!(probe("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"), Some(0));
+        assert_eq!(probe("8/8/8/8/8/4k3/4p3/4K3 w - - 0 1"), Some(0));
+        assert_eq!(probe("7k/8/8/8/8/8/7P/7K w - - 0 1"), Some(0));
+    }
+
+    #[test]
+    fn scales_opposite_colored_bishops() {
+        let board = Board::from_fen_str("4kb2/pp6/8/8/8/8/PPP5/3BK3 w - - 0 1").unwrap();
+        assert_eq!(endgames().scale_factor(&board), SCALE_NORMAL / 2);
+
+        let board = Board::from_fen_str("4k1b1/pp6/8/8/8/8/PPP5/3BK3 w - - 0 1").unwrap();
+        assert_eq!(endgames().scale_factor(&board), SCALE_NORMAL);
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt;
+
+use common::{Board, Color};
+
+use super::{evaluate_with, phase, Score, Term, Tracer};
+
+/// Records every term of the evaluation for both sides so it can be printed out as a table. This
+/// is what backs the `eval` command so we can see why the engine likes or dislikes a position.
+///
+/// ```
+/// use common::Board;
+/// use engine::evaluation::trace::EvalTrace;
+///
+/// let trace = EvalTrace::new(&Board::from_start_position().unwrap());
+/// assert_eq!(trace.eval, 0);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct EvalTrace {
+    pub white: [Score; Term::ALL.len()],
+    pub black: [Score; Term::ALL.len()],
+    pub phase: i32,
+    pub turn: Color,
+    /// The final evaluation from the point of view of the side to move
+    pub eval: i32,
+}
+
+impl Tracer for EvalTrace {
+    fn record(&mut self, term: Term, color: Color, score: Score) {
+        match color {
+            Color::White => self.white[term as usize] += score,
+            Color::Black => self.black[term as usize] += score,
+        }
+    }
+}
+
+impl EvalTrace {
+    pub fn new(board: &Board) -> Self {
+        let mut trace = Self {
+            phase: phase(board),
+            turn: board.turn,
+            ..Default::default()
+        };
+
+        trace.eval = evaluate_with(board, &mut trace);
+        trace
+    }
+
+    /// The score of a single term from whites point of view
+    pub fn total(&self, term: Term) -> Score {
+        self.white[term as usize] - self.black[term as usize]
+    }
+}
+
+/// Formats a centipawn score as pawns so it lines up in the table.
+fn pawns(value: i32) -> String {
+    format!("{:.2}", value as f64 / 100.0)
+}
+
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "        Term |    White    |    Black    |    Total")?;
+        writeln!(f, "             |   MG    EG  |   MG    EG  |   MG    EG")?;
+        writeln!(f, " ------------+-------------+-------------+------------")?;
+
+        for term in Term::ALL {
+            let white = self.white[term as usize];
+            let black = self.black[term as usize];
+            let total = self.total(term);
+
+            writeln!(
+                f,
+                " {:>11} | {:>5} {:>5} | {:>5} {:>5} | {:>5} {:>5}",
+                term.name(),
+                pawns(white.mg),
+                pawns(white.eg),
+                pawns(black.mg),
+                pawns(black.eg),
+                pawns(total.mg),
+                pawns(total.eg),
+            )?;
+        }
+
+        writeln!(f, " ------------+-------------+-------------+------------")?;
+
+        let white_eval = match self.turn {
+            Color::White => self.eval,
+            Color::Black => -self.eval,
+        };
+
+        writeln!(f)?;
+        writeln!(f, "Phase: {}/24", self.phase)?;
+        write!(f, "Final evaluation: {} (white side)", pawns(white_eval))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_terms_add_up_to_the_evaluation() {
+        let board = Board::from_fen_str(
+            "r1b1qrk1/pp2bpp1/3pnn1p/2p1p3/2P5/1QNPP1P1/PP1N1PBP/R1B2RK1 b - - 6 12",
+        )
+        .unwrap();
+        let trace = EvalTrace::new(&board);
+
+        let mut total = Score::default();
+        for term in Term::ALL {
+            total += trace.total(term);
+        }
+
+        assert_eq!(trace.eval, -total.taper(trace.phase));
+        assert_eq!(trace.eval, super::super::evaluate(&board));
+    }
+
+    #[test]
+    fn records_the_material_for_each_side() {
+        let board = Board::from_fen_str("4k3/8/8/8/8/8/3PP3/3QK3 w - - 0 1").unwrap();
+        let trace = EvalTrace::new(&board);
+
+        assert_eq!(trace.white[Term::Material as usize], Score::new(1100, 1100));
+        assert_eq!(trace.black[Term::Material as usize], Score::default());
+    }
+
+    #[test]
+    fn records_the_endgame_evaluator_score() {
+        let board = Board::from_fen_str("8/8/8/4k3/8/8/8/R3K3 b - - 0 1").unwrap();
+        let trace = EvalTrace::new(&board);
+
+        // The rook ending is scored by its own evaluator, so that is the only term
+        for term in Term::ALL {
+            if term != Term::Endgame {
+                assert_eq!(trace.total(term), Score::default());
+            }
+        }
+
+        assert!(trace.eval < 0);
+        assert_eq!(trace.total(Term::Endgame).mg, -trace.eval);
+        assert!(format!("{trace}").contains("Endgame"));
+    }
+
+    #[test]
+    fn prints_a_row_for_each_term() {
+        let trace = EvalTrace::new(&Board::from_start_position().unwrap());
+        let output = format!("{trace}");
+
+        for term in Term::ALL {
+            assert!(output.contains(term.name()));
+        }
+
+        assert!(output.contains("Final evaluation: 0.00 (white side)"));
+    }
+}
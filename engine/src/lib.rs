@@ -1,6 +1,9 @@
 pub mod evaluation;
 pub mod move_sort;
+pub mod nnue;
 pub mod search;
+pub mod search_info;
 pub mod transposition_table;
 pub mod uci;
 pub mod uci_command;
+pub mod xboard;
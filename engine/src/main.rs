@@ -1,18 +1,48 @@
 mod evaluation;
 mod move_sort;
+mod nnue;
 mod search;
+mod search_info;
 mod transposition_table;
 mod uci;
 mod uci_command;
+mod xboard;
+
+fn read_line() -> Option<String> {
+    let mut line = String::new();
+    match std::io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line),
+    }
+}
 
 fn main() {
     let mut writer = uci::UciOutputWriter::new();
-    let mut uci = uci::Uci::new();
 
-    loop {
-        let mut line = String::new();
-        std::io::stdin().read_line(&mut line).unwrap();
+    // The protocol is picked from the first command, xboard GUIs always start with `xboard`
+    let Some(first) = read_line() else {
+        return;
+    };
+
+    if first.trim() == "xboard" {
+        let mut xboard = xboard::XBoard::new();
+        let mut line = Some(first);
+        while let Some(input) = line {
+            xboard.handle(&input, &mut writer);
+            line = read_line();
+        }
 
-        uci.handle(&line, &mut writer);
+        xboard.wait();
+        return;
     }
+
+    let mut uci = uci::Uci::new();
+    let mut line = Some(first);
+    while let Some(input) = line {
+        uci.handle(&input, &mut writer);
+        line = read_line();
+    }
+
+    // The input can end while the search is still running, we still want to see the best move
+    uci.wait();
 }
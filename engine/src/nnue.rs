@@ -0,0 +1,492 @@
+//! An efficiently updatable neural network evaluation.
+//!
+//! The network is a single hidden layer with a HalfKA style input. Every piece on the board,
+//! including the kings, is a feature relative to the king of the side we are looking from. There
+//! is an accumulator for each side that holds the output of the hidden layer, as only a couple of
+//! pieces change with each move we can add and remove the changed features rather than working
+//! out the whole layer again. The only time we need to start from scratch is when a king moves,
+//! because every feature for that side depends on where its king is.
+//!
+//! See: https://www.chessprogramming.org/NNUE
+
+use common::{Board, Color, Piece};
+
+use crate::evaluation::endgame::KNOWN_WIN;
+
+/// The number of neurons in the hidden layer for each side.
+pub const HIDDEN: usize = 256;
+
+/// King square, piece color relative to the side, piece type and piece square.
+pub const FEATURES: usize = 64 * 2 * 6 * 64;
+
+/// The quantisation of the hidden layer, this is also the top of the clipped relu.
+const QA: i32 = 255;
+
+/// The quantisation of the output layer.
+const QB: i32 = 64;
+
+/// Converts the output of the network into centipawns.
+const SCALE: i32 = 400;
+
+const MAGIC: &[u8; 8] = b"CEIRNNUE";
+const VERSION: u32 = 1;
+
+const PIECES: [Piece; 6] = [
+    Piece::King,
+    Piece::Queen,
+    Piece::Rook,
+    Piece::Bishop,
+    Piece::Knight,
+    Piece::Pawn,
+];
+
+/// The output of the hidden layer for both sides of the board, indexed by color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Accumulator {
+    values: [[i16; HIDDEN]; 2],
+}
+
+pub struct Network {
+    feature_weights: Vec<i16>,
+    feature_bias: [i16; HIDDEN],
+    output_weights: [i16; HIDDEN * 2],
+    output_bias: i32,
+}
+
+/// Reads the little endian values out of the network file, keeping track of where we are so we
+/// can say when the file is too short.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.position + count;
+        if end > self.bytes.len() {
+            return Err(format!(
+                "Unexpected end of the network file at byte {}",
+                self.bytes.len()
+            ));
+        }
+
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i16s(&mut self, output: &mut [i16]) -> Result<(), String> {
+        let bytes = self.take(output.len() * 2)?;
+        for (value, chunk) in output.iter_mut().zip(bytes.chunks_exact(2)) {
+            *value = i16::from_le_bytes([chunk[0], chunk[1]]);
+        }
+
+        Ok(())
+    }
+}
+
+fn color_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+fn king_square(board: &Board, color: Color) -> usize {
+    board.piece_board(color, Piece::King).trailing_zeros() as usize & 63
+}
+
+/// The index of a feature as seen from one side of the board. Black sees the board flipped so
+/// both sides share the same weights.
+fn feature_index(
+    perspective: Color,
+    king: usize,
+    color: Color,
+    piece: Piece,
+    square: usize,
+) -> usize {
+    let (king, square) = match perspective {
+        Color::White => (king, square),
+        Color::Black => (king ^ 56, square ^ 56),
+    };
+
+    let relative = if color == perspective { 0 } else { 1 };
+
+    ((king * 2 + relative) * 6 + piece as usize) * 64 + square
+}
+
+fn squares(mut board: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if board == 0 {
+            return None;
+        }
+
+        let square = board.trailing_zeros() as usize;
+        board &= board - 1;
+
+        Some(square)
+    })
+}
+
+/// The sum wraps the same way as the SIMD lanes do, so a network with extreme weights gives the
+/// same result with or without AVX2 rather than panicking.
+fn dot_scalar(values: &[i16; HIDDEN], weights: &[i16]) -> i32 {
+    values
+        .iter()
+        .zip(weights)
+        .map(|(&value, &weight)| (value as i32).clamp(0, QA) * weight as i32)
+        .fold(0, i32::wrapping_add)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn dot_avx2(values: &[i16; HIDDEN], weights: &[i16]) -> i32 {
+    use std::arch::x86_64::*;
+
+    let zero = _mm256_setzero_si256();
+    let max = _mm256_set1_epi16(QA as i16);
+    let mut sum = _mm256_setzero_si256();
+
+    for i in (0..HIDDEN).step_by(16) {
+        let value = _mm256_loadu_si256(values.as_ptr().add(i) as *const __m256i);
+        let weight = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+        let clipped = _mm256_min_epi16(_mm256_max_epi16(value, zero), max);
+        sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, weight));
+    }
+
+    let mut lanes = [0i32; 8];
+    _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+    lanes.iter().sum()
+}
+
+/// The clipped relu of the accumulator multiplied by the output weights. This is the hot part of
+/// the inference so we use AVX2 when the CPU has it.
+fn dot(values: &[i16; HIDDEN], weights: &[i16]) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: We have just checked the CPU supports AVX2 and both slices hold at least
+        // HIDDEN values.
+        return unsafe { dot_avx2(values, weights) };
+    }
+
+    dot_scalar(values, weights)
+}
+
+impl Network {
+    /// Loads a network from a file in the format written by the trainer. The file starts with
+    /// the magic bytes, the version and the size of the hidden layer, then all of the weights as
+    /// little endian integers.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes, position: 0 };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err("This is not a network file".to_string());
+        }
+
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(format!("Unsupported network version {version}"));
+        }
+
+        let hidden = reader.u32()? as usize;
+        if hidden != HIDDEN {
+            return Err(format!(
+                "The network has a hidden layer of {hidden} but the engine expects {HIDDEN}"
+            ));
+        }
+
+        let mut network = Network {
+            feature_weights: vec![0; FEATURES * HIDDEN],
+            feature_bias: [0; HIDDEN],
+            output_weights: [0; HIDDEN * 2],
+            output_bias: 0,
+        };
+
+        reader.i16s(&mut network.feature_weights)?;
+        reader.i16s(&mut network.feature_bias)?;
+        reader.i16s(&mut network.output_weights)?;
+        network.output_bias = reader.i32()?;
+
+        if reader.position != bytes.len() {
+            return Err("Unexpected data at the end of the network file".to_string());
+        }
+
+        Ok(network)
+    }
+
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * HIDDEN..(feature + 1) * HIDDEN]
+    }
+
+    /// The accumulator wraps rather than overflowing, the network file is not trusted and adding
+    /// and removing features still cancels out exactly when it wraps.
+    fn add_feature(&self, values: &mut [i16; HIDDEN], feature: usize) {
+        for (value, weight) in values.iter_mut().zip(self.weights(feature)) {
+            *value = value.wrapping_add(*weight);
+        }
+    }
+
+    fn remove_feature(&self, values: &mut [i16; HIDDEN], feature: usize) {
+        for (value, weight) in values.iter_mut().zip(self.weights(feature)) {
+            *value = value.wrapping_sub(*weight);
+        }
+    }
+
+    fn refresh_side(&self, values: &mut [i16; HIDDEN], board: &Board, perspective: Color) {
+        *values = self.feature_bias;
+
+        let king = king_square(board, perspective);
+        for color in [Color::White, Color::Black] {
+            for piece in PIECES {
+                for square in squares(board.piece_board(color, piece)) {
+                    let feature = feature_index(perspective, king, color, piece, square);
+                    self.add_feature(values, feature);
+                }
+            }
+        }
+    }
+
+    /// Builds the accumulator for a board from scratch.
+    pub fn refresh(&self, board: &Board) -> Accumulator {
+        let mut accumulator = Accumulator {
+            values: [[0; HIDDEN]; 2],
+        };
+
+        for perspective in [Color::White, Color::Black] {
+            let values = &mut accumulator.values[color_index(perspective)];
+            self.refresh_side(values, board, perspective);
+        }
+
+        accumulator
+    }
+
+    /// Updates the accumulator after a move has been made. Rather than decoding the move we look
+    /// at which pieces have changed between the two boards, that way captures, castling, en
+    /// passant and promotions are all handled the same way.
+    pub fn update(&self, accumulator: &mut Accumulator, before: &Board, after: &Board) {
+        for perspective in [Color::White, Color::Black] {
+            let values = &mut accumulator.values[color_index(perspective)];
+
+            let king = king_square(after, perspective);
+            if king != king_square(before, perspective) {
+                self.refresh_side(values, after, perspective);
+                continue;
+            }
+
+            for color in [Color::White, Color::Black] {
+                for piece in PIECES {
+                    let old = before.piece_board(color, piece);
+                    let new = after.piece_board(color, piece);
+                    if old == new {
+                        continue;
+                    }
+
+                    for square in squares(old & !new) {
+                        let feature = feature_index(perspective, king, color, piece, square);
+                        self.remove_feature(values, feature);
+                    }
+
+                    for square in squares(new & !old) {
+                        let feature = feature_index(perspective, king, color, piece, square);
+                        self.add_feature(values, feature);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs the output layer, returning the evaluation in centipawns from the point of view of
+    /// the side to move. The score is kept within a known win so a bad network can't look like a
+    /// mate to the search.
+    pub fn evaluate(&self, accumulator: &Accumulator, turn: Color) -> i32 {
+        let us = &accumulator.values[color_index(turn)];
+        let them = &accumulator.values[color_index(turn.opposite())];
+
+        let output = self.output_bias as i64
+            + dot(us, &self.output_weights[..HIDDEN]) as i64
+            + dot(them, &self.output_weights[HIDDEN..]) as i64;
+
+        let score = output * SCALE as i64 / (QA * QB) as i64;
+        score.clamp(-KNOWN_WIN as i64, KNOWN_WIN as i64) as i32
+    }
+
+    /// Evaluates a board without an existing accumulator.
+    pub fn evaluate_board(&self, board: &Board) -> i32 {
+        self.evaluate(&self.refresh(board), board.turn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{attacked_squares, castle_moves, pseudo_moves};
+
+    /// A small xorshift generator so the test networks are the same every run.
+    fn random_values(seed: u64, count: usize, range: i16) -> Vec<i16> {
+        let mut state = seed;
+        (0..count)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state % (range as u64 * 2 + 1)) as i16 - range
+            })
+            .collect()
+    }
+
+    fn test_network() -> Network {
+        Network {
+            feature_weights: random_values(1, FEATURES * HIDDEN, 32),
+            feature_bias: random_values(2, HIDDEN, 32).try_into().unwrap(),
+            output_weights: random_values(3, HIDDEN * 2, 64).try_into().unwrap(),
+            output_bias: 17,
+        }
+    }
+
+    fn to_bytes(network: &Network) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend((HIDDEN as u32).to_le_bytes());
+
+        let weights = network
+            .feature_weights
+            .iter()
+            .chain(network.feature_bias.iter())
+            .chain(network.output_weights.iter());
+
+        for weight in weights {
+            bytes.extend(weight.to_le_bytes());
+        }
+
+        bytes.extend(network.output_bias.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn incremental_updates_match_a_refresh() {
+        let network = test_network();
+
+        // Positions with castling, en passant and promotions available
+        let fens = [
+            "r3k2r/1P6/8/3pP3/8/8/6p1/R3K2R w KQkq d6 0 1",
+            "r3k2r/1P6/8/8/3Pp3/8/6p1/R3K2R b KQkq d3 0 1",
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+        ];
+
+        for fen in fens {
+            let mut board = Board::from_fen_str(fen).unwrap();
+            let mut accumulator = network.refresh(&board);
+
+            for ply in 0..12 {
+                let moves: Vec<_> = [
+                    pseudo_moves(&board),
+                    castle_moves(&board, &attacked_squares(&board, &board.turn)),
+                ]
+                .concat()
+                .into_iter()
+                .filter(|movement| movement.capture != Some(Piece::King))
+                .collect();
+
+                for movement in &moves {
+                    let mut new_board = board;
+                    new_board.move_piece(*movement);
+
+                    let mut new_accumulator = accumulator;
+                    network.update(&mut new_accumulator, &board, &new_board);
+
+                    assert_eq!(new_accumulator, network.refresh(&new_board), "{movement:?}");
+                }
+
+                let movement = moves[(ply * 7) % moves.len()];
+                let mut new_board = board;
+                new_board.move_piece(movement);
+                network.update(&mut accumulator, &board, &new_board);
+                board = new_board;
+            }
+        }
+    }
+
+    #[test]
+    fn extreme_weights_do_not_overflow() {
+        let mut network = test_network();
+        network.feature_weights.fill(i16::MAX);
+        network.feature_bias = [i16::MIN; HIDDEN];
+        network.output_weights = [i16::MAX; HIDDEN * 2];
+        network.output_bias = i32::MAX;
+
+        let mut board = Board::from_start_position().unwrap();
+        let mut accumulator = network.refresh(&board);
+        assert_eq!(network.evaluate(&accumulator, board.turn), KNOWN_WIN);
+
+        let movement = common::legal_moves(&board)[0];
+        let mut new_board = board;
+        new_board.move_piece(movement);
+        network.update(&mut accumulator, &board, &new_board);
+        board = new_board;
+
+        assert_eq!(accumulator, network.refresh(&board));
+
+        let values = [QA as i16; HIDDEN];
+        let weights = [i16::MAX; HIDDEN];
+        assert_eq!(dot(&values, &weights), dot_scalar(&values, &weights));
+    }
+
+    #[test]
+    fn simd_matches_the_scalar_fallback() {
+        let values: [i16; HIDDEN] = random_values(4, HIDDEN, 400).try_into().unwrap();
+        let weights = random_values(5, HIDDEN, 128);
+
+        assert_eq!(dot(&values, &weights), dot_scalar(&values, &weights));
+    }
+
+    #[test]
+    fn loads_a_network_from_bytes() {
+        let network = test_network();
+        let loaded = Network::from_bytes(&to_bytes(&network)).unwrap();
+
+        let board =
+            Board::from_fen_str("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")
+                .unwrap();
+
+        assert_eq!(
+            loaded.evaluate_board(&board),
+            network.evaluate_board(&board)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_network_files() {
+        let bytes = to_bytes(&test_network());
+
+        assert!(Network::from_bytes(b"NOTANNUE").is_err());
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Network::from_bytes(&[bytes.as_slice(), &[0]].concat()).is_err());
+    }
+
+    #[test]
+    fn evaluation_is_from_the_side_to_move() {
+        let network = test_network();
+
+        let white = Board::from_fen_str("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        let black = Board::from_fen_str("4k3/4p3/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+
+        assert_eq!(
+            network.evaluate_board(&white),
+            network.evaluate_board(&black)
+        );
+    }
+}
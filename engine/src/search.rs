@@ -1,17 +1,84 @@
+use crate::nnue::{Accumulator, Network};
+use crate::search_info::{Score, SearchInfo};
 use crate::transposition_table::{Bound, TTEntry, TranspositionTable};
 use crate::{evaluation, move_sort, uci::UciWriter};
-use common::{attacked_squares, castle_moves, pseudo_moves, Board, Color, ResolvedMovement};
+use common::{
+    attacked_squares, castle_moves, is_in_check, legal_moves, pseudo_moves, Board, Color,
+    ResolvedMovement,
+};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 const MAX_POSITIVE: i32 = 500000;
 const MAX_NEGATIVE: i32 = -500000;
-const MATE_SCORE: i32 = 400000;
+pub const MATE_SCORE: i32 = 400000;
+
+/// Scores further from zero than this are mates, the difference from the mate score is the number
+/// of plies to the mate.
+pub const MATE_THRESHOLD: i32 = MATE_SCORE - 1000;
+
+/// How long the search runs before we start sending updates in the middle of a depth.
+const UPDATE_AFTER: Duration = Duration::from_secs(1);
+
+/// One of the lines found by the search, the score is from the point of view of the side to move
+/// at the root.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchLine {
+    pub score: i32,
+    pub pv: Vec<ResolvedMovement>,
+}
+
+/// Mate scores are stored in the transposition table as the plies from the position rather than
+/// from the root, so they are still right when the position is found at a different ply.
+fn to_tt_score(score: i32, ply: i32) -> i32 {
+    match score {
+        score if score > MATE_THRESHOLD => score + ply,
+        score if score < -MATE_THRESHOLD => score - ply,
+        score => score,
+    }
+}
+
+/// Converts a score from the transposition table back to the plies from the root.
+fn from_tt_score(score: i32, ply: i32) -> i32 {
+    match score {
+        score if score > MATE_THRESHOLD => score - ply,
+        score if score < -MATE_THRESHOLD => score + ply,
+        score => score,
+    }
+}
 
 pub struct Search<'a, T: UciWriter + ?Sized> {
     pub writer: &'a mut T,
     start_pos: Board,
     max_depth: usize,
     nodes: i32,
+    /// Stop searching after this many nodes, used when we want quick searches of the same size
+    /// no matter how fast the machine is. Like the time limit the first depth is always finished
+    node_limit: Option<i32>,
+    /// Stop searching once this much time has passed, the first depth is always finished so
+    /// there is a move to play
+    time_limit: Option<Duration>,
+    /// Set from another thread to stop the search, this is `stop` in UCI
+    stop: Option<&'a AtomicBool>,
+    started: Instant,
+    /// When we last sent an update in the middle of a depth
+    last_update: Instant,
+    /// If we are writing out the progress of the search
+    report: bool,
+    /// The deepest ply reached in the current depth
+    seldepth: usize,
+    /// The last depth that was searched all the way through
+    depth: usize,
+    /// The moves to search at the root, all of them when this is empty
+    search_moves: Vec<ResolvedMovement>,
+    /// The number of lines to find
+    multi_pv: usize,
+    stopped: bool,
     transposition_table: &'a mut TranspositionTable,
+    network: Option<&'a Network>,
+    /// The accumulators for each of the boards in the current line when searching with a network
+    accumulators: Vec<Accumulator>,
 }
 
 impl<'a, T: UciWriter + ?Sized> Search<'a, T> {
@@ -26,65 +93,310 @@ impl<'a, T: UciWriter + ?Sized> Search<'a, T> {
             start_pos,
             max_depth,
             nodes: 0,
+            node_limit: None,
+            time_limit: None,
+            stop: None,
+            started: Instant::now(),
+            last_update: Instant::now(),
+            report: false,
+            seldepth: 0,
+            depth: 0,
+            search_moves: Vec::new(),
+            multi_pv: 1,
+            stopped: false,
             transposition_table,
+            network: None,
+            accumulators: Vec::new(),
         }
     }
 
+    /// Evaluates the positions with a neural network rather than the handcrafted evaluation.
+    pub fn with_network(mut self, network: &'a Network) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Stops the search once it has looked at this many nodes.
+    pub fn with_node_limit(mut self, nodes: i32) -> Self {
+        self.node_limit = Some(nodes);
+        self
+    }
+
+    /// Stops the search once it has been running for this long.
+    pub fn with_time_limit(mut self, time: Duration) -> Self {
+        self.time_limit = Some(time);
+        self
+    }
+
+    /// Stops the search once the flag is set, the first depth is still finished.
+    pub fn with_stop(mut self, stop: &'a AtomicBool) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// The number of nodes searched so far.
+    pub fn nodes(&self) -> i32 {
+        self.nodes
+    }
+
+    /// The last depth that was searched all the way through.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Only search these moves at the root, this is `searchmoves` in UCI.
+    pub fn with_search_moves(mut self, moves: Vec<ResolvedMovement>) -> Self {
+        self.search_moves = moves;
+        self
+    }
+
+    /// Find the best this many lines rather than just the best one.
+    pub fn with_multi_pv(mut self, lines: usize) -> Self {
+        self.multi_pv = lines.max(1);
+        self
+    }
+
+    /// Searches one depth at a time until the max depth or the node limit is reached, returning
+    /// the best lines from the last depth that finished, best first. Nothing is written out so
+    /// this can be used when the engine is playing itself rather than talking to a GUI.
+    pub fn lines(&mut self) -> Vec<SearchLine> {
+        let board = self.start_pos;
+        if let Some(network) = self.network {
+            self.accumulators.push(network.refresh(&board));
+        }
+
+        let mut root_moves: Vec<ResolvedMovement> = legal_moves(&board)
+            .into_iter()
+            .filter(|movement| self.search_moves.is_empty() || self.search_moves.contains(movement))
+            .collect();
+        root_moves.sort_by_key(move_sort::sort_key);
+
+        // Always search at least one depth, even for `go depth 0`, so there is a move to play
+        let mut lines = Vec::new();
+        for depth in 1..=self.max_depth.max(1) {
+            let mut depth_lines: Vec<SearchLine> = Vec::new();
+            self.seldepth = 0;
+
+            // Each line is searched with the moves from the better lines left out
+            while depth_lines.len() < self.multi_pv.min(root_moves.len()) {
+                let Some((movement, score)) =
+                    self.search_root(&board, &root_moves, &depth_lines, depth)
+                else {
+                    break;
+                };
+
+                depth_lines.push(SearchLine {
+                    score,
+                    pv: self.pv(&board, movement),
+                });
+            }
+
+            if self.stopped {
+                break;
+            }
+
+            self.depth = depth;
+            lines = depth_lines;
+
+            if self.report {
+                for (index, line) in lines.iter().enumerate() {
+                    let info = self.line_info(index, line, Bound::Exact);
+                    self.writer.info(&info);
+                }
+            }
+
+            // Search the best moves first next time round
+            for (index, line) in lines.iter().enumerate() {
+                if let Some(position) = root_moves.iter().position(|m| *m == line.pv[0]) {
+                    root_moves.remove(position);
+                    root_moves.insert(index, line.pv[0]);
+                }
+            }
+
+            if let Some(best) = lines.first() {
+                self.transposition_table.store(
+                    board.hash(),
+                    TTEntry {
+                        seen: 1,
+                        depth,
+                        value: best.score,
+                        movement: Some(best.pv[0]),
+                        bound: Bound::Exact,
+                    },
+                );
+            }
+        }
+
+        lines
+    }
+
+    /// The best move and its score, see [`Search::lines`].
+    pub fn best_move(&mut self) -> Option<(ResolvedMovement, i32)> {
+        self.lines().first().map(|line| (line.pv[0], line.score))
+    }
+
+    /// Searches and writes out the progress as it goes, the lines after each depth and then the
+    /// best move.
     pub fn search(&mut self) {
-        let mut board = self.start_pos;
-        self.nega_max(
-            &mut board,
-            &Vec::new(),
-            self.max_depth,
-            MAX_NEGATIVE,
-            MAX_POSITIVE,
-        );
-
-        self.writer.writeln(
-            &self
-                .transposition_table
-                .uci_info(&board, self.nodes)
-                .unwrap(),
-        );
-
-        self.writer
-            .writeln(&self.transposition_table.uci_best_move(&board).unwrap());
+        self.report = true;
+        let lines = self.lines();
+
+        match lines.first() {
+            Some(line) => self
+                .writer
+                .writeln(&format!("bestmove {}", line.pv[0].uci())),
+            None => self.writer.writeln("bestmove (none)"),
+        }
+    }
+
+    /// The principal variation starting with a root move.
+    fn pv(&self, board: &Board, movement: ResolvedMovement) -> Vec<ResolvedMovement> {
+        let mut after = *board;
+        after.move_piece(movement);
+
+        let mut pv = vec![movement];
+        pv.extend(self.transposition_table.get_pv(&after));
+        pv
+    }
+
+    /// The info for where the search is up to, without a line.
+    fn progress_info(&self) -> SearchInfo {
+        SearchInfo {
+            depth: Some(self.depth + 1),
+            seldepth: Some(self.seldepth),
+            nodes: Some(self.nodes),
+            time: Some(self.started.elapsed()),
+            hashfull: Some(self.transposition_table.hashfull()),
+            ..Default::default()
+        }
+    }
+
+    fn line_info(&self, index: usize, line: &SearchLine, bound: Bound) -> SearchInfo {
+        SearchInfo {
+            depth: Some(self.depth),
+            multi_pv: Some(index + 1).filter(|_| self.multi_pv > 1),
+            score: Some(Score::from_search(line.score)),
+            bound,
+            pv: line.pv.clone(),
+            ..self.progress_info()
+        }
+    }
+
+    /// If it is worth telling the GUI what is going on in the middle of a depth, we only do this
+    /// once the search has been going for a while so fast searches are not flooded with output.
+    fn should_update(&self) -> bool {
+        self.report && self.started.elapsed() >= UPDATE_AFTER
+    }
+
+    /// Finds the best of the root moves that are not already in one of the lines. This does not
+    /// use the transposition table for the root position so the moves that are left out are
+    /// never played.
+    fn search_root(
+        &mut self,
+        board: &Board,
+        root_moves: &[ResolvedMovement],
+        lines: &[SearchLine],
+        depth: usize,
+    ) -> Option<(ResolvedMovement, i32)> {
+        let mut alpha = MAX_NEGATIVE;
+        let mut best = None;
+
+        for (index, movement) in root_moves.iter().enumerate() {
+            if lines.iter().any(|line| line.pv[0] == *movement) {
+                continue;
+            }
+
+            if self.should_update() {
+                let info = SearchInfo {
+                    depth: Some(depth),
+                    current_move: Some((*movement, index + 1)),
+                    ..Default::default()
+                };
+                self.writer.info(&info);
+            }
+
+            let mut new_board = *board;
+            new_board.move_piece(*movement);
+            self.push_accumulator(board, &new_board);
+            let score = -self.nega_max(
+                &mut new_board,
+                &[*movement],
+                depth - 1,
+                -MAX_POSITIVE,
+                -alpha,
+            );
+            self.pop_accumulator();
+
+            if self.stopped {
+                return None;
+            }
+
+            if score > alpha || best.is_none() {
+                // The other moves have not been searched yet so this is only a lower bound of
+                // the score for this depth
+                if best.is_some() && self.should_update() {
+                    let line = SearchLine {
+                        score,
+                        pv: self.pv(board, *movement),
+                    };
+                    let info = SearchInfo {
+                        depth: Some(depth),
+                        ..self.line_info(lines.len(), &line, Bound::LowerBound)
+                    };
+                    self.writer.info(&info);
+                }
+
+                alpha = alpha.max(score);
+                best = Some((*movement, score));
+            }
+        }
+
+        best
     }
 
     fn nega_max(
         &mut self,
         board: &mut Board,
-        line: &Vec<ResolvedMovement>,
+        line: &[ResolvedMovement],
         depth: usize,
         mut alpha: i32,
         mut beta: i32,
     ) -> i32 {
+        if self.out_of_nodes() {
+            return 0;
+        }
+
         self.nodes += 1;
+        self.seldepth = self.seldepth.max(line.len());
 
-        if let Some(entry) = self.transposition_table.retrieve(board.hash().unwrap()) {
+        let ply = line.len() as i32;
+        if let Some(entry) = self.transposition_table.retrieve(board.hash()) {
             if entry.depth >= depth {
+                let value = from_tt_score(entry.value, ply);
                 match entry.bound {
-                    Bound::Exact => return entry.value,
-                    Bound::LowerBound => alpha = alpha.max(entry.value),
-                    Bound::UpperBound => beta = beta.min(entry.value),
+                    Bound::Exact => return value,
+                    Bound::LowerBound => alpha = alpha.max(value),
+                    Bound::UpperBound => beta = beta.min(value),
                 }
 
                 if alpha >= beta {
-                    return entry.value;
+                    return value;
                 }
             }
         }
 
         if depth == 0 {
-            return self.quiesce(board, alpha, beta);
+            return self.quiesce(board, line.len(), alpha, beta);
         }
 
         let mut moved = false;
-        let mut moves = [
-            pseudo_moves(board),
-            castle_moves(board, &attacked_squares(board, &board.turn)),
-        ]
-        .concat();
+        let mut moves = pseudo_moves(board);
+
+        // The same rules as `legal_moves`, we can't castle out of or through check
+        let attackers = attacked_squares(board, &board.turn.opposite());
+        if !is_in_check(board, &attackers) {
+            moves.extend(castle_moves(board, &attackers));
+        }
 
         let mut best_value = i32::MIN;
         let mut best_move = None;
@@ -94,6 +406,7 @@ impl<'a, T: UciWriter + ?Sized> Search<'a, T> {
         for movement in &moves {
             let mut new_board = *board;
             new_board.move_piece(*movement);
+            self.push_accumulator(board, &new_board);
 
             let attackers = attacked_squares(&new_board, &new_board.turn);
             let king = match new_board.turn {
@@ -103,15 +416,21 @@ impl<'a, T: UciWriter + ?Sized> Search<'a, T> {
 
             // We are in check and its not a valid move
             if attackers & king != 0 {
+                self.pop_accumulator();
                 continue;
             }
 
             moved = true;
 
-            let mut new_line = line.clone();
+            let mut new_line = line.to_vec();
             new_line.push(*movement);
 
             let score = -self.nega_max(&mut new_board, &new_line, depth - 1, -beta, -alpha);
+            self.pop_accumulator();
+
+            if self.stopped {
+                return 0;
+            }
 
             if score > best_value {
                 best_value = score;
@@ -125,12 +444,13 @@ impl<'a, T: UciWriter + ?Sized> Search<'a, T> {
             }
         }
 
-        let mated_value = -MATE_SCORE + (depth as i32);
+        // Getting mated sooner is worse, so the score counts the plies from the root
+        let mated_value = -MATE_SCORE + ply;
 
         let entry = TTEntry {
             seen: 1,
             depth,
-            value: if !moved { mated_value } else { best_value },
+            value: to_tt_score(if !moved { mated_value } else { best_value }, ply),
             movement: best_move,
             bound: if best_value <= alpha {
                 Bound::UpperBound
@@ -141,7 +461,7 @@ impl<'a, T: UciWriter + ?Sized> Search<'a, T> {
             },
         };
 
-        self.transposition_table.store(board.hash().unwrap(), entry);
+        self.transposition_table.store(board.hash(), entry);
 
         if !moved {
             return mated_value;
@@ -150,8 +470,58 @@ impl<'a, T: UciWriter + ?Sized> Search<'a, T> {
         alpha
     }
 
-    fn quiesce(&mut self, board: &Board, mut alpha: i32, beta: i32) -> i32 {
-        let score = evaluation::evaluate(board);
+    fn out_of_nodes(&mut self) -> bool {
+        // The limits only apply once the first depth is done so there is always a move to play
+        if let Some(limit) = self.node_limit.filter(|_| self.depth > 0) {
+            self.stopped = self.stopped || self.nodes >= limit;
+        }
+
+        if let Some(stop) = self.stop.filter(|_| self.depth > 0) {
+            self.stopped = self.stopped || stop.load(Ordering::Relaxed);
+        }
+
+        // Only look at the clock every so often, it is a lot slower than counting nodes
+        if self.nodes % 1024 != 0 {
+            return self.stopped;
+        }
+
+        if let Some(limit) = self.time_limit.filter(|_| self.depth > 0) {
+            self.stopped = self.stopped || self.started.elapsed() >= limit;
+        }
+
+        if self.should_update() && self.last_update.elapsed() >= UPDATE_AFTER {
+            self.last_update = Instant::now();
+            let info = self.progress_info();
+            self.writer.info(&info);
+        }
+
+        self.stopped
+    }
+
+    fn evaluate(&self, board: &Board) -> i32 {
+        match (self.network, self.accumulators.last()) {
+            (Some(network), Some(accumulator)) => network.evaluate(accumulator, board.turn),
+            _ => evaluation::evaluate(board),
+        }
+    }
+
+    fn push_accumulator(&mut self, before: &Board, after: &Board) {
+        if let Some(network) = self.network {
+            let mut accumulator = *self.accumulators.last().unwrap();
+            network.update(&mut accumulator, before, after);
+            self.accumulators.push(accumulator);
+        }
+    }
+
+    fn pop_accumulator(&mut self) {
+        if self.network.is_some() {
+            self.accumulators.pop();
+        }
+    }
+
+    fn quiesce(&mut self, board: &Board, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.seldepth = self.seldepth.max(ply);
+        let score = self.evaluate(board);
 
         if score >= beta {
             return beta;
@@ -161,11 +531,8 @@ impl<'a, T: UciWriter + ?Sized> Search<'a, T> {
             alpha = score;
         }
 
-        let moves = [
-            pseudo_moves(board),
-            castle_moves(board, &attacked_squares(board, &board.turn)),
-        ]
-        .concat();
+        // Castling never captures anything so there is no need to look at it here
+        let moves = pseudo_moves(board);
 
         for movement in &moves {
             if movement.capture.is_none() {
@@ -174,8 +541,10 @@ impl<'a, T: UciWriter + ?Sized> Search<'a, T> {
 
             let mut new_board = *board;
             new_board.move_piece(*movement);
+            self.push_accumulator(board, &new_board);
 
-            let score = -self.quiesce(&new_board, -beta, -alpha);
+            let score = -self.quiesce(&new_board, ply + 1, -beta, -alpha);
+            self.pop_accumulator();
 
             if score >= beta {
                 return beta;
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use common::ResolvedMovement;
+
+use crate::search::{MATE_SCORE, MATE_THRESHOLD};
+use crate::transposition_table::Bound;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Score {
+    Centipawns(i32),
+    /// Mate in the number of moves, negative when we are getting mated.
+    Mate(i32),
+}
+
+impl Score {
+    /// Converts a score from the search, a mate score is the mate score less the number of plies
+    /// to the mate so we can count the moves from it.
+    ///
+    /// ```
+    /// use engine::search::MATE_SCORE;
+    /// use engine::search_info::Score;
+    ///
+    /// assert_eq!(Score::from_search(35), Score::Centipawns(35));
+    /// assert_eq!(Score::from_search(MATE_SCORE - 3), Score::Mate(2));
+    /// assert_eq!(Score::from_search(-MATE_SCORE + 4), Score::Mate(-2));
+    /// ```
+    pub fn from_search(score: i32) -> Self {
+        if score.abs() <= MATE_THRESHOLD {
+            return Score::Centipawns(score);
+        }
+
+        let plies = MATE_SCORE - score.abs();
+        let moves = (plies + 1) / 2;
+        match score > 0 {
+            true => Score::Mate(moves),
+            false => Score::Mate(-moves),
+        }
+    }
+}
+
+/// What the search has found so far. Everything is optional so the same type can be used for the
+/// full report at the end of each depth, the progress updates in the middle of a long search and
+/// plain messages.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchInfo {
+    /// The depth that has been searched all the way through, or is being searched for updates.
+    pub depth: Option<usize>,
+    /// The deepest ply reached, including the quiescence search.
+    pub seldepth: Option<usize>,
+    /// Which of the lines this is when finding more than one, starting from 1.
+    pub multi_pv: Option<usize>,
+    pub score: Option<Score>,
+    /// If the score is exact or only a bound, the search stopped before it found the real score.
+    pub bound: Bound,
+    pub nodes: Option<i32>,
+    /// The time the search has been running.
+    pub time: Option<Duration>,
+    /// How full the transposition table is in parts per thousand.
+    pub hashfull: Option<usize>,
+    /// The root move being searched and its position in the move list, starting from 1.
+    pub current_move: Option<(ResolvedMovement, usize)>,
+    pub pv: Vec<ResolvedMovement>,
+    /// Any other message for the GUI.
+    pub string: Option<String>,
+}
+
+impl SearchInfo {
+    /// The nodes per second, if we know the nodes and the time.
+    pub fn nps(&self) -> Option<u64> {
+        let nodes = self.nodes? as u64;
+        let millis = self.time?.as_millis() as u64;
+
+        Some(nodes * 1000 / millis.max(1))
+    }
+}
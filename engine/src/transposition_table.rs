@@ -2,8 +2,13 @@ use std::collections::HashMap;
 
 use common::{Board, ResolvedMovement};
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+/// The number of positions the table keeps by default.
+const DEFAULT_ENTRIES: usize = 1 << 20;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum Bound {
+    #[default]
     Exact,
     LowerBound,
     UpperBound,
@@ -21,6 +26,8 @@ pub struct TTEntry {
 #[derive(Clone, Debug)]
 pub struct TranspositionTable {
     pub table: HashMap<u64, TTEntry>,
+    /// The most positions the table will keep, new positions are not stored once it is full
+    entries: usize,
 }
 
 impl Default for TranspositionTable {
@@ -31,8 +38,14 @@ impl Default for TranspositionTable {
 
 impl TranspositionTable {
     pub fn new() -> Self {
+        Self::with_entries(DEFAULT_ENTRIES)
+    }
+
+    /// A table that keeps at most this many positions.
+    pub fn with_entries(entries: usize) -> Self {
         Self {
             table: HashMap::new(),
+            entries: entries.max(1),
         }
     }
 
@@ -43,7 +56,7 @@ impl TranspositionTable {
             found.bound = entry.bound;
             found.movement = entry.movement;
             found.seen += entry.seen;
-        } else {
+        } else if self.table.len() < self.entries {
             self.table.insert(key, entry);
         }
     }
@@ -56,7 +69,7 @@ impl TranspositionTable {
         let mut pv = Vec::new();
         let mut current_pos = *board;
 
-        while let Some(entry) = self.retrieve(current_pos.hash().unwrap()) {
+        while let Some(entry) = self.retrieve(current_pos.hash()) {
             if let Some(best_move) = entry.movement {
                 pv.push(best_move);
                 current_pos.move_piece(best_move);
@@ -72,43 +85,48 @@ impl TranspositionTable {
         pv
     }
 
+    /// How full the table is in parts per thousand of the positions it can keep.
+    pub fn hashfull(&self) -> usize {
+        self.table.len() * 1000 / self.entries
+    }
+
     pub fn clean(&mut self) {
         self.table.retain(|_, v| v.seen < 3)
     }
+}
 
-    pub fn uci_best_move(&self, board: &Board) -> Result<String, String> {
-        let list = self.get_pv(board);
-        match list.first() {
-            Some(node) => Ok(format!("bestmove {}", node.uci())),
-            None => Err("Empty PV list".to_string()),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> TTEntry {
+        TTEntry {
+            depth: 1,
+            value: 0,
+            movement: None,
+            bound: Bound::Exact,
+            seen: 1,
         }
     }
 
-    pub fn uci_info(&self, board: &Board, nodes: i32) -> Result<String, String> {
-        let node = match self.retrieve(board.hash().unwrap()) {
-            Some(node) => node,
-            None => return Err("No entry for this node".to_string()),
-        };
-
-        let list = self.get_pv(board);
-
-        let is_mate = node.value <= (400000 + 100) || node.value >= (400000 - 100);
-
-        let score_unit = if is_mate { "mate" } else { "cp" };
-        let score_value = if is_mate {
-            list.len() as i32
-        } else {
-            node.value
-        };
-
-        Ok(format!(
-            "info depth {} nodes {} score {score_unit} {score_value} pv {}",
-            list.len(),
-            nodes,
-            list.iter()
-                .map(|node| node.uci())
-                .collect::<Vec<_>>()
-                .join(" ")
-        ))
+    #[test]
+    fn reports_hashfull_against_the_entries_it_can_keep() {
+        let mut table = TranspositionTable::with_entries(4);
+        assert_eq!(table.hashfull(), 0);
+
+        table.store(1, entry());
+        assert_eq!(table.hashfull(), 250);
+
+        // Storing the same position again does not take up any more room
+        table.store(1, entry());
+        assert_eq!(table.hashfull(), 250);
+
+        for key in 2..10 {
+            table.store(key, entry());
+        }
+
+        assert_eq!(table.hashfull(), 1000);
+        assert!(table.retrieve(4).is_some());
+        assert!(table.retrieve(5).is_none());
     }
 }
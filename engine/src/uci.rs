@@ -1,18 +1,140 @@
 use crate::evaluation::evaluate;
+use crate::evaluation::trace::EvalTrace;
+use crate::nnue::Network;
 use crate::search::Search;
-use crate::transposition_table::TranspositionTable;
-use crate::uci_command::{GoOptions, PositionOptions, UciCommand};
-use common::bb;
+use crate::search_info::{Score, SearchInfo};
+use crate::transposition_table::{Bound, TranspositionTable};
+use crate::uci_command::{GoOptions, PositionOptions, SetOptionOptions, UciCommand};
+use book::{encode_move, BookMode, Outcome, PolyglotBook};
+use common::legal_moves;
 use common::Board;
 use common::Fen;
-use common::Piece;
+use common::GameResult;
 use common::ResolvedMovement;
-use common::Square;
 
-use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// The depth we search to when there is no time control or depth limit.
+const DEFAULT_DEPTH: usize = 4;
+
+/// The depth we let the iterative deepening go up to when the clock or `stop` is going to end the
+/// search.
+const MAX_DEPTH: usize = 64;
 
 pub trait UciWriter {
     fn writeln(&mut self, output: &str);
+
+    /// A writer the search can use from another thread. When there is one the search runs in the
+    /// background so we can still answer `isready` and `stop`, otherwise it is finished before
+    /// `go` returns.
+    fn background(&self) -> Option<Box<dyn UciWriter + Send>> {
+        None
+    }
+
+    /// Writes out what the search has found as an `info` line.
+    fn info(&mut self, info: &SearchInfo) {
+        self.writeln(&format_info(info));
+    }
+}
+
+/// Formats the search info as a UCI `info` line, only the parts that are set are written.
+///
+/// ```
+/// use engine::search_info::{Score, SearchInfo};
+/// use engine::transposition_table::Bound;
+/// use std::time::Duration;
+///
+/// let info = SearchInfo {
+///     depth: Some(6),
+///     seldepth: Some(11),
+///     score: Some(Score::Mate(-3)),
+///     bound: Bound::UpperBound,
+///     nodes: Some(20000),
+///     time: Some(Duration::from_millis(400)),
+///     ..Default::default()
+/// };
+///
+/// assert_eq!(
+///     engine::uci::format_info(&info),
+///     "info depth 6 seldepth 11 score mate -3 upperbound nodes 20000 nps 50000 time 400"
+/// );
+/// ```
+pub fn format_info(info: &SearchInfo) -> String {
+    let mut parts = vec!["info".to_string()];
+
+    if let Some(depth) = info.depth {
+        parts.push(format!("depth {depth}"));
+    }
+
+    if let Some(seldepth) = info.seldepth {
+        parts.push(format!("seldepth {seldepth}"));
+    }
+
+    if let Some(multi_pv) = info.multi_pv {
+        parts.push(format!("multipv {multi_pv}"));
+    }
+
+    if let Some(score) = info.score {
+        parts.push(match score {
+            Score::Centipawns(cp) => format!("score cp {cp}"),
+            Score::Mate(moves) => format!("score mate {moves}"),
+        });
+
+        match info.bound {
+            Bound::Exact => {}
+            Bound::LowerBound => parts.push("lowerbound".to_string()),
+            Bound::UpperBound => parts.push("upperbound".to_string()),
+        }
+    }
+
+    if let Some(nodes) = info.nodes {
+        parts.push(format!("nodes {nodes}"));
+    }
+
+    if let Some(nps) = info.nps() {
+        parts.push(format!("nps {nps}"));
+    }
+
+    if let Some(hashfull) = info.hashfull {
+        parts.push(format!("hashfull {hashfull}"));
+    }
+
+    if let Some(time) = info.time {
+        parts.push(format!("time {}", time.as_millis()));
+    }
+
+    if let Some((movement, number)) = info.current_move {
+        parts.push(format!(
+            "currmove {} currmovenumber {number}",
+            movement.uci()
+        ));
+    }
+
+    if !info.pv.is_empty() {
+        let pv: Vec<String> = info.pv.iter().map(|movement| movement.uci()).collect();
+        parts.push(format!("pv {}", pv.join(" ")));
+    }
+
+    // The string has to come last, everything after it is part of the message
+    if let Some(string) = &info.string {
+        parts.push(format!("string {string}"));
+    }
+
+    parts.join(" ")
+}
+
+/// The depth to search to, without a depth from the GUI we let the clock or `stop` end the search
+/// if there is one.
+fn depth_for_move(options: &GoOptions, time: Option<Duration>) -> usize {
+    match (options.depth, time) {
+        (Some(depth), _) => depth.max(0) as usize,
+        (None, _) if options.infinite => MAX_DEPTH,
+        (None, Some(_)) => MAX_DEPTH,
+        (None, None) => DEFAULT_DEPTH,
+    }
 }
 
 pub struct UciOutputWriter {}
@@ -32,11 +154,47 @@ impl UciWriter for UciOutputWriter {
     fn writeln(&mut self, output: &str) {
         println!("{output}");
     }
+
+    fn background(&self) -> Option<Box<dyn UciWriter + Send>> {
+        Some(Box::new(UciOutputWriter::new()))
+    }
 }
 
 pub struct Uci {
     board: Board,
     transposition_table: TranspositionTable,
+    /// The network loaded from the `EvalFile` option
+    network: Option<Arc<Network>>,
+    /// If we should use the network when one is loaded, the handcrafted evaluation is always used
+    /// when there is no network
+    use_nnue: bool,
+    /// The book loaded from the `BookFile` option
+    book: Option<PolyglotBook>,
+    /// The path the book was loaded from, so it can be written back when we are learning
+    book_file: Option<String>,
+    /// If the book should learn from the results of games, this is the `BookLearning` option
+    book_learning: bool,
+    /// The book moves played in the current game, the hash of the position, the move and the
+    /// side that played it
+    book_moves: Vec<(u64, u16, common::Color)>,
+    /// If we should play moves from the book, this is the `OwnBook` option
+    own_book: bool,
+    /// The number of moves into the game the book is used for
+    book_depth: usize,
+    book_mode: BookMode,
+    /// The number of lines to search and report, this is the `MultiPV` option
+    multi_pv: usize,
+    /// The number of plies played in the current game
+    ply: usize,
+    /// The position and moves from the last `position` command that `board` is the result of
+    last_position: Option<(String, Vec<String>)>,
+    /// The state of the random number generator for the weighted book mode
+    random: u64,
+    /// The search running in the background, it hands the transposition table back when it is
+    /// done
+    search: Option<JoinHandle<TranspositionTable>>,
+    /// Set by `stop` to end the search running in the background
+    stop: Arc<AtomicBool>,
 }
 
 impl Default for Uci {
@@ -50,6 +208,97 @@ impl Uci {
         Self {
             board: Board::from_start_position().unwrap(),
             transposition_table: TranspositionTable::new(),
+            network: None,
+            use_nnue: true,
+            book: None,
+            book_file: None,
+            book_learning: false,
+            book_moves: Vec::new(),
+            own_book: false,
+            book_depth: 20,
+            book_mode: BookMode::Weighted,
+            multi_pv: 1,
+            ply: 0,
+            last_position: None,
+            random: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(1)
+                .max(1),
+            search: None,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Waits for the search running in the background to send its best move.
+    pub fn wait(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.transposition_table = search.join().expect("The search thread panicked");
+        }
+    }
+
+    /// A xorshift generator, it only picks between book moves so there is no need for anything
+    /// better.
+    fn next_random(&mut self) -> u64 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        self.random
+    }
+
+    /// Finds a move in the book for the current position if we are using one.
+    fn book_move(&mut self) -> Option<ResolvedMovement> {
+        if !self.own_book || self.ply >= self.book_depth * 2 {
+            return None;
+        }
+
+        let random = self.next_random();
+        let movement = self
+            .book
+            .as_ref()?
+            .choose(&self.board, self.book_mode, random)?;
+
+        self.book_moves
+            .push((self.board.hash(), encode_move(&movement), self.board.turn));
+
+        Some(movement)
+    }
+
+    /// Updates the book with the result of the game for all the book moves we have played, and
+    /// writes it back to the book file.
+    fn learn(&mut self, writer: &mut dyn UciWriter, result: &GameResult) {
+        let book_moves = std::mem::take(&mut self.book_moves);
+        if !self.book_learning || *result == GameResult::InProgress {
+            return;
+        }
+
+        let (Some(book), Some(book_file)) = (self.book.as_mut(), self.book_file.as_ref()) else {
+            return;
+        };
+
+        let mut learned = 0;
+        for (key, mv, color) in book_moves {
+            let outcome = match (result, color) {
+                (GameResult::WhiteWin, common::Color::White) => Outcome::Win,
+                (GameResult::BlackWin, common::Color::Black) => Outcome::Win,
+                (GameResult::Draw, _) => Outcome::Draw,
+                _ => Outcome::Loss,
+            };
+
+            if book.learn(key, mv, outcome) {
+                learned += 1;
+            }
+        }
+
+        if learned == 0 {
+            return;
+        }
+
+        match book.write_bin_file(book_file) {
+            Ok(()) => writer.writeln(&format!("info string Learned from {learned} book moves")),
+            Err(message) => {
+                writer.writeln(&format!("info string {message}, unable to save the book"))
+            }
         }
     }
 
@@ -57,19 +306,38 @@ impl Uci {
         let command: UciCommand = match input.try_into() {
             Ok(c) => c,
             Err(message) => {
-                writer.writeln(message.as_str());
+                writer.writeln(&format!("info string {message}"));
                 return;
             }
         };
 
+        // Only `isready` and `stop` are answered during a search, everything else waits for it
+        match command {
+            UciCommand::IsReady => {}
+            UciCommand::Stop | UciCommand::Quit => {
+                self.stop.store(true, Ordering::Relaxed);
+                self.wait();
+            }
+            _ => self.wait(),
+        }
+
         match command {
             UciCommand::Uci => self.uci(writer),
-            UciCommand::NewGame => self.board = Board::from_start_position().unwrap(),
+            UciCommand::NewGame => {
+                self.board = Board::from_start_position().unwrap();
+                self.ply = 0;
+                self.last_position = None;
+                self.book_moves.clear();
+            }
             UciCommand::IsReady => writer.writeln("readyok"),
             UciCommand::Print => self.print(writer),
-            UciCommand::Stop => std::process::exit(0),
+            UciCommand::Eval => self.eval(writer),
+            UciCommand::Stop => {}
+            UciCommand::Quit => std::process::exit(0),
             UciCommand::Position(options) => self.position(writer, &options),
             UciCommand::Go(options) => self.go(writer, &options),
+            UciCommand::SetOption(options) => self.set_option(writer, &options),
+            UciCommand::Result(result) => self.learn(writer, &result),
         }
     }
 
@@ -80,98 +348,292 @@ impl Uci {
             options.position.clone()
         };
 
-        let fen = match Fen::from_str(&fen_string) {
-            Ok(fen) => fen,
-            Err(message) => {
-                writer.writeln(&message);
-                return;
-            }
-        };
+        // GUIs send the whole game every move, when it is the last position with some more moves
+        // on the end we only need to play the new ones
+        let last = self.last_position.as_ref().filter(|(position, moves)| {
+            *position == fen_string && options.moves.starts_with(moves)
+        });
 
-        self.board.load_fen(&fen);
+        let (mut board, mut ply, played) = match last {
+            Some((_, moves)) => (self.board, self.ply, moves.len()),
+            None => {
+                // Check the position is legal up front, searching an impossible board can crash
+                // the engine
+                let fen = match Fen::new_strict(&fen_string) {
+                    Ok(fen) => fen,
+                    Err(error) => {
+                        writer.writeln(&format!("info string {error}"));
+                        return;
+                    }
+                };
 
-        for m in &options.moves {
-            let s = m.as_str();
-            if s.len() != 4 && s.len() != 5 {
-                writer.writeln(&format!("Invalid move '{s}'"));
-                return;
+                let mut board = Board::default();
+                board.load_fen(&fen);
+
+                let ply = (fen.full_move_number.max(1) as usize - 1) * 2
+                    + match fen.turn {
+                        common::Color::White => 0,
+                        common::Color::Black => 1,
+                    };
+
+                (board, ply, 0)
             }
+        };
 
-            let from = match Square::from_str(&s[0..2]) {
-                Ok(s) => s,
-                Err(m) => {
-                    writer.writeln(&m);
-                    return;
-                }
+        // Nothing is changed until all the moves have been played, so a bad move leaves the
+        // engine in the last good position
+        for text in &options.moves[played..] {
+            let uci = text.to_lowercase();
+            let Some(movement) = legal_moves(&board)
+                .into_iter()
+                .find(|movement| movement.uci() == uci)
+            else {
+                writer.writeln(&format!(
+                    "info string Illegal move '{text}' in {}",
+                    board.to_fen()
+                ));
+                return;
             };
 
-            let (color, piece) = match self.board.get_piece_at(&bb!(from)) {
-                Some(p) => p,
+            board.move_piece(movement);
+            ply += 1;
+        }
+
+        self.board = board;
+        self.ply = ply;
+        self.last_position = Some((fen_string, options.moves.clone()));
+    }
+
+    fn go(&mut self, writer: &mut dyn UciWriter, options: &GoOptions) {
+        // The GUI is still waiting for a bestmove, so a bad search move means we search all of
+        // the moves rather than not searching at all
+        let moves = legal_moves(&self.board);
+        let mut search_moves = Vec::new();
+        for text in &options.searchmoves {
+            match moves.iter().find(|movement| movement.uci() == *text) {
+                Some(movement) => search_moves.push(*movement),
                 None => {
-                    writer.writeln(&format!("There is no piece on the source square of {s}"));
-                    return;
+                    writer.writeln(&format!(
+                        "info string Illegal search move '{text}', searching all moves"
+                    ));
+                    search_moves.clear();
+                    break;
                 }
-            };
+            }
+        }
 
-            if color != self.board.turn {
-                writer.writeln(&format!("It is not {color}'s turn to move"));
+        // When analysing we want to see the search, not the book
+        if search_moves.is_empty() && self.multi_pv == 1 {
+            if let Some(movement) = self.book_move() {
+                writer.info(&SearchInfo {
+                    string: Some(format!("book move {}", movement.uci())),
+                    ..Default::default()
+                });
+                writer.writeln(&format!("bestmove {}", movement.uci()));
                 return;
             }
+        }
 
-            let to = match Square::from_str(&s[2..4]) {
-                Ok(s) => s,
-                Err(m) => {
-                    writer.writeln(&m);
-                    return;
-                }
-            };
+        let board = self.board;
+        let time = self.time_for_move(options);
+        let depth = depth_for_move(options, time);
+        let nodes = options.nodes;
+        let multi_pv = self.multi_pv;
+        let network = self.network.clone().filter(|_| self.use_nnue);
 
-            let mut capture: Option<Piece> = None;
-            if let Some((color, c)) = self.board.get_piece_at(&bb!(to)) {
-                capture = Some(c);
-                if color == self.board.turn {
-                    writer.writeln("You can not capture your own piece");
-                    return;
-                }
+        self.stop.store(false, Ordering::Relaxed);
+        let stop = Arc::clone(&self.stop);
+
+        let run = move |writer: &mut dyn UciWriter,
+                        transposition_table: &mut TranspositionTable| {
+            let mut search =
+                Search::new(writer, transposition_table, board, depth).with_stop(&stop);
+
+            if let Some(network) = &network {
+                search = search.with_network(network);
+            }
+
+            if let Some(nodes) = nodes {
+                search = search.with_node_limit(nodes);
             }
 
-            let mut promotion: Option<Piece> = None;
-            if s.len() == 5 {
-                promotion = Piece::from_str(&s[4..5]);
+            if let Some(time) = time {
+                search = search.with_time_limit(time);
             }
 
-            self.board.move_piece(ResolvedMovement {
-                piece,
-                from,
-                to,
-                capture,
-                promotion,
-            });
+            search = search
+                .with_search_moves(search_moves)
+                .with_multi_pv(multi_pv);
+
+            search.search();
+
+            transposition_table.clean();
+        };
+
+        match writer.background() {
+            Some(mut background) => {
+                let mut transposition_table = std::mem::take(&mut self.transposition_table);
+                self.search = Some(std::thread::spawn(move || {
+                    run(background.as_mut(), &mut transposition_table);
+                    transposition_table
+                }));
+            }
+            None => run(writer, &mut self.transposition_table),
         }
     }
 
-    fn go(&mut self, writer: &mut dyn UciWriter, options: &GoOptions) {
-        let mut search = Search::new(
-            writer,
-            &mut self.transposition_table,
-            self.board,
-            options.depth as usize,
-        );
-        search.search();
+    /// The time to spend on this move if the GUI sent `movetime` or the clocks. With the clocks
+    /// we plan for the moves to go or 30 more moves, and never use more than half of the time
+    /// that is left. An infinite search ignores the clocks.
+    fn time_for_move(&self, options: &GoOptions) -> Option<Duration> {
+        if options.infinite {
+            return None;
+        }
+
+        if let Some(movetime) = options.movetime {
+            return Some(Duration::from_millis(movetime.max(0) as u64));
+        }
 
-        self.transposition_table.clean();
+        let (time, increment) = match self.board.turn {
+            common::Color::White => (options.wtime, options.winc),
+            common::Color::Black => (options.btime, options.binc),
+        };
+
+        if time <= 0 {
+            return None;
+        }
+
+        let moves_to_go = match options.movestogo {
+            moves if moves > 0 => moves as u32,
+            _ => 30,
+        };
+
+        let time = Duration::from_millis(time as u64);
+        let increment = Duration::from_millis(increment.max(0) as u64);
+
+        Some((time / moves_to_go + increment / 2).min(time / 2))
     }
 
     fn uci(&self, writer: &mut dyn UciWriter) {
         writer.writeln("id name Ceir Development");
         writer.writeln("id author Ade Attwood");
+        writer.writeln("option name UseNNUE type check default true");
+        writer.writeln("option name EvalFile type string default <empty>");
+        writer.writeln("option name OwnBook type check default false");
+        writer.writeln("option name BookFile type string default <empty>");
+        writer.writeln("option name BookDepth type spin default 20 min 0 max 100");
+        writer.writeln("option name BookMode type combo default Weighted var Best var Weighted");
+        writer.writeln("option name BookLearning type check default false");
+        writer.writeln("option name MultiPV type spin default 1 min 1 max 256");
         writer.writeln("uciok");
     }
 
+    fn set_option(&mut self, writer: &mut dyn UciWriter, options: &SetOptionOptions) {
+        let value = options.value.clone().unwrap_or_default();
+
+        if options.name.eq_ignore_ascii_case("UseNNUE") {
+            match value.as_str() {
+                "true" => self.use_nnue = true,
+                "false" => self.use_nnue = false,
+                _ => writer.writeln(&format!("info string Invalid value '{value}' for UseNNUE")),
+            }
+        } else if options.name.eq_ignore_ascii_case("EvalFile") {
+            if value.is_empty() || value == "<empty>" {
+                self.network = None;
+                return;
+            }
+
+            match Network::load(&value) {
+                Ok(network) => {
+                    self.network = Some(Arc::new(network));
+                    writer.writeln(&format!("info string Loaded network {value}"));
+                }
+                Err(message) => {
+                    self.network = None;
+                    writer.writeln(&format!(
+                        "info string {message}, using the handcrafted evaluation"
+                    ));
+                }
+            }
+        } else if options.name.eq_ignore_ascii_case("OwnBook") {
+            match value.as_str() {
+                "true" => self.own_book = true,
+                "false" => self.own_book = false,
+                _ => writer.writeln(&format!("info string Invalid value '{value}' for OwnBook")),
+            }
+        } else if options.name.eq_ignore_ascii_case("BookFile") {
+            self.book_moves.clear();
+            if value.is_empty() || value == "<empty>" {
+                self.book = None;
+                self.book_file = None;
+                return;
+            }
+
+            let mut book = PolyglotBook::default();
+            match book.load_bin_file(&value) {
+                Ok(()) => {
+                    self.book = Some(book);
+                    self.book_file = Some(value.clone());
+                    writer.writeln(&format!("info string Loaded book {value}"));
+                }
+                Err(message) => {
+                    self.book = None;
+                    self.book_file = None;
+                    writer.writeln(&format!("info string {message}, not using a book"));
+                }
+            }
+        } else if options.name.eq_ignore_ascii_case("BookLearning") {
+            match value.as_str() {
+                "true" => self.book_learning = true,
+                "false" => self.book_learning = false,
+                _ => writer.writeln(&format!(
+                    "info string Invalid value '{value}' for BookLearning"
+                )),
+            }
+        } else if options.name.eq_ignore_ascii_case("MultiPV") {
+            match value.parse::<usize>() {
+                Ok(lines) if (1..=256).contains(&lines) => self.multi_pv = lines,
+                _ => writer.writeln(&format!("info string Invalid value '{value}' for MultiPV")),
+            }
+        } else if options.name.eq_ignore_ascii_case("BookDepth") {
+            match value.parse() {
+                Ok(depth) => self.book_depth = depth,
+                Err(_) => writer.writeln(&format!(
+                    "info string Invalid value '{value}' for BookDepth"
+                )),
+            }
+        } else if options.name.eq_ignore_ascii_case("BookMode") {
+            match BookMode::parse(&value) {
+                Ok(mode) => self.book_mode = mode,
+                Err(message) => writer.writeln(&format!("info string {message}")),
+            }
+        } else {
+            writer.writeln(&format!("info string Unknown option '{}'", options.name));
+        }
+    }
+
     fn print(&self, writer: &mut dyn UciWriter) {
         self.board.print();
-        let eval = evaluate(&self.board);
-        writer.writeln(&format!("Eval: {eval}"))
+        let eval = match self.network.as_ref().filter(|_| self.use_nnue) {
+            Some(network) => network.evaluate_board(&self.board),
+            None => evaluate(&self.board),
+        };
+
+        writer.writeln(&format!("Eval: {eval}"));
+
+        let moves: Vec<String> = legal_moves(&self.board)
+            .iter()
+            .map(|movement| movement.to_san(&self.board))
+            .collect();
+
+        writer.writeln(&format!("Legal moves: {}", moves.join(" ")));
+    }
+
+    fn eval(&self, writer: &mut dyn UciWriter) {
+        let trace = EvalTrace::new(&self.board);
+        for line in trace.to_string().lines() {
+            writer.writeln(line);
+        }
     }
 }
 
@@ -201,7 +663,7 @@ mod tests {
         let mut uci = Uci::new();
 
         uci.handle(&String::from("uci"), &mut writer);
-        assert_eq!(writer.lines.len(), 3);
+        assert_eq!(writer.lines.len(), 11);
         assert!(writer.lines.join("\n").contains("uciok"))
     }
 
@@ -215,5 +677,488 @@ mod tests {
         assert_eq!(writer.lines[0], "readyok");
     }
 
+    #[test]
+    fn will_print_the_evaluation_breakdown() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+
+        uci.handle(&String::from("eval"), &mut writer);
+        assert!(writer.lines.iter().any(|line| line.contains("King safety")));
+        assert_eq!(
+            writer.lines.last().unwrap(),
+            "Final evaluation: 0.00 (white side)"
+        );
+    }
+
+    #[test]
+    fn will_advertise_the_nnue_options() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+
+        uci.handle(&String::from("uci"), &mut writer);
+        assert!(writer
+            .lines
+            .iter()
+            .any(|line| line.starts_with("option name EvalFile")));
+        assert!(writer
+            .lines
+            .iter()
+            .any(|line| line.starts_with("option name UseNNUE")));
+    }
+
+    #[test]
+    fn will_fall_back_when_the_network_can_not_be_loaded() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+
+        uci.handle(
+            &String::from("setoption name EvalFile value /does/not/exist.nnue"),
+            &mut writer,
+        );
+        assert!(writer.lines[0].ends_with("using the handcrafted evaluation"));
+
+        uci.handle(&String::from("go depth 1"), &mut writer);
+        assert!(writer.lines.last().unwrap().starts_with("bestmove"));
+    }
+
+    #[test]
+    fn will_set_use_nnue() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+
+        uci.handle(
+            &String::from("setoption name UseNNUE value false"),
+            &mut writer,
+        );
+        assert!(!uci.use_nnue);
+        assert!(writer.lines.is_empty());
+
+        uci.handle(
+            &String::from("setoption name UseNNUE value maybe"),
+            &mut writer,
+        );
+        assert_eq!(
+            writer.lines[0],
+            "info string Invalid value 'maybe' for UseNNUE"
+        );
+    }
+
+    #[test]
+    fn will_reject_illegal_positions() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+
+        uci.handle(
+            &String::from("position fen 4k3/8/8/8/8/8/8/4K2r b - - 0 1"),
+            &mut writer,
+        );
+        assert_eq!(
+            writer.lines,
+            ["info string Illegal position, the side not to move is in check"]
+        );
+
+        uci.handle(
+            &String::from("position fen 8/8/8/8/8/8/8/7P w K - 0 1"),
+            &mut writer,
+        );
+        assert_eq!(
+            writer.lines[1],
+            "info string Illegal position, white has 0 kings, black has 0 kings, there is a pawn \
+             on h1, castling right 'K' without the king and rook"
+        );
+
+        // The board is left as it was
+        assert_eq!(
+            uci.board.to_fen(),
+            Board::from_start_position().unwrap().to_fen()
+        );
+    }
+
+    fn start_position_book() -> PolyglotBook {
+        let board = Board::from_start_position().unwrap();
+        let e4 = legal_moves(&board)
+            .into_iter()
+            .find(|m| m.uci() == "e2e4")
+            .unwrap();
+
+        let mut entry = book::PolyglotEntry::from_move(&e4);
+        entry.weight = 1;
+
+        let mut book = PolyglotBook::default();
+        book.insert(board.hash(), entry);
+        book
+    }
+
+    #[test]
+    fn will_play_moves_from_the_book() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+        uci.book = Some(start_position_book());
+
+        // The book is off by default
+        uci.handle(&String::from("go depth 1"), &mut writer);
+        assert!(writer.lines.iter().any(|line| line.starts_with("info")));
+
+        writer.lines.clear();
+        uci.handle(
+            &String::from("setoption name OwnBook value true"),
+            &mut writer,
+        );
+        uci.handle(
+            &String::from("setoption name BookMode value Best"),
+            &mut writer,
+        );
+        uci.handle(&String::from("go depth 1"), &mut writer);
+        assert_eq!(
+            writer.lines,
+            ["info string book move e2e4", "bestmove e2e4"]
+        );
+    }
+
+    #[test]
+    fn will_stop_using_the_book_after_the_book_depth() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+        uci.book = Some(start_position_book());
+
+        uci.handle(
+            &String::from("setoption name OwnBook value true"),
+            &mut writer,
+        );
+        uci.handle(
+            &String::from("setoption name BookDepth value 0"),
+            &mut writer,
+        );
+        uci.handle(&String::from("go depth 1"), &mut writer);
+        assert!(writer.lines.iter().any(|line| line.starts_with("info")));
+
+        uci.handle(
+            &String::from("position fen 4k3/8/8/8/8/8/8/4K3 b - - 0 12"),
+            &mut writer,
+        );
+        assert_eq!(uci.ply, 23);
+
+        uci.handle(
+            &String::from("setoption name BookFile value /does/not/exist.bin"),
+            &mut writer,
+        );
+        assert!(uci.book.is_none());
+        assert!(writer.lines.last().unwrap().ends_with("not using a book"));
+    }
+
+    #[test]
+    fn will_learn_from_the_result_of_the_game() {
+        let path = std::env::temp_dir().join(format!("ceir-learn-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+
+        let mut book = start_position_book();
+        book.entries.values_mut().next().unwrap()[0].weight = 16;
+        book.write_bin_file(&path).unwrap();
+
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+        for command in [
+            format!("setoption name BookFile value {path}"),
+            "setoption name OwnBook value true".to_string(),
+            "setoption name BookLearning value true".to_string(),
+            "go depth 1".to_string(),
+        ] {
+            uci.handle(&command, &mut writer);
+        }
+
+        assert_eq!(writer.lines.last().unwrap(), "bestmove e2e4");
+
+        writer.lines.clear();
+        uci.handle(&String::from("result 0-1"), &mut writer);
+        assert_eq!(writer.lines, ["info string Learned from 1 book moves"]);
+
+        // The book has been saved with the loss
+        let mut saved = PolyglotBook::default();
+        saved.load_bin_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let board = Board::from_start_position().unwrap();
+        let entry = &saved.entries[&board.hash()][0];
+        assert_eq!((entry.weight, entry.learned_games()), (14, 1));
+
+        // The moves are only learned from once
+        writer.lines.clear();
+        uci.handle(&String::from("result 0-1"), &mut writer);
+        assert!(writer.lines.is_empty());
+    }
+
+    #[test]
+    fn will_only_search_the_searchmoves() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+
+        uci.handle(&String::from("go depth 2 searchmoves a2a3"), &mut writer);
+        assert_eq!(writer.lines.last().unwrap(), "bestmove a2a3");
+        assert!(writer.lines[0].contains(" pv a2a3"));
+
+        writer.lines.clear();
+        uci.handle(
+            &String::from("go depth 2 searchmoves a2a3 e2e5"),
+            &mut writer,
+        );
+        assert_eq!(
+            writer.lines[0],
+            "info string Illegal search move 'e2e5', searching all moves"
+        );
+        assert!(writer.lines.last().unwrap().starts_with("bestmove "));
+        assert_ne!(writer.lines.last().unwrap(), "bestmove a2a3");
+    }
+
+    #[test]
+    fn will_report_multiple_lines() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+
+        uci.handle(&String::from("setoption name MultiPV value 3"), &mut writer);
+        uci.handle(&String::from("go depth 2"), &mut writer);
+
+        // There are lines for each depth
+        let info: Vec<&String> = writer
+            .lines
+            .iter()
+            .filter(|line| line.starts_with("info depth 2 "))
+            .collect();
+        assert_eq!(info.len(), 3);
+        assert_eq!(writer.lines.len(), 7);
+
+        let mut first_moves = Vec::new();
+        let mut scores = Vec::new();
+        for (index, line) in info.iter().enumerate() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let value = |name: &str| tokens[tokens.iter().position(|t| *t == name).unwrap() + 1];
+
+            assert_eq!(value("multipv"), (index + 1).to_string());
+            scores.push(value("cp").parse::<i32>().unwrap());
+            first_moves.push(value("pv"));
+        }
+
+        // Each line starts with a different move and they are best first
+        let distinct: std::collections::HashSet<&str> = first_moves.iter().copied().collect();
+        assert_eq!(distinct.len(), 3);
+        assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]));
+
+        let best = format!("bestmove {}", first_moves[0]);
+        assert_eq!(writer.lines.last().unwrap(), &best);
+
+        writer.lines.clear();
+        uci.handle(&String::from("setoption name MultiPV value 0"), &mut writer);
+        assert_eq!(writer.lines, ["info string Invalid value '0' for MultiPV"]);
+    }
+
+    #[test]
+    fn will_report_the_search_after_each_depth() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+
+        uci.handle(&String::from("go depth 3"), &mut writer);
+        assert_eq!(writer.lines.len(), 4);
+
+        for (index, line) in writer.lines[..3].iter().enumerate() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let value = |name: &str| {
+                let position = tokens.iter().position(|t| *t == name).unwrap();
+                tokens[position + 1].parse::<i64>().unwrap()
+            };
+
+            assert_eq!(value("depth"), index as i64 + 1);
+            assert!(value("seldepth") >= value("depth"));
+            assert!(value("nps") >= 0 && value("time") >= 0);
+            assert!((0..=1000).contains(&value("hashfull")));
+            assert!(!line.contains("multipv"));
+        }
+    }
+
+    #[test]
+    fn will_count_a_mate_from_the_score() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+
+        // Kb6 and then Rh8 is mate, the principal variation can be cut short by the table
+        uci.handle(
+            &String::from("position fen k7/8/2K5/8/8/8/8/7R w - - 0 1"),
+            &mut writer,
+        );
+        uci.handle(&String::from("go depth 6"), &mut writer);
+
+        for line in &writer.lines[3..writer.lines.len() - 1] {
+            assert!(line.contains(" score mate 2 "), "{line}");
+        }
+    }
+
+    #[test]
+    fn will_always_finish_the_first_depth() {
+        for command in ["go nodes 10", "go depth 1 nodes 5", "go depth 0"] {
+            let mut writer = UciTestWriter::new();
+            let mut uci = Uci::new();
+
+            uci.handle(&String::from(command), &mut writer);
+            assert!(writer.lines[0].starts_with("info depth 1 "), "{command}");
+            assert_ne!(writer.lines.last().unwrap(), "bestmove (none)", "{command}");
+        }
+    }
+
+    #[test]
+    fn will_castle_in_the_search() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+
+        // After black takes on c4, white gets the king to safety by castling rather than
+        // walking it to f1
+        uci.handle(
+            &String::from(
+                "position fen r3k2r/pppq1ppp/2n1bn2/2bpp3/2BPP3/2N1BN2/PPPQ1PPP/R3K2R b KQkq - 0 1",
+            ),
+            &mut writer,
+        );
+        uci.handle(&String::from("go depth 3"), &mut writer);
+
+        let line = &writer.lines[2];
+        assert!(line.contains(" pv d5c4 e1g1 "), "{line}");
+    }
+
+    #[test]
+    fn will_play_castling_and_en_passant_from_a_fen() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+
+        uci.handle(
+            &String::from(
+                "position fen r3k2r/pppp1ppp/8/8/4p3/8/PPPPPPPP/R3K2R w KQkq - 0 1 \
+                 moves e1g1 e8c8 d2d4 e4d3",
+            ),
+            &mut writer,
+        );
+
+        assert!(writer.lines.is_empty());
+        assert_eq!(
+            uci.board.to_fen(),
+            "2kr3r/pppp1ppp/8/8/8/3p4/PPP1PPPP/R4RK1 w - - 0 1"
+        );
+        assert_eq!(uci.ply, 4);
+    }
+
+    #[test]
+    fn will_reject_illegal_moves() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+
+        uci.handle(
+            &String::from("position startpos moves e2e4 e7e5 e1g1 d2d4"),
+            &mut writer,
+        );
+        assert_eq!(
+            writer.lines,
+            ["info string Illegal move 'e1g1' in \
+                 rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 1"]
+        );
+
+        // None of the moves are played
+        assert_eq!(
+            uci.board.to_fen(),
+            Board::from_start_position().unwrap().to_fen()
+        );
+    }
+
+    #[test]
+    fn will_only_play_the_new_moves() {
+        let mut writer = UciTestWriter::new();
+        let mut uci = Uci::new();
+
+        uci.handle(&String::from("position startpos moves e2e4"), &mut writer);
+        uci.handle(
+            &String::from("position startpos moves e2e4 e7e5 g1f3"),
+            &mut writer,
+        );
+
+        let mut expected = Uci::new();
+        expected.handle(
+            &String::from("position startpos moves e2e4 e7e5 g1f3"),
+            &mut writer,
+        );
+        assert_eq!(uci.board.to_fen(), expected.board.to_fen());
+        assert_eq!(uci.ply, 3);
+
+        // A different game starts again from the position
+        uci.handle(&String::from("position startpos moves d2d4"), &mut writer);
+        assert_eq!(
+            uci.board.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 1"
+        );
+        assert_eq!(uci.ply, 1);
+        assert!(writer.lines.is_empty());
+    }
+
+    #[test]
+    fn will_share_the_clock_between_the_moves_to_go() {
+        let time_for_move = |uci: &Uci, command: &str| match UciCommand::try_from(&command.into()) {
+            Ok(UciCommand::Go(options)) => uci.time_for_move(&options),
+            _ => panic!("Invalid go command '{command}'"),
+        };
+
+        let mut uci = Uci::new();
+        assert_eq!(time_for_move(&uci, "go depth 4"), None);
+        assert_eq!(
+            time_for_move(&uci, "go wtime 30000 btime 60000"),
+            Some(Duration::from_secs(1))
+        );
+
+        // Half of the increment is used on top
+        assert_eq!(
+            time_for_move(&uci, "go wtime 30000 btime 60000 winc 2000 binc 2000"),
+            Some(Duration::from_secs(2))
+        );
+
+        // Never more than half of the time on the clock, even with one move to go
+        assert_eq!(
+            time_for_move(&uci, "go wtime 1000 btime 1000 movestogo 1"),
+            Some(Duration::from_millis(500))
+        );
+
+        let mut writer = UciTestWriter::new();
+        uci.handle(&String::from("position startpos moves e2e4"), &mut writer);
+        assert_eq!(
+            time_for_move(&uci, "go wtime 30000 btime 60000 movestogo 10"),
+            Some(Duration::from_secs(6))
+        );
+    }
+
+    #[test]
+    fn will_let_the_clock_end_the_search_without_a_depth() {
+        let depth_for_move = |command: &str| match UciCommand::try_from(&command.into()) {
+            Ok(UciCommand::Go(options)) => {
+                depth_for_move(&options, Uci::new().time_for_move(&options))
+            }
+            _ => panic!("Invalid go command '{command}'"),
+        };
+
+        assert_eq!(depth_for_move("go"), DEFAULT_DEPTH);
+        assert_eq!(depth_for_move("go depth 6 wtime 3000 btime 3000"), 6);
+        assert_eq!(depth_for_move("go wtime 3000 btime 3000"), MAX_DEPTH);
+        assert_eq!(depth_for_move("go movetime 500"), MAX_DEPTH);
+        assert_eq!(depth_for_move("go infinite"), MAX_DEPTH);
+
+        // Only the clock of the side to move counts
+        assert_eq!(depth_for_move("go btime 3000"), DEFAULT_DEPTH);
+    }
+
+    #[test]
+    fn will_search_for_the_movetime_and_ignore_the_clock_when_infinite() {
+        let time_for_move = |command: &str| match UciCommand::try_from(&command.into()) {
+            Ok(UciCommand::Go(options)) => Uci::new().time_for_move(&options),
+            _ => panic!("Invalid go command '{command}'"),
+        };
+
+        assert_eq!(
+            time_for_move("go movetime 250 wtime 30000 btime 30000"),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(time_for_move("go infinite wtime 30000 btime 30000"), None);
+    }
+
     // position startpos moves e2e4
 }
@@ -1,3 +1,5 @@
+use common::GameResult;
+
 #[derive(Debug, PartialEq)]
 pub struct PositionOptions {
     pub position: String,
@@ -7,7 +9,7 @@ pub struct PositionOptions {
 #[derive(Debug, PartialEq)]
 pub struct GoOptions {
     /// search x plies only.
-    pub depth: i32,
+    pub depth: Option<i32>,
     /// white has x msec left on the clock
     pub wtime: i32,
     /// black has x msec left on the clock
@@ -19,6 +21,22 @@ pub struct GoOptions {
     /// there are x moves to the next time control this will only be sent if x > 0, if you don't
     /// get this and get the wtime and btime it's sudden death
     pub movestogo: i32,
+    /// search x nodes only
+    pub nodes: Option<i32>,
+    /// search exactly x mseconds
+    pub movetime: Option<i32>,
+    /// search until the stop command
+    pub infinite: bool,
+    /// restrict the search to these moves only
+    pub searchmoves: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SetOptionOptions {
+    /// The name of the option, this can have spaces in it
+    pub name: String,
+    /// The new value, buttons don't have a value
+    pub value: Option<String>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -27,9 +45,16 @@ pub enum UciCommand {
     NewGame,
     IsReady,
     Print,
+    Eval,
+    /// Ends the search running in the background, the best move so far is still sent
     Stop,
+    Quit,
     Position(PositionOptions),
     Go(GoOptions),
+    SetOption(SetOptionOptions),
+    /// Not part of UCI, the result of the game so far sent by a match runner so the engine can
+    /// learn from its book moves
+    Result(GameResult),
 }
 
 impl TryFrom<&String> for UciCommand {
@@ -43,8 +68,9 @@ impl TryFrom<&String> for UciCommand {
             Some("ucinewgame") => Ok(UciCommand::NewGame),
             Some("isready") => Ok(UciCommand::IsReady),
             Some("stop") => Ok(UciCommand::Stop),
-            Some("quit") => Ok(UciCommand::Stop),
+            Some("quit") => Ok(UciCommand::Quit),
             Some("d") => Ok(UciCommand::Print),
+            Some("eval") => Ok(UciCommand::Eval),
             Some("position") => {
                 let mut options = PositionOptions {
                     position: String::from(""),
@@ -79,19 +105,24 @@ impl TryFrom<&String> for UciCommand {
             }
             Some("go") => {
                 let mut options = GoOptions {
-                    depth: 4,
+                    depth: None,
                     wtime: 0,
                     btime: 0,
                     binc: 0,
                     winc: 0,
                     movestogo: 0,
+                    nodes: None,
+                    movetime: None,
+                    infinite: false,
+                    searchmoves: Vec::new(),
                 };
 
+                let mut tokens = tokens.peekable();
                 while let Some(token) = tokens.next() {
                     match token {
                         "depth" => match tokens.next() {
-                            Some(depth) => match depth.to_string().parse::<i32>() {
-                                Ok(number) => options.depth = number,
+                            Some(value) => match value.to_string().parse::<i32>() {
+                                Ok(number) => options.depth = Some(number),
                                 Err(message) => return Err(message.to_string()),
                             },
                             None => return Err("Missing depth".to_string()),
@@ -131,12 +162,82 @@ impl TryFrom<&String> for UciCommand {
                             },
                             None => return Err("Missing movestogo value".to_string()),
                         },
+                        "nodes" => match tokens.next() {
+                            Some(nodes) => match nodes.to_string().parse::<i32>() {
+                                Ok(number) => options.nodes = Some(number),
+                                Err(message) => return Err(message.to_string()),
+                            },
+                            None => return Err("Missing nodes value".to_string()),
+                        },
+                        "movetime" => match tokens.next() {
+                            Some(movetime) => match movetime.to_string().parse::<i32>() {
+                                Ok(number) => options.movetime = Some(number),
+                                Err(message) => return Err(message.to_string()),
+                            },
+                            None => return Err("Missing movetime value".to_string()),
+                        },
+                        "infinite" => options.infinite = true,
+                        "searchmoves" => {
+                            // The moves go on until the next option
+                            while let Some(movement) = tokens.next_if(|token| {
+                                !matches!(
+                                    *token,
+                                    "depth"
+                                        | "wtime"
+                                        | "btime"
+                                        | "winc"
+                                        | "binc"
+                                        | "movestogo"
+                                        | "nodes"
+                                        | "movetime"
+                                        | "infinite"
+                                )
+                            }) {
+                                options.searchmoves.push(movement.to_string());
+                            }
+
+                            if options.searchmoves.is_empty() {
+                                return Err("Missing searchmoves".to_string());
+                            }
+                        }
                         _ => return Err(format!("Unexpected token {token}")),
                     }
                 }
 
                 Ok(UciCommand::Go(options))
             }
+            Some("setoption") => {
+                if tokens.next() != Some("name") {
+                    return Err("Missing option name".to_string());
+                }
+
+                let mut name: Vec<&str> = Vec::new();
+                let mut value: Option<Vec<&str>> = None;
+
+                for token in tokens {
+                    match value.as_mut() {
+                        Some(value) => value.push(token),
+                        None if token == "value" => value = Some(Vec::new()),
+                        None => name.push(token),
+                    }
+                }
+
+                if name.is_empty() {
+                    return Err("Missing option name".to_string());
+                }
+
+                Ok(UciCommand::SetOption(SetOptionOptions {
+                    name: name.join(" "),
+                    value: value.map(|value| value.join(" ")),
+                }))
+            }
+            Some("result") => match tokens.next() {
+                Some("1-0") => Ok(UciCommand::Result(GameResult::WhiteWin)),
+                Some("0-1") => Ok(UciCommand::Result(GameResult::BlackWin)),
+                Some("1/2-1/2") => Ok(UciCommand::Result(GameResult::Draw)),
+                Some(result) => Err(format!("Invalid result '{result}'")),
+                None => Err("Missing result".to_string()),
+            },
             Some(command) => Err(format!("Invalid command {command}")),
             None => Err("Missing command".to_string()),
         }
@@ -245,7 +346,7 @@ mod tests {
             _ => panic!("Unable to get the options from the position"),
         };
 
-        assert_eq!(go_options.depth, 4);
+        assert_eq!(go_options.depth, None);
     }
 
     #[test]
@@ -256,7 +357,7 @@ mod tests {
             _ => panic!("Unable to get the options from the position"),
         };
 
-        assert_eq!(go_options.depth, 20);
+        assert_eq!(go_options.depth, Some(20));
     }
 
     #[test]
@@ -270,9 +371,124 @@ mod tests {
         assert_eq!(go_options.wtime, 300000);
     }
 
+    #[test]
+    fn will_parse_go_with_nodes() {
+        let command = parse_command(&String::from("go depth 10 nodes 5000"));
+        let go_options = match command {
+            UciCommand::Go(options) => options,
+            _ => panic!("Unable to get the options from the position"),
+        };
+
+        assert_eq!(go_options.depth, Some(10));
+        assert_eq!(go_options.nodes, Some(5000));
+    }
+
+    #[test]
+    fn will_parse_go_with_searchmoves() {
+        let command = parse_command(&String::from("go searchmoves e2e4 d2d4 depth 3"));
+        let go_options = match command {
+            UciCommand::Go(options) => options,
+            _ => panic!("Unable to get the options from the position"),
+        };
+
+        assert_eq!(go_options.searchmoves, ["e2e4", "d2d4"]);
+        assert_eq!(go_options.depth, Some(3));
+
+        let error = parse_command_error(&String::from("go searchmoves nodes 10"));
+        assert_eq!(error, "Missing searchmoves");
+    }
+
+    #[test]
+    fn will_parse_go_with_movetime() {
+        let command = parse_command(&String::from("go movetime 250"));
+        let go_options = match command {
+            UciCommand::Go(options) => options,
+            _ => panic!("Unable to get the options from the position"),
+        };
+
+        assert_eq!(go_options.movetime, Some(250));
+        assert_eq!(go_options.depth, None);
+
+        let error = parse_command_error(&String::from("go movetime"));
+        assert_eq!(error, "Missing movetime value");
+    }
+
+    #[test]
+    fn will_parse_go_infinite() {
+        let command = parse_command(&String::from("go infinite"));
+        let go_options = match command {
+            UciCommand::Go(options) => options,
+            _ => panic!("Unable to get the options from the position"),
+        };
+
+        assert!(go_options.infinite);
+        assert_eq!(go_options.depth, None);
+
+        let command = parse_command(&String::from("go infinite depth 6"));
+        let go_options = match command {
+            UciCommand::Go(options) => options,
+            _ => panic!("Unable to get the options from the position"),
+        };
+
+        assert_eq!(go_options.depth, Some(6));
+    }
+
+    #[test]
+    fn will_parse_stop_and_quit() {
+        assert_eq!(parse_command(&String::from("stop")), UciCommand::Stop);
+        assert_eq!(parse_command(&String::from("quit")), UciCommand::Quit);
+    }
+
+    #[test]
+    fn will_parse_eval_command() {
+        let command = parse_command(&String::from("eval"));
+        assert_eq!(command, UciCommand::Eval);
+    }
+
     #[test]
     fn will_parse_new_game_command() {
         let command = parse_command(&String::from("ucinewgame"));
         assert_eq!(command, UciCommand::NewGame);
     }
+
+    #[test]
+    fn will_parse_setoption() {
+        let command = parse_command(&String::from(
+            "setoption name EvalFile value nets/ceir 1.nnue",
+        ));
+        assert_eq!(
+            command,
+            UciCommand::SetOption(SetOptionOptions {
+                name: "EvalFile".to_string(),
+                value: Some("nets/ceir 1.nnue".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn will_parse_setoption_without_a_value() {
+        let command = parse_command(&String::from("setoption name Clear Hash"));
+        assert_eq!(
+            command,
+            UciCommand::SetOption(SetOptionOptions {
+                name: "Clear Hash".to_string(),
+                value: None,
+            })
+        );
+    }
+
+    #[test]
+    fn will_parse_result() {
+        let command = parse_command(&String::from("result 1/2-1/2"));
+        assert_eq!(command, UciCommand::Result(GameResult::Draw));
+
+        let message = parse_command_error(&String::from("result *"));
+        assert_eq!(message, "Invalid result '*'");
+    }
+
+    #[test]
+    fn will_error_on_setoption_without_a_name() {
+        let message = parse_command_error(&String::from("setoption value 1"));
+        assert_eq!(message, "Missing option name");
+    }
 }
@@ -0,0 +1,476 @@
+use crate::search::Search;
+use crate::transposition_table::TranspositionTable;
+use crate::uci::UciWriter;
+use common::ResolvedMovement;
+use common::{attacked_squares, is_in_check, legal_moves, Board, Color, Fen, Movement};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// The depth we search to when there is no time control or depth limit, the same as `go` in UCI.
+const DEFAULT_DEPTH: usize = 4;
+
+/// The depth we let the iterative deepening go up to when the clock is going to stop the search.
+const MAX_DEPTH: usize = 64;
+
+/// The number of moves we assume are left in the game when the time control does not say.
+const MOVES_TO_GO: u64 = 30;
+
+/// The Chess Engine Communication Protocol, also known as the xboard protocol. This uses the same
+/// search as the UCI front end, the GUI tells us the moves one at a time and we keep track of the
+/// game and decide when to move.
+pub struct XBoard {
+    board: Board,
+    /// The positions before each move so moves can be taken back with `undo` and `remove`.
+    history: Vec<Board>,
+    transposition_table: TranspositionTable,
+    /// The side the engine is playing, `None` in force mode when the engine just follows the
+    /// moves it is sent.
+    engine_color: Option<Color>,
+    /// The depth limit from `sd`.
+    max_depth: Option<usize>,
+    /// The exact time to spend on each move from `st`.
+    move_time: Option<Duration>,
+    /// The number of moves in each time control from `level`, 0 when the whole game is one time
+    /// control.
+    moves_per_control: u64,
+    /// The increment from `level`.
+    increment: Duration,
+    /// The time left on our clock from `time`.
+    time_left: Option<Duration>,
+    /// If we should print the thinking output, this is set with `post` and `nopost`.
+    post: bool,
+    /// The search running in the background, it hands back the transposition table and the move
+    /// it made when it is done
+    search: Option<JoinHandle<(TranspositionTable, Option<ResolvedMovement>)>>,
+    /// Set by `?` to make the move we have found so far
+    stop: Arc<AtomicBool>,
+    /// Set when the game changes under the search so it stops without making a move
+    abort: Arc<AtomicBool>,
+}
+
+impl Default for XBoard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl XBoard {
+    pub fn new() -> Self {
+        Self {
+            board: Board::from_start_position().unwrap(),
+            history: Vec::new(),
+            transposition_table: TranspositionTable::new(),
+            engine_color: Some(Color::Black),
+            max_depth: None,
+            move_time: None,
+            moves_per_control: 0,
+            increment: Duration::ZERO,
+            time_left: None,
+            post: false,
+            search: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            abort: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Waits for the search running in the background to make its move.
+    pub fn wait(&mut self) {
+        if let Some(search) = self.search.take() {
+            let (transposition_table, movement) =
+                search.join().expect("The search thread panicked");
+            self.transposition_table = transposition_table;
+            self.finish(movement);
+        }
+    }
+
+    pub fn handle(&mut self, input: &str, writer: &mut dyn UciWriter) {
+        let mut tokens = input.split_whitespace();
+        let Some(command) = tokens.next() else {
+            return;
+        };
+
+        let args: Vec<&str> = tokens.collect();
+
+        // Only `?` and `ping` are answered while we are thinking. Commands that change the game
+        // stop the search without making a move, everything else waits for the move
+        match command {
+            "?" => self.stop.store(true, Ordering::Relaxed),
+            "ping" | "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy"
+            | "computer" | "name" | "rating" | "otim" => {}
+            "new" | "force" | "result" | "setboard" | "undo" | "remove" | "quit" => {
+                self.abort.store(true, Ordering::Relaxed);
+                self.stop.store(true, Ordering::Relaxed);
+                self.wait();
+            }
+            _ => self.wait(),
+        }
+
+        match command {
+            "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer"
+            | "name" | "rating" | "otim" | "?" => {}
+            "protover" => writer.writeln(
+                "feature myname=\"Ceir Development\" ping=1 setboard=1 usermove=1 time=1 \
+                 colors=0 sigint=0 sigterm=0 reuse=1 analyze=0 done=1",
+            ),
+            "ping" => writer.writeln(&format!("pong {}", args.join(" "))),
+            "new" => {
+                *self = Self {
+                    post: self.post,
+                    ..Self::new()
+                };
+            }
+            "setboard" => self.set_board(writer, &args.join(" ")),
+            "usermove" => self.user_move(writer, args.first().copied().unwrap_or_default()),
+            "go" => {
+                self.engine_color = Some(self.board.turn);
+                self.think(writer);
+            }
+            "force" | "result" => self.engine_color = None,
+            "level" => self.level(writer, &args),
+            "st" => match args.first().and_then(|value| value.parse::<f64>().ok()) {
+                Some(seconds) => self.move_time = Some(Duration::from_secs_f64(seconds)),
+                None => writer.writeln(&format!("Error (bad time): {}", args.join(" "))),
+            },
+            "sd" => match args.first().and_then(|value| value.parse().ok()) {
+                Some(depth) => self.max_depth = Some(depth),
+                None => writer.writeln(&format!("Error (bad depth): {}", args.join(" "))),
+            },
+            "time" => match args.first().and_then(|value| value.parse::<u64>().ok()) {
+                Some(centiseconds) => {
+                    self.time_left = Some(Duration::from_millis(centiseconds * 10))
+                }
+                None => writer.writeln(&format!("Error (bad time): {}", args.join(" "))),
+            },
+            "undo" => self.take_back(1),
+            "remove" => self.take_back(2),
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "quit" => std::process::exit(0),
+            // With `usermove=1` the moves should always come with the command, this is here for
+            // GUIs that ignore the feature
+            _ if self.find_move(command).is_some() => self.user_move(writer, command),
+            _ => writer.writeln(&format!("Error (unknown command): {command}")),
+        }
+    }
+
+    /// Sets the time control, `level 40 5 0` is 40 moves in 5 minutes and `level 0 2:30 1` is
+    /// the whole game in 2 minutes 30 seconds with a 1 second increment.
+    fn level(&mut self, writer: &mut dyn UciWriter, args: &[&str]) {
+        let base = args.get(1).and_then(|base| {
+            let mut seconds = 0;
+            for part in base.split(':') {
+                seconds = seconds * 60 + part.parse::<u64>().ok()?;
+            }
+
+            // Without a colon the time is in minutes
+            match base.contains(':') {
+                true => Some(seconds),
+                false => Some(seconds * 60),
+            }
+        });
+
+        let moves = args.first().and_then(|moves| moves.parse().ok());
+        let increment = args.get(2).and_then(|inc| inc.parse::<f64>().ok());
+
+        match (moves, base, increment) {
+            (Some(moves), Some(base), Some(increment)) => {
+                self.moves_per_control = moves;
+                self.time_left = Some(Duration::from_secs(base));
+                self.increment = Duration::from_secs_f64(increment);
+                self.move_time = None;
+            }
+            _ => writer.writeln(&format!("Error (bad level): {}", args.join(" "))),
+        }
+    }
+
+    fn set_board(&mut self, writer: &mut dyn UciWriter, fen: &str) {
+        match Fen::new_strict(fen) {
+            Ok(fen) => {
+                self.board.load_fen(&fen);
+                self.history.clear();
+            }
+            Err(error) => writer.writeln(&format!("tellusererror Illegal position: {error}")),
+        }
+    }
+
+    /// Finds a legal move from the coordinate notation, like `e2e4` or `e7e8q`, falling back to
+    /// SAN for GUIs that send that instead.
+    fn find_move(&self, text: &str) -> Option<ResolvedMovement> {
+        legal_moves(&self.board)
+            .into_iter()
+            .find(|movement| movement.uci() == text)
+            .or_else(|| Movement::from_san(text, &self.board).ok())
+    }
+
+    fn user_move(&mut self, writer: &mut dyn UciWriter, text: &str) {
+        let Some(movement) = self.find_move(text) else {
+            writer.writeln(&format!("Illegal move: {text}"));
+            return;
+        };
+
+        self.play(movement);
+        if !self.game_over(writer) && self.engine_color == Some(self.board.turn) {
+            self.think(writer);
+        }
+    }
+
+    fn play(&mut self, movement: ResolvedMovement) {
+        self.history.push(self.board);
+        self.board.move_piece(movement);
+    }
+
+    fn take_back(&mut self, plies: usize) {
+        for _ in 0..plies {
+            if let Some(board) = self.history.pop() {
+                self.board = board;
+            }
+        }
+    }
+
+    /// Writes out the result if there are no moves left in the game.
+    fn game_over(&mut self, writer: &mut dyn UciWriter) -> bool {
+        let Some(result) = result(&self.board) else {
+            return false;
+        };
+
+        writer.writeln(result);
+        self.engine_color = None;
+        true
+    }
+
+    /// Plays the move the search made, the search has already written it and the result out.
+    fn finish(&mut self, movement: Option<ResolvedMovement>) {
+        if let Some(movement) = movement {
+            self.play(movement);
+            if result(&self.board).is_some() {
+                self.engine_color = None;
+            }
+        }
+    }
+
+    /// The time to spend on the next move, if there is a time control.
+    fn time_for_move(&self) -> Option<Duration> {
+        if let Some(move_time) = self.move_time {
+            return Some(move_time);
+        }
+
+        let time_left = self.time_left?;
+        let moves_to_go = match self.moves_per_control {
+            0 => MOVES_TO_GO,
+            moves => moves - (self.history.len() as u64 / 2) % moves,
+        };
+
+        Some((time_left / moves_to_go as u32 + self.increment / 2).min(time_left / 2))
+    }
+
+    fn think(&mut self, writer: &mut dyn UciWriter) {
+        if self.game_over(writer) {
+            return;
+        }
+
+        let board = self.board;
+        let time = self.time_for_move();
+        let depth = match (self.max_depth, time) {
+            (Some(depth), _) => depth,
+            (None, Some(_)) => MAX_DEPTH,
+            (None, None) => DEFAULT_DEPTH,
+        };
+
+        let post = self.post;
+        self.stop.store(false, Ordering::Relaxed);
+        self.abort.store(false, Ordering::Relaxed);
+        let (stop, abort) = (Arc::clone(&self.stop), Arc::clone(&self.abort));
+
+        let run = move |writer: &mut dyn UciWriter,
+                        transposition_table: &mut TranspositionTable| {
+            let started = Instant::now();
+            let mut search =
+                Search::new(writer, transposition_table, board, depth).with_stop(&stop);
+            if let Some(time) = time {
+                search = search.with_time_limit(time);
+            }
+
+            let best = search.best_move();
+            let (depth, nodes) = (search.depth(), search.nodes());
+
+            // The game is not over so there is a move, and the first depth is always searched
+            let (movement, score) = best.expect("The search did not find a move");
+
+            if abort.load(Ordering::Relaxed) {
+                transposition_table.clean();
+                return None;
+            }
+
+            if post {
+                let pv: Vec<String> = transposition_table
+                    .get_pv(&board)
+                    .iter()
+                    .map(|movement| movement.uci())
+                    .collect();
+
+                writer.writeln(&format!(
+                    "{depth} {score} {} {nodes} {}",
+                    started.elapsed().as_millis() / 10,
+                    pv.join(" ")
+                ));
+            }
+
+            transposition_table.clean();
+            writer.writeln(&format!("move {}", movement.uci()));
+
+            let mut after = board;
+            after.move_piece(movement);
+            if let Some(result) = result(&after) {
+                writer.writeln(result);
+            }
+
+            Some(movement)
+        };
+
+        match writer.background() {
+            Some(mut background) => {
+                let mut transposition_table = std::mem::take(&mut self.transposition_table);
+                self.search = Some(std::thread::spawn(move || {
+                    let movement = run(background.as_mut(), &mut transposition_table);
+                    (transposition_table, movement)
+                }));
+            }
+            None => {
+                let movement = run(writer, &mut self.transposition_table);
+                self.finish(movement);
+            }
+        }
+    }
+}
+
+/// The result if there are no moves left in the game.
+fn result(board: &Board) -> Option<&'static str> {
+    if !legal_moves(board).is_empty() {
+        return None;
+    }
+
+    let in_check = is_in_check(board, &attacked_squares(board, &board.turn.opposite()));
+    match (in_check, board.turn) {
+        (true, Color::White) => Some("0-1 {Black mates}"),
+        (true, Color::Black) => Some("1-0 {White mates}"),
+        (false, _) => Some("1/2-1/2 {Stalemate}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestWriter {
+        lines: Vec<String>,
+    }
+
+    impl UciWriter for TestWriter {
+        fn writeln(&mut self, output: &str) {
+            self.lines.push(String::from(output));
+        }
+    }
+
+    fn run(xboard: &mut XBoard, commands: &[&str]) -> Vec<String> {
+        let mut writer = TestWriter { lines: Vec::new() };
+        for command in commands {
+            xboard.handle(command, &mut writer);
+        }
+
+        writer.lines
+    }
+
+    #[test]
+    fn will_send_the_features() {
+        let mut xboard = XBoard::new();
+        let lines = run(&mut xboard, &["xboard", "protover 2", "ping 7"]);
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("feature "));
+        assert!(lines[0].contains("usermove=1"));
+        assert!(lines[0].ends_with("done=1"));
+        assert_eq!(lines[1], "pong 7");
+    }
+
+    #[test]
+    fn will_reply_to_user_moves() {
+        let mut xboard = XBoard::new();
+        let lines = run(&mut xboard, &["new", "sd 1", "post", "usermove e2e4"]);
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("1 "));
+        assert!(lines[1].starts_with("move "));
+        assert_eq!(xboard.history.len(), 2);
+        assert_eq!(xboard.board.turn, Color::White);
+
+        let lines = run(&mut xboard, &["usermove e2e5"]);
+        assert_eq!(lines, ["Illegal move: e2e5"]);
+    }
+
+    #[test]
+    fn will_only_follow_the_moves_in_force_mode() {
+        let mut xboard = XBoard::new();
+        let lines = run(
+            &mut xboard,
+            &["new", "force", "usermove e2e4", "usermove e7e5", "undo"],
+        );
+
+        assert!(lines.is_empty());
+        assert_eq!(xboard.history.len(), 1);
+        assert_eq!(xboard.board.turn, Color::Black);
+
+        // The engine takes the side to move when told to go
+        let lines = run(&mut xboard, &["sd 1", "go"]);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("move "));
+        assert_eq!(xboard.engine_color, Some(Color::Black));
+
+        run(&mut xboard, &["remove"]);
+        assert!(xboard.history.is_empty());
+    }
+
+    #[test]
+    fn will_finish_the_game() {
+        let mut xboard = XBoard::new();
+        let lines = run(
+            &mut xboard,
+            &[
+                "new",
+                "force",
+                "setboard 7k/8/5KQ1/8/8/8/8/8 w - - 0 1",
+                "sd 2",
+                "go",
+            ],
+        );
+
+        assert_eq!(lines, ["move g6g7", "1-0 {White mates}"]);
+        assert_eq!(xboard.engine_color, None);
+    }
+
+    #[test]
+    fn will_read_the_time_controls() {
+        let mut xboard = XBoard::new();
+        run(&mut xboard, &["level 40 5 2"]);
+        assert_eq!(xboard.time_left, Some(Duration::from_secs(300)));
+        assert_eq!(xboard.time_for_move(), Some(Duration::from_millis(8500)));
+
+        run(&mut xboard, &["level 0 0:30 0", "time 1000"]);
+        assert_eq!(xboard.time_for_move(), Some(Duration::from_secs(10) / 30));
+
+        run(&mut xboard, &["st 2"]);
+        assert_eq!(xboard.time_for_move(), Some(Duration::from_secs(2)));
+
+        let lines = run(&mut xboard, &["level 40", "sd x", "bogus"]);
+        assert_eq!(
+            lines,
+            [
+                "Error (bad level): 40",
+                "Error (bad depth): x",
+                "Error (unknown command): bogus"
+            ]
+        );
+    }
+}
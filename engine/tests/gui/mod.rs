@@ -0,0 +1,133 @@
+//! A stand in for a GUI that drives the engine binary over stdin and stdout, shared by the
+//! protocol tests. Each test binary only uses some of the helpers.
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use common::{legal_moves, Board};
+
+/// Long enough for a debug build on a slow machine, a reply that takes this long is never coming.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A stand in for a GUI, the output of the engine is read on another thread so we can wait for
+/// replies with a timeout rather than hanging when one never comes.
+pub struct Gui {
+    engine: Child,
+    stdin: ChildStdin,
+    output: Receiver<String>,
+    /// Everything the engine has written, to show what happened when a test fails
+    transcript: Vec<String>,
+}
+
+impl Gui {
+    pub fn start() -> Self {
+        let mut engine = Command::new(env!("CARGO_BIN_EXE_engine"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Unable to start the engine");
+
+        let stdin = engine.stdin.take().unwrap();
+        let stdout = engine.stdout.take().unwrap();
+
+        let (sender, output) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            engine,
+            stdin,
+            output,
+            transcript: Vec::new(),
+        }
+    }
+
+    pub fn send(&mut self, command: &str) {
+        self.transcript.push(format!("> {command}"));
+        writeln!(self.stdin, "{command}").unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    /// Reads lines until one starts with the prefix, returning all of the lines read including
+    /// the one that matched.
+    pub fn expect(&mut self, prefix: &str) -> Vec<String> {
+        let deadline = Instant::now() + TIMEOUT;
+        let mut lines = Vec::new();
+
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.output.recv_timeout(timeout) {
+                Ok(line) => {
+                    self.transcript.push(line.clone());
+                    lines.push(line.clone());
+                    if line.starts_with(prefix) {
+                        return lines;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    panic!("Timed out waiting for '{prefix}'\n{}", self.transcript())
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    panic!("The engine exited before '{prefix}'\n{}", self.transcript())
+                }
+            }
+        }
+    }
+
+    /// Checks the engine has not written anything it should not have.
+    pub fn expect_nothing(&mut self, wait: Duration) {
+        if let Ok(line) = self.output.recv_timeout(wait) {
+            self.transcript.push(line.clone());
+            panic!("Unexpected output '{line}'\n{}", self.transcript());
+        }
+    }
+
+    pub fn transcript(&self) -> String {
+        self.transcript.join("\n")
+    }
+
+    /// Sends `quit` and waits for the engine to exit.
+    pub fn quit(mut self) {
+        self.send("quit");
+
+        let deadline = Instant::now() + TIMEOUT;
+        while Instant::now() < deadline {
+            if let Some(status) = self.engine.try_wait().unwrap() {
+                assert!(status.success(), "The engine exited with {status}");
+                return;
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        self.engine.kill().unwrap();
+        panic!("The engine did not quit\n{}", self.transcript());
+    }
+}
+
+/// The move from a `bestmove` line, checking it is legal on the board.
+pub fn best_move(line: &str, board: &Board) -> String {
+    let movement = line
+        .strip_prefix("bestmove ")
+        .unwrap_or_else(|| panic!("Expected a bestmove but got '{line}'"))
+        .split_whitespace()
+        .next()
+        .unwrap()
+        .to_string();
+
+    assert!(
+        legal_moves(board).iter().any(|m| m.uci() == movement),
+        "bestmove {movement} is not legal in {}",
+        board.to_fen()
+    );
+
+    movement
+}
@@ -0,0 +1,260 @@
+//! Drives the engine binary over stdin and stdout the way a GUI would, to check the replies to
+//! whole UCI sessions rather than single commands.
+
+mod gui;
+
+use std::time::{Duration, Instant};
+
+use common::{legal_moves, Board};
+use gui::{best_move, Gui};
+
+#[test]
+fn handshake() {
+    let mut gui = Gui::start();
+
+    gui.send("uci");
+    let lines = gui.expect("uciok");
+
+    assert!(lines[0].starts_with("id name "));
+    assert!(lines[1].starts_with("id author "));
+    assert!(lines[2..lines.len() - 1]
+        .iter()
+        .all(|line| line.starts_with("option name ")));
+
+    gui.send("isready");
+    assert_eq!(gui.expect("readyok"), ["readyok"]);
+
+    gui.quit();
+}
+
+#[test]
+fn always_sends_a_bestmove() {
+    let mut gui = Gui::start();
+    gui.send("uci");
+    gui.expect("uciok");
+
+    let positions = [
+        ("startpos", Board::from_start_position().unwrap()),
+        (
+            "fen 6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+            Board::from_fen_str("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap(),
+        ),
+        // There is only one legal move
+        (
+            "fen 7k/8/8/8/8/8/6q1/7K w - - 0 1",
+            Board::from_fen_str("7k/8/8/8/8/8/6q1/7K w - - 0 1").unwrap(),
+        ),
+    ];
+
+    for (position, board) in positions {
+        gui.send(&format!("position {position}"));
+        gui.send("go depth 3");
+
+        let lines = gui.expect("bestmove");
+        assert!(lines[..lines.len() - 1]
+            .iter()
+            .all(|line| line.starts_with("info ")));
+        best_move(lines.last().unwrap(), &board);
+    }
+
+    // Checkmate, there is no move to make but we still have to reply
+    gui.send("position fen 7k/8/8/8/8/8/5q2/6qK w - - 0 1");
+    gui.send("go depth 3");
+    assert_eq!(gui.expect("bestmove"), ["bestmove (none)"]);
+
+    gui.quit();
+}
+
+#[test]
+fn answers_isready_during_a_search() {
+    let mut gui = Gui::start();
+    gui.send("uci");
+    gui.expect("uciok");
+
+    gui.send("position startpos");
+    gui.send("go infinite");
+    gui.send("isready");
+
+    // The search only ends with stop, so readyok has to come while it is still running
+    let lines = gui.expect("readyok");
+    assert!(
+        !lines.iter().any(|line| line.starts_with("bestmove")),
+        "bestmove before readyok\n{}",
+        gui.transcript()
+    );
+
+    gui.send("stop");
+    let lines = gui.expect("bestmove");
+    best_move(
+        lines.last().unwrap(),
+        &Board::from_start_position().unwrap(),
+    );
+
+    gui.quit();
+}
+
+#[test]
+fn stop_ends_an_infinite_search() {
+    let mut gui = Gui::start();
+    gui.send("uci");
+    gui.expect("uciok");
+
+    let board = Board::from_fen_str("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap();
+    gui.send("position fen 6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1");
+    gui.send("go infinite");
+
+    // Let it get going before stopping it
+    gui.expect("info depth 2 ");
+    gui.send("stop");
+    let lines = gui.expect("bestmove");
+    best_move(lines.last().unwrap(), &board);
+
+    // There is only ever one bestmove for each go, and stop does not end the session
+    gui.expect_nothing(Duration::from_millis(200));
+    gui.send("isready");
+    gui.expect("readyok");
+
+    gui.send("go depth 2");
+    let lines = gui.expect("bestmove");
+    best_move(lines.last().unwrap(), &board);
+
+    gui.quit();
+}
+
+#[test]
+fn plays_a_game_with_ucinewgame_and_moves() {
+    let mut gui = Gui::start();
+    gui.send("uci");
+    gui.expect("uciok");
+
+    for _ in 0..2 {
+        gui.send("ucinewgame");
+        gui.send("isready");
+        gui.expect("readyok");
+
+        let mut board = Board::from_start_position().unwrap();
+        let mut moves: Vec<String> = Vec::new();
+
+        for _ in 0..6 {
+            gui.send(&format!("position startpos moves {}", moves.join(" ")));
+            gui.send("go depth 2");
+
+            let lines = gui.expect("bestmove");
+            let movement = best_move(lines.last().unwrap(), &board);
+
+            let played = legal_moves(&board)
+                .into_iter()
+                .find(|m| m.uci() == movement)
+                .unwrap();
+            board.move_piece(played);
+            moves.push(movement);
+        }
+    }
+
+    gui.quit();
+}
+
+#[test]
+fn keeps_to_the_clock() {
+    let mut gui = Gui::start();
+    gui.send("uci");
+    gui.expect("uciok");
+
+    gui.send("position startpos moves e2e4 e7e5");
+
+    // Without the clock this would search for a very long time
+    let started = Instant::now();
+    gui.send("go wtime 3000 btime 3000 winc 0 binc 0");
+    let lines = gui.expect("bestmove");
+    let elapsed = started.elapsed();
+
+    let mut board = Board::from_start_position().unwrap();
+    for uci in ["e2e4", "e7e5"] {
+        let movement = legal_moves(&board)
+            .into_iter()
+            .find(|m| m.uci() == uci)
+            .unwrap();
+        board.move_piece(movement);
+    }
+
+    best_move(lines.last().unwrap(), &board);
+
+    // The engine plans to use a small part of the clock, but a busy machine can hold it up so we
+    // only check it did not use the whole clock and lose on time
+    assert!(
+        elapsed < Duration::from_millis(3000),
+        "Searched for {elapsed:?}\n{}",
+        gui.transcript()
+    );
+
+    gui.quit();
+}
+
+#[test]
+fn searches_for_the_movetime() {
+    let mut gui = Gui::start();
+    gui.send("uci");
+    gui.expect("uciok");
+
+    gui.send("position startpos");
+
+    let started = Instant::now();
+    gui.send("go movetime 500");
+    let lines = gui.expect("bestmove");
+    let elapsed = started.elapsed();
+
+    best_move(
+        lines.last().unwrap(),
+        &Board::from_start_position().unwrap(),
+    );
+
+    // Without a depth the search only stops for the time. It runs a bit over as the depth being
+    // searched is thrown away, and a lot over on a busy machine
+    assert!(
+        elapsed >= Duration::from_millis(500) && elapsed < Duration::from_millis(500) * 10,
+        "Searched for {elapsed:?}\n{}",
+        gui.transcript()
+    );
+
+    gui.quit();
+}
+
+#[test]
+fn survives_bad_input() {
+    let mut gui = Gui::start();
+    gui.send("uci");
+    gui.expect("uciok");
+
+    gui.send("position startpos moves e2e4 e2e4");
+    let lines = gui.expect("info string Illegal move 'e2e4'");
+    assert_eq!(lines.len(), 1);
+
+    gui.send("position fen 8/8/8/8/8/8/8/8 w - - 0 1");
+    assert_eq!(
+        gui.expect("info string"),
+        ["info string Illegal position, white has 0 kings, black has 0 kings"]
+    );
+
+    // Commands we can't read are reported rather than searched
+    gui.send("not a command");
+    gui.send("go depth nine");
+    gui.send("isready");
+    assert_eq!(
+        gui.expect("readyok"),
+        [
+            "info string Invalid command not",
+            "info string invalid digit found in string",
+            "readyok"
+        ]
+    );
+
+    // The bad commands did not change the position
+    gui.send("go depth 1");
+    let lines = gui.expect("bestmove");
+    best_move(
+        lines.last().unwrap(),
+        &Board::from_start_position().unwrap(),
+    );
+
+    gui.quit();
+}
@@ -0,0 +1,95 @@
+//! Drives the engine binary with the xboard protocol, to check the engine still listens to the
+//! GUI while it is thinking.
+
+mod gui;
+
+use std::time::Duration;
+
+use common::{legal_moves, Board};
+use gui::Gui;
+
+/// Checks the engine replied with a legal move, returning it.
+fn engine_move(line: &str, board: &Board) -> String {
+    let movement = line
+        .strip_prefix("move ")
+        .unwrap_or_else(|| panic!("Expected a move but got '{line}'"))
+        .to_string();
+
+    assert!(
+        legal_moves(board).iter().any(|m| m.uci() == movement),
+        "move {movement} is not legal in {}",
+        board.to_fen()
+    );
+
+    movement
+}
+
+fn after(moves: &[&str]) -> Board {
+    let mut board = Board::from_start_position().unwrap();
+    for uci in moves {
+        let movement = legal_moves(&board)
+            .into_iter()
+            .find(|m| m.uci() == *uci)
+            .unwrap();
+        board.move_piece(movement);
+    }
+
+    board
+}
+
+#[test]
+fn moves_now_while_thinking() {
+    let mut gui = Gui::start();
+    gui.send("xboard");
+    gui.send("protover 2");
+    gui.expect("feature ");
+
+    // Far too long to wait for, the engine only moves because it is told to
+    gui.send("new");
+    gui.send("st 1000");
+    gui.send("usermove e2e4");
+
+    gui.send("ping 1");
+    let lines = gui.expect("pong 1");
+    assert!(
+        !lines.iter().any(|line| line.starts_with("move ")),
+        "Moved before pong\n{}",
+        gui.transcript()
+    );
+
+    gui.send("?");
+    let lines = gui.expect("move ");
+    let reply = engine_move(lines.last().unwrap(), &after(&["e2e4"]));
+
+    // The engine plays on from the move it made
+    gui.send("sd 1");
+    gui.send("usermove d2d4");
+    let lines = gui.expect("move ");
+    engine_move(lines.last().unwrap(), &after(&["e2e4", &reply, "d2d4"]));
+
+    gui.quit();
+}
+
+#[test]
+fn force_stops_thinking_without_a_move() {
+    let mut gui = Gui::start();
+    gui.send("xboard");
+    gui.send("protover 2");
+    gui.expect("feature ");
+
+    gui.send("new");
+    gui.send("st 1000");
+    gui.send("usermove e2e4");
+    gui.send("force");
+    gui.send("ping 1");
+    assert_eq!(gui.expect("pong 1"), ["pong 1"]);
+    gui.expect_nothing(Duration::from_millis(200));
+
+    // Black is still to move, so the engine can play it when told to go
+    gui.send("sd 1");
+    gui.send("go");
+    let lines = gui.expect("move ");
+    engine_move(lines.last().unwrap(), &after(&["e2e4"]));
+
+    gui.quit();
+}
@@ -0,0 +1,68 @@
+use pest::iterators::Pair;
+
+use crate::Rule;
+
+/// An error from reading or writing a PGN. The game and location are 0 when they are not known,
+/// like when writing a game out.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PgnError {
+    /// The index of the game in the input, starting at 1.
+    pub game: usize,
+    /// The line in the input, starting at 1.
+    pub line: usize,
+    /// The column in the line, starting at 1.
+    pub column: usize,
+    /// The text of the move that could not be played.
+    pub movement: Option<String>,
+    /// The position the move was being played from.
+    pub fen: Option<String>,
+    pub message: String,
+}
+
+impl PgnError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Creates an error at the start of a rule in the input.
+    pub(crate) fn at(pair: &Pair<Rule>, message: impl Into<String>) -> Self {
+        let (line, column) = pair.line_col();
+        Self {
+            line,
+            column,
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl std::fmt::Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+
+        if self.game > 0 {
+            write!(f, " in game {}", self.game)?;
+        }
+
+        if self.line > 0 {
+            write!(f, " at line {} column {}", self.line, self.column)?;
+        }
+
+        if let Some(fen) = &self.fen {
+            write!(f, " from '{fen}'")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+impl From<common::Error> for PgnError {
+    fn from(error: common::Error) -> Self {
+        Self::new(error.to_string())
+    }
+}
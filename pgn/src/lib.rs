@@ -1,300 +1,240 @@
-use common::AmbiguousMovement;
+use std::collections::HashMap;
+
 use common::Board;
-use common::Color;
 use common::Game;
 use common::GameResult;
-use common::Piece;
-use common::Square;
-use pest::{iterators::Pair, Parser};
+use common::Movement;
+use common::ResolvedMovement;
+use pest::{
+    error::LineColLocation,
+    iterators::{Pair, Pairs},
+    Parser,
+};
 use pest_derive::Parser;
 
+mod error;
+pub use crate::error::*;
+
+mod reader;
+pub use crate::reader::*;
+
+mod tree;
+pub use crate::tree::*;
+
+mod writer;
+pub use crate::writer::*;
+
 #[derive(Parser)]
 #[grammar = "pgn.pest"]
 struct PGNParser;
 
-fn parse_san_move(pair: Pair<Rule>) -> Result<AmbiguousMovement, String> {
-    if pair.as_rule() != Rule::san_move {
-        return Err("Unable to parse rule, its not a san move".to_string());
+/// The NAG for each of the traditional move annotations.
+fn annotation_nag(pair: &Pair<Rule>) -> Result<u8, PgnError> {
+    match pair.as_str() {
+        "!" => Ok(1),
+        "?" => Ok(2),
+        "!!" => Ok(3),
+        "??" => Ok(4),
+        "!?" => Ok(5),
+        "?!" => Ok(6),
+        annotation => Err(PgnError::at(
+            pair,
+            format!("Invalid annotation '{annotation}'"),
+        )),
     }
+}
 
-    for item in pair.into_inner() {
-        match item.as_rule() {
-            Rule::pawn_move => {
-                let mut item_itr = item.into_inner();
-                let file = item_itr.next().unwrap().as_str();
-                let rank = item_itr.next().unwrap().as_str();
-
-                return Ok(AmbiguousMovement {
-                    file: None,
-                    piece: Some(Piece::Pawn),
-                    from: None,
-                    to: Square::from_file_and_rank_str(file, rank)?,
-                    capture: None,
-                    promotion: None,
-                });
-            }
-            Rule::pice_move => {
-                let mut item_itr = item.into_inner();
-                let piece = item_itr.next().unwrap().as_str();
-                let file = item_itr.next().unwrap().as_str();
-                let rank = item_itr.next().unwrap().as_str();
-
-                return Ok(AmbiguousMovement {
-                    file: None,
-                    piece: match Piece::from_str(piece) {
-                        Some(p) => Some(p),
-                        None => return Err(format!("Invalid piece '{}'", piece)),
-                    },
-                    from: None,
-                    to: Square::from_file_and_rank_str(file, rank)?,
-                    capture: None,
-                    promotion: None,
-                });
-            }
-            Rule::disambiguous_move => {
-                let mut item_itr = item.into_inner();
-                let piece_str = item_itr.next().unwrap().as_str();
-                let file_or_rank = item_itr.next().unwrap();
-                let file = item_itr.next().unwrap().as_str();
-                let rank = item_itr.next().unwrap().as_str();
-
-                return Ok(AmbiguousMovement {
-                    file: Some(file_or_rank.as_str().chars().next().unwrap()),
-                    piece: match Piece::from_str(piece_str) {
-                        Some(p) => Some(p),
-                        None => return Err(format!("Invalid piece '{}'", piece_str)),
-                    },
-                    from: None,
-                    to: Square::from_file_and_rank_str(file, rank)?,
-                    capture: None,
-                    promotion: None,
-                });
-            }
-            _ => unreachable!("Parse error, invalid san move item {:?}", item.as_rule()),
-        }
-    }
+fn parse_nag(pair: &Pair<Rule>) -> Result<u8, PgnError> {
+    pair.as_str()[1..]
+        .parse()
+        .map_err(|_| PgnError::at(pair, format!("Invalid NAG '{}'", pair.as_str())))
+}
 
-    unreachable!("Parse error, unable to parse san move");
+fn parse_comment(pair: Pair<Rule>) -> Comment {
+    Comment::parse(pair.into_inner().as_str())
 }
 
-fn parse_san(board: &Board, pair: Pair<Rule>) -> Result<AmbiguousMovement, String> {
-    if pair.as_rule() != Rule::san {
-        return Err("Unable to parse rule, its not a san".to_string());
-    }
+fn parse_move(pair: &Pair<Rule>, board: &Board) -> Result<ResolvedMovement, PgnError> {
+    Movement::from_san(pair.as_str(), board).map_err(|error| PgnError {
+        movement: Some(pair.as_str().to_string()),
+        fen: Some(board.to_fen()),
+        ..PgnError::at(pair, error.to_string())
+    })
+}
 
-    let mut movement = AmbiguousMovement::default();
+/// Parses a line of moves from the board. Comments after a move are attached to that move, any
+/// comments before the first move are returned so the caller can decide where they go.
+fn parse_line(
+    pairs: Pairs<Rule>,
+    mut board: Board,
+) -> Result<(Vec<MoveNode>, Vec<Comment>), PgnError> {
+    let mut moves: Vec<MoveNode> = Vec::new();
+    let mut comments: Vec<Comment> = Vec::new();
+    let mut previous_board = board;
+
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::move_number => {}
+            Rule::san => {
+                let movement = parse_move(&pair, &board)?;
+                moves.push(MoveNode {
+                    movement,
+                    comments_before: std::mem::take(&mut comments),
+                    ..Default::default()
+                });
 
-    for item in pair.into_inner() {
-        match item.as_rule() {
-            Rule::san_move => {
-                movement = parse_san_move(item)?;
+                previous_board = board;
+                board.move_piece(movement);
             }
-            Rule::kings_side_castle => {
-                if board.turn == Color::White {
-                    movement = AmbiguousMovement {
-                        file: None,
-                        piece: Some(Piece::King),
-                        from: Some(Square::E1),
-                        to: Square::G1,
-                        capture: None,
-                        promotion: None,
-                    }
-                } else {
-                    movement = AmbiguousMovement {
-                        file: None,
-                        piece: Some(Piece::King),
-                        from: Some(Square::E8),
-                        to: Square::G8,
-                        capture: None,
-                        promotion: None,
+            Rule::annotation | Rule::nag => {
+                let nag = match pair.as_rule() {
+                    Rule::annotation => annotation_nag(&pair)?,
+                    _ => parse_nag(&pair)?,
+                };
+
+                match moves.last_mut() {
+                    Some(node) => node.nags.push(nag),
+                    None => {
+                        let message = format!("Expected a move before '{}'", pair.as_str());
+                        return Err(PgnError::at(&pair, message));
                     }
                 }
             }
-            Rule::queens_side_castle => {
-                if board.turn == Color::White {
-                    movement = AmbiguousMovement {
-                        file: None,
-                        piece: Some(Piece::King),
-                        from: Some(Square::E1),
-                        to: Square::C1,
-                        capture: None,
-                        promotion: None,
-                    }
-                } else {
-                    movement = AmbiguousMovement {
-                        file: None,
-                        piece: Some(Piece::King),
-                        from: Some(Square::E8),
-                        to: Square::C8,
-                        capture: None,
-                        promotion: None,
-                    }
+            Rule::comment | Rule::line_comment => match moves.last_mut() {
+                Some(node) => node.comments_after.push(parse_comment(pair)),
+                None => comments.push(parse_comment(pair)),
+            },
+            Rule::variation => {
+                if moves.is_empty() {
+                    return Err(PgnError::at(&pair, "Expected a move before a variation"));
                 }
-            }
-            Rule::capture => {
-                let mut item_itr = item.into_inner();
-                let file_or_piece = item_itr.next().unwrap();
-                let _unused_capture_marker = item_itr.next();
-                let file = item_itr.next().unwrap().as_str();
-                let rank = item_itr.next().unwrap().as_str();
-
-                let to_square = Square::from_file_and_rank_str(file, rank)?;
-
-                movement = match file_or_piece.as_rule() {
-                    Rule::pice => AmbiguousMovement {
-                        file: None,
-                        piece: Some(Piece::from_str(file_or_piece.as_str()).unwrap()),
-                        from: None,
-                        to: to_square,
-                        capture: None,
-                        promotion: None,
-                    },
-
-                    Rule::file => AmbiguousMovement {
-                        file: Some(file_or_piece.as_str().chars().next().unwrap()),
-                        piece: Some(Piece::Pawn),
-                        from: None,
-                        to: to_square,
-                        capture: None,
-                        promotion: None,
-                    },
-                    _ => unreachable!("Unexpected capture expected a file or a piece"),
-                };
-            }
-            Rule::capture_with_pice => {
-                let mut item_itr = item.into_inner();
-                let pice = item_itr.next().unwrap();
-                let first_file = item_itr.next().unwrap();
-
-                let _unused_capture_marker = item_itr.next();
-
-                let file = item_itr.next().unwrap().as_str();
-                let rank = item_itr.next().unwrap().as_str();
-                let to_square = Square::from_file_and_rank_str(file, rank)?;
-
-                movement = AmbiguousMovement {
-                    file: Some(first_file.as_str().chars().next().unwrap()),
-                    piece: Some(Piece::from_str(pice.as_str()).unwrap()),
-                    from: None,
-                    to: to_square,
-                    capture: None,
-                    promotion: None,
-                };
-            }
-            Rule::promotion => {
-                let piece_str = item.into_inner().next().unwrap().as_str();
-                movement.promotion = Piece::from_str(piece_str);
-            }
-            Rule::suffix => {
-                // { ("+" | "#" | "?" | "!")+ }
-            }
-            _ => unreachable!("Parse error, invalid san item {:?}", item.as_rule()),
-        }
-    }
 
-    Ok(movement)
-}
-
-fn parse_move(game: &mut Game, pair: Pair<Rule>) -> Result<(), String> {
-    if pair.as_rule() != Rule::pgn_move {
-        return Err("Unable to parse rule, its not a move".to_string());
-    }
-
-    for item in pair.into_inner() {
-        match item.as_rule() {
-            Rule::move_number => {}
-            Rule::single_san => {
-                let movement = parse_san(&game.board, item.into_inner().next().unwrap())?;
-                game.move_piece(movement.resolve(&game.board)?);
-                return Ok(());
-            }
-            Rule::double_san => {
-                let mut itr = item.into_inner();
-
-                let left = parse_san(&game.board, itr.next().unwrap())?;
-                game.move_piece(left.resolve(&game.board)?);
-
-                let right = parse_san(&game.board, itr.next().unwrap())?;
-                game.move_piece(right.resolve(&game.board)?);
-
-                return Ok(());
+                let (variation, _) = parse_line(pair.into_inner(), previous_board)?;
+                if let Some(node) = moves.last_mut() {
+                    node.variations.push(variation);
+                }
             }
-            _ => unreachable!("Parse error, invalid move item {:?}", item.as_rule()),
+            rule => return Err(PgnError::at(&pair, format!("Unexpected {rule:?}"))),
         }
     }
 
-    unreachable!("Parse error, expected a move");
+    Ok((moves, comments))
 }
 
-fn parse_result(pair: Pair<Rule>) -> Result<GameResult, String> {
-    if pair.as_rule() != Rule::result {
-        return Err("Unable to parse rule, its not a result".to_string());
-    }
-
-    let result_rule = pair.into_inner().next().unwrap().as_rule();
-    match result_rule {
-        Rule::in_progress => Ok(GameResult::InProgress),
-        Rule::white_win => Ok(GameResult::WhiteWin),
-        Rule::black_win => Ok(GameResult::BlackWin),
-        Rule::draw => Ok(GameResult::Draw),
-        _ => unreachable!("Parse error, invalid game result {:?}", result_rule),
+fn parse_result(pair: Pair<Rule>) -> Result<GameResult, PgnError> {
+    match pair
+        .clone()
+        .into_inner()
+        .next()
+        .map(|inner| inner.as_rule())
+    {
+        Some(Rule::in_progress) => Ok(GameResult::InProgress),
+        Some(Rule::white_win) => Ok(GameResult::WhiteWin),
+        Some(Rule::black_win) => Ok(GameResult::BlackWin),
+        Some(Rule::draw) => Ok(GameResult::Draw),
+        _ => Err(PgnError::at(
+            &pair,
+            format!("Invalid game result '{}'", pair.as_str()),
+        )),
     }
 }
 
-fn parse_game(pair: Pair<Rule>) -> Result<Game, String> {
-    if pair.as_rule() != Rule::game {
-        return Err("Unable to parse rule, its not a game".to_string());
+/// The position the game starts from, this is the `FEN` tag if there is one, unless the `SetUp`
+/// tag says it is not being used.
+pub fn start_position(metadata: &HashMap<String, String>) -> Result<Board, PgnError> {
+    match (
+        metadata.get("FEN"),
+        metadata.get("SetUp").map(|v| v.as_str()),
+    ) {
+        (Some(fen), None | Some("1")) => Board::from_fen_str(fen).map_err(|e| PgnError {
+            fen: Some(fen.to_string()),
+            ..PgnError::new(format!("Invalid FEN tag, {e}"))
+        }),
+        _ => Ok(Board::from_start_position()?),
     }
+}
 
-    let mut game = Game::default();
-
-    // TODO(AdeAttwood): Find a way to get the start pos from the metadata
-    game.board = Board::from_start_position()?;
+fn parse_game(pair: Pair<Rule>) -> Result<GameTree, PgnError> {
+    let mut game = GameTree::default();
 
     for item in pair.into_inner() {
         match item.as_rule() {
             Rule::meta => {
                 let mut meta_itr = item.into_inner();
-                let key = meta_itr.next().unwrap().as_str();
-                let value = meta_itr
-                    .next()
-                    .into_iter()
-                    .next()
-                    .unwrap()
-                    .into_inner()
-                    .as_str();
-
-                game.metadata.insert(key.to_string(), value.to_string());
+                let key = meta_itr.next().map(|key| key.as_str().to_string());
+                let value = meta_itr.next().map(|value| value.into_inner().as_str());
+
+                if let (Some(key), Some(value)) = (key, value) {
+                    game.metadata.insert(key, value.to_string());
+                }
+            }
+            Rule::movetext => {
+                // The tags always come before the moves so we know where the game starts
+                let (line, column) = item.line_col();
+                game.board =
+                    start_position(&game.metadata).map_err(|e| PgnError { line, column, ..e })?;
+
+                (game.moves, game.comments) = parse_line(item.into_inner(), game.board)?;
             }
-            Rule::pgn_move => parse_move(&mut game, item)?,
             Rule::result => game.result = parse_result(item)?,
-            _ => unreachable!("Unexpected game item: {:?}", item.as_rule()),
+            rule => return Err(PgnError::at(&item, format!("Unexpected {rule:?}"))),
         }
     }
 
     Ok(game)
 }
 
-pub fn parse(string: &String) -> Result<Vec<Game>, String> {
-    let parsed = match PGNParser::parse(Rule::root, string) {
-        Ok(mut parsed) => parsed.next().unwrap(),
-        Err(e) => return Err(format!("{}", e)),
-    };
+/// Parses all the games in a PGN string keeping all the variations, comments and annotations.
+///
+/// ```
+/// let games = pgn::parse_tree("1. e4 {[%eval 0.17]} e5 (1... c5 $1 {Sicilian}) 2. Nf3 *").unwrap();
+/// let e4 = &games[0].moves[0];
+///
+/// assert_eq!(e4.eval(), Some(pgn::Evaluation::Centipawns(17)));
+/// assert_eq!(games[0].moves[1].variations[0][0].nags, vec![1]);
+/// assert_eq!(games[0].moves[1].variations[0][0].comments_after[0].text, "Sicilian");
+/// ```
+pub fn parse_tree(string: &str) -> Result<Vec<GameTree>, PgnError> {
+    let parsed = PGNParser::parse(Rule::root, string).map_err(|e| {
+        let (line, column) = match e.line_col {
+            LineColLocation::Pos(position) | LineColLocation::Span(position, _) => position,
+        };
+
+        PgnError {
+            line,
+            column,
+            ..PgnError::new(e.variant.message())
+        }
+    })?;
 
-    let mut games: Vec<Game> = Vec::new();
+    let mut games: Vec<GameTree> = Vec::new();
 
-    for pair in parsed.into_inner() {
-        match pair.as_rule() {
-            Rule::game => match parse_game(pair) {
-                Ok(game) => games.push(game),
-                Err(err) => return Err(err),
-            },
-            Rule::EOI => return Ok(games),
-            _ => unreachable!("Unexpected rule: {:?}", pair.as_rule()),
-        }
+    let root = parsed.into_iter().flat_map(|root| root.into_inner());
+    for pair in root.filter(|pair| pair.as_rule() == Rule::game) {
+        let game = parse_game(pair).map_err(|e| PgnError {
+            game: games.len() + 1,
+            ..e
+        })?;
+
+        games.push(game);
     }
 
-    unreachable!("Expected EOI");
+    Ok(games)
+}
+
+/// Parses all the games in a PGN string, only the main line of each game is kept.
+///
+/// ```
+/// let error = pgn::parse("1. e4 e5 *\n\n1. e4 e4 *").unwrap_err();
+///
+/// assert_eq!(error.game, 2);
+/// assert_eq!((error.line, error.column), (3, 7));
+/// assert_eq!(error.movement.as_deref(), Some("e4"));
+/// assert_eq!(error.to_string(), "Illegal move 'e4' in game 2 at line 3 column 7 from \
+///     'rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1'");
+/// ```
+pub fn parse(string: &str) -> Result<Vec<Game>, PgnError> {
+    Ok(parse_tree(string)?.iter().map(GameTree::to_game).collect())
 }
 
 #[cfg(test)]
@@ -319,4 +259,69 @@ mod tests {
             assert!(!games.is_empty());
         }
     }
+
+    #[test]
+    fn parses_every_san_form() {
+        let input = "1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. 0-0 Bc5 5. d4 exd4 6. e5 d5 7. exf6 dxc4 \
+                     8. Re1+ Be6 9. Ng5 Qxf6 10. Nxe6 fxe6 11. Rxe6+ Kf7 12. Re1 Rad8 1-0";
+        let games = parse(input).unwrap();
+
+        assert_eq!(games[0].history.len(), 24);
+        assert_eq!(games[0].history[6].uci(), "e1g1");
+    }
+
+    #[test]
+    fn starts_from_the_fen_tag() {
+        let input = r#"
+            [SetUp "1"]
+            [FEN "6k1/5ppp/8/8/8/8/5PPP/3r2K1 b - - 0 23"]
+
+            23... Rd2 24. h3 Rxf2 *
+        "#;
+
+        let game = parse(input).unwrap().remove(0);
+        assert_eq!(game.history.len(), 3);
+        assert_eq!(game.board.to_fen(), "6k1/5ppp/8/8/8/7P/5rP1/6K1 w - - 0 1");
+
+        // Without the tag the moves are not legal from the start position
+        assert!(parse("23... Rd2 24. h3 Rxf2 *").is_err());
+    }
+
+    #[test]
+    fn ignores_the_fen_tag_when_set_up_is_off() {
+        let input = r#"
+            [SetUp "0"]
+            [FEN "6k1/5ppp/8/8/8/8/5PPP/3r2K1 b - - 0 23"]
+
+            1. e4 *
+        "#;
+
+        assert_eq!(parse(input).unwrap()[0].history.len(), 1);
+    }
+
+    #[test]
+    fn errors_with_the_location() {
+        let error = parse("[Event \"One\"]\n\n1. e4 e5 2. Nf3 @ *").unwrap_err();
+        assert_eq!((error.game, error.line, error.column), (0, 3, 17));
+        assert_eq!(error.movement, None);
+
+        let error = parse("1. e4 *\n\n[FEN \"8/8/8 w - - 0 1\"]\n\n$1 1. e4 *").unwrap_err();
+        assert_eq!((error.game, error.line, error.column), (2, 5, 1));
+        assert_eq!(error.fen.as_deref(), Some("8/8/8 w - - 0 1"));
+        assert!(error.message.starts_with("Invalid FEN tag"));
+
+        let error = parse("$1 1. e4 *").unwrap_err();
+        assert_eq!((error.game, error.line, error.column), (1, 1, 1));
+        assert_eq!(error.message, "Expected a move before '$1'");
+    }
+
+    #[test]
+    fn errors_on_ambiguous_moves() {
+        // Both knights can go to d2
+        assert!(parse("1. Nf3 Nf6 2. d4 d5 3. Nbd2 *").is_ok());
+        assert_eq!(
+            parse("1. Nf3 Nf6 2. d4 d5 3. Nd2 *").unwrap_err().message,
+            "Ambiguous move 'Nd2'"
+        );
+    }
 }
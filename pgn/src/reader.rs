@@ -0,0 +1,256 @@
+use std::collections::VecDeque;
+use std::io::BufRead;
+
+use common::Game;
+
+use crate::{parse, PgnError};
+
+/// Reads games one at a time from any `BufRead`, so a database can be processed without loading
+/// the whole thing into memory. Each game is parsed on its own so an invalid game is returned as
+/// an error and the reader carries on with the next one.
+///
+/// ```
+/// let input = "[Event \"One\"]\n\n1. e4 e5 *\n\n[Event \"Two\"]\n\n1. e4 Ke7 Ke2 *\n\n[Event \"Three\"]\n\n1. d4 *\n";
+/// let games: Vec<_> = pgn::Reader::new(input.as_bytes()).collect();
+///
+/// assert_eq!(games.len(), 3);
+/// assert!(games[0].is_ok());
+/// assert_eq!(games[1].as_ref().unwrap_err().game, 2);
+/// assert_eq!(games[2].as_ref().unwrap().history.len(), 1);
+/// ```
+pub struct Reader<R> {
+    reader: R,
+    /// The tag line that starts the next game, we only know a game has ended once we have read
+    /// the first line of the next one.
+    next_line: String,
+    /// The number of lines that have been read from the reader.
+    lines: usize,
+    games: VecDeque<Game>,
+    /// The number of games that have been returned so far.
+    index: usize,
+    finished: bool,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            next_line: String::new(),
+            lines: 0,
+            games: VecDeque::new(),
+            index: 0,
+            finished: false,
+        }
+    }
+
+    fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        let mut bytes = Vec::new();
+        match self.reader.read_until(b'\n', &mut bytes)? {
+            0 => Ok(None),
+            _ => {
+                self.lines += 1;
+                Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+            }
+        }
+    }
+
+    /// Reads the text of the next game. A game ends when a tag is found after some moves, or
+    /// when a line of moves ends with the game result so games without tags are split up too.
+    /// Tags and results inside comments are skipped so a comment like `{[%clk 0:03:00]}` that has
+    /// been wrapped onto a new line does not start a new game. The text is returned with the
+    /// number of the line it starts on.
+    fn next_game_text(&mut self) -> std::io::Result<Option<(usize, String)>> {
+        let mut text = std::mem::take(&mut self.next_line);
+        let first_line = match text.is_empty() {
+            true => self.lines + 1,
+            false => self.lines,
+        };
+
+        let mut has_moves = false;
+        let mut in_comment = false;
+
+        while let Some(line) = self.read_line()? {
+            let trimmed = line.trim_start();
+            let is_tag = !in_comment && trimmed.starts_with('[');
+
+            if is_tag && has_moves {
+                self.next_line = line;
+                return Ok(Some((first_line, text)));
+            }
+
+            text.push_str(&line);
+
+            if !is_tag && !trimmed.is_empty() {
+                has_moves = true;
+
+                let (moves, ends_in_comment) = strip_comments(in_comment, &line);
+                in_comment = ends_in_comment;
+
+                let last = moves.split_whitespace().last();
+                if !in_comment && last.is_some_and(|token| RESULTS.contains(&token)) {
+                    return Ok(Some((first_line, text)));
+                }
+            }
+        }
+
+        match text.trim().is_empty() {
+            true => Ok(None),
+            false => Ok(Some((first_line, text))),
+        }
+    }
+}
+
+/// The tokens that end the movetext of a game.
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// Removes the comments from a line of movetext, returning what is left and if we are inside a
+/// brace comment at the end of the line.
+fn strip_comments(mut in_comment: bool, line: &str) -> (String, bool) {
+    let mut moves = String::new();
+    for c in line.chars() {
+        match (in_comment, c) {
+            (true, '}') => in_comment = false,
+            (false, '{') => in_comment = true,
+            // The rest of the line is a comment
+            (false, ';') => break,
+            (false, c) => moves.push(c),
+            _ => {}
+        }
+    }
+
+    (moves, in_comment)
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Game, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(game) = self.games.pop_front() {
+                self.index += 1;
+                return Some(Ok(game));
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            let (first_line, text) = match self.next_game_text() {
+                Ok(Some(game)) => game,
+                Ok(None) => {
+                    self.finished = true;
+                    return None;
+                }
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(PgnError {
+                        game: self.index + 1,
+                        ..PgnError::new(e.to_string())
+                    }));
+                }
+            };
+
+            match parse(&text) {
+                // Games are only split at the end of a line, so we may still get more than one back
+                Ok(games) => self.games.extend(games),
+                Err(e) => {
+                    // Move the location from the game text to the whole input
+                    self.index += e.game.max(1);
+                    return Some(Err(PgnError {
+                        game: self.index,
+                        line: match e.line {
+                            0 => 0,
+                            line => line + first_line - 1,
+                        },
+                        ..e
+                    }));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(file: &str) -> String {
+        let file_path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), file);
+        std::fs::read_to_string(file_path).unwrap()
+    }
+
+    #[test]
+    fn reads_the_same_games_as_parse() {
+        let files = [
+            "data/first.pgn",
+            "data/second.pgn",
+            "data/third.pgn",
+            "data/fourth.pgn",
+            "data/fifth.pgn",
+            "data/sixth.pgn",
+        ];
+
+        let input: String = files.iter().map(|file| read(file) + "\n").collect();
+        let expected = parse(&input).unwrap();
+        let games: Vec<Game> = Reader::new(input.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(games.len(), expected.len());
+        for (left, right) in games.iter().zip(expected.iter()) {
+            assert_eq!(left.metadata, right.metadata);
+            assert_eq!(left.history, right.history);
+        }
+    }
+
+    #[test]
+    fn does_not_split_on_tags_in_comments() {
+        let input = "[Event \"One\"]\n\n1. e4 {\n[%clk 0:03:00]} e5 ; {\n2. Nf3 *\n\
+                     [Event \"Two\"]\n\n1. d4 *\n";
+        let games: Vec<_> = Reader::new(input.as_bytes()).collect();
+
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].as_ref().unwrap().history.len(), 3);
+        assert_eq!(games[1].as_ref().unwrap().history.len(), 1);
+    }
+
+    #[test]
+    fn splits_games_without_tags() {
+        let input = "1. e4 e5 2. Nf3 {ends 1-0} Nc6 1-0\n\n1. e4 e4 *\n1. d4 d5 1/2-1/2\n";
+        let mut reader = Reader::new(input.as_bytes());
+
+        // Each game is read on its own rather than all of them at once
+        assert_eq!(reader.next_game_text().unwrap().unwrap().0, 1);
+        assert_eq!(reader.next_game_text().unwrap().unwrap().0, 2);
+
+        let games: Vec<_> = Reader::new(input.as_bytes()).collect();
+        assert_eq!(games.len(), 3);
+        assert_eq!(games[0].as_ref().unwrap().history.len(), 4);
+
+        let error = games[1].as_ref().unwrap_err();
+        assert_eq!((error.game, error.line), (2, 3));
+        assert_eq!(games[2].as_ref().unwrap().history.len(), 2);
+    }
+
+    #[test]
+    fn skips_invalid_games() {
+        let input = format!(
+            "{}\n[Event \"Broken\"]\n\n1. e4 e4 *\n\n[Event \"Three\"]\n\n1. d4 d5 *\n1. c4 *\n",
+            read("data/first.pgn")
+        );
+
+        let games: Vec<_> = Reader::new(input.as_bytes()).collect();
+
+        let lines = read("data/first.pgn").lines().count();
+
+        assert_eq!(games.len(), 4);
+        assert!(games[0].is_ok());
+
+        let error = games[1].as_ref().unwrap_err();
+        assert_eq!(error.game, 2);
+        assert_eq!((error.line, error.column), (lines + 4, 7));
+        assert_eq!(error.movement.as_deref(), Some("e4"));
+        assert!(games[2].is_ok());
+        assert_eq!(games[3].as_ref().unwrap().history.len(), 1);
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use common::{Board, Game, GameResult, ResolvedMovement};
+
+/// A command embedded in a comment, like the `%clk` in `{[%clk 0:03:00]}`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Command {
+    pub name: String,
+    pub value: String,
+}
+
+/// A comment with any commands pulled out of it. The whitespace in the text is collapsed down to
+/// single spaces so comments that have been wrapped over lines can be written out again.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub commands: Vec<Command>,
+}
+
+impl Comment {
+    /// Parses the text inside the braces of a comment, pulling out all the `[%name value]`
+    /// commands.
+    ///
+    /// ```
+    /// let comment = pgn::Comment::parse("[%eval 0.17] [%clk 0:03:00] Good move");
+    /// assert_eq!(comment.text, "Good move");
+    /// assert_eq!(comment.commands[0].name, "eval");
+    /// assert_eq!(comment.commands[1].value, "0:03:00");
+    /// ```
+    pub fn parse(input: &str) -> Comment {
+        let mut text = String::new();
+        let mut commands = Vec::new();
+        let mut rest = input;
+
+        while let Some(start) = rest.find("[%") {
+            let Some(length) = rest[start..].find(']') else {
+                break;
+            };
+
+            text.push_str(&rest[..start]);
+            text.push(' ');
+
+            let command = &rest[start + 2..start + length];
+            let mut parts = command.trim().splitn(2, char::is_whitespace);
+            commands.push(Command {
+                name: parts.next().unwrap_or_default().to_string(),
+                value: parts
+                    .next()
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .collect::<Vec<&str>>()
+                    .join(" "),
+            });
+
+            rest = &rest[start + length + 1..];
+        }
+
+        text.push_str(rest);
+
+        Comment {
+            text: text.split_whitespace().collect::<Vec<&str>>().join(" "),
+            commands,
+        }
+    }
+
+    pub fn command(&self, name: &str) -> Option<&str> {
+        self.commands
+            .iter()
+            .find(|command| command.name == name)
+            .map(|command| command.value.as_str())
+    }
+}
+
+impl std::fmt::Display for Comment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts: Vec<String> = self
+            .commands
+            .iter()
+            .map(|command| format!("[%{} {}]", command.name, command.value))
+            .collect();
+
+        if !self.text.is_empty() {
+            parts.push(self.text.clone());
+        }
+
+        write!(f, "{{{}}}", parts.join(" "))
+    }
+}
+
+/// The engine evaluation from a `%eval` command, always from whites point of view.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Evaluation {
+    Centipawns(i32),
+    /// Mate in the number of moves, negative if black is mating.
+    Mate(i32),
+}
+
+/// A move in the tree along with everything that has been said about it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MoveNode {
+    pub movement: ResolvedMovement,
+    /// The numeric annotation glyphs, `!` is read in as `$1`, `?` as `$2` and so on.
+    pub nags: Vec<u8>,
+    /// Comments before the move, this is only used at the start of a game or variation.
+    pub comments_before: Vec<Comment>,
+    pub comments_after: Vec<Comment>,
+    /// Alternatives to this move, each one starts from the position before this move.
+    pub variations: Vec<Vec<MoveNode>>,
+}
+
+impl MoveNode {
+    pub fn new(movement: ResolvedMovement) -> Self {
+        Self {
+            movement,
+            ..Default::default()
+        }
+    }
+
+    /// Finds the value of a command in the comments after the move.
+    pub fn command(&self, name: &str) -> Option<&str> {
+        self.comments_after
+            .iter()
+            .find_map(|comment| comment.command(name))
+    }
+
+    /// The time left on the clock after the move from the `%clk` command.
+    pub fn clock(&self) -> Option<Duration> {
+        let mut seconds = 0.0;
+        for part in self.command("clk")?.split(':') {
+            seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+        }
+
+        Some(Duration::from_secs_f64(seconds))
+    }
+
+    /// The evaluation after the move from the `%eval` command. This is either in pawns like
+    /// `0.17` or a mate like `#-3`.
+    pub fn eval(&self) -> Option<Evaluation> {
+        let value = self.command("eval")?;
+        match value.strip_prefix('#') {
+            Some(mate) => mate.parse().ok().map(Evaluation::Mate),
+            None => value
+                .parse::<f64>()
+                .ok()
+                .map(|pawns| Evaluation::Centipawns((pawns * 100.0).round() as i32)),
+        }
+    }
+}
+
+/// A game with all of its variations, comments and annotations, so it can be written back out
+/// without losing anything.
+#[derive(Debug, Default)]
+pub struct GameTree {
+    pub metadata: HashMap<String, String>,
+    /// The position before the first move.
+    pub board: Board,
+    pub moves: Vec<MoveNode>,
+    /// Comments that are not attached to a move, this will only happen in games without any
+    /// moves.
+    pub comments: Vec<Comment>,
+    pub result: GameResult,
+}
+
+impl GameTree {
+    /// Plays the main line, dropping all of the variations and comments.
+    pub fn to_game(&self) -> Game {
+        let mut game = Game {
+            metadata: self.metadata.clone(),
+            board: self.board,
+            result: self.result.clone(),
+            ..Default::default()
+        };
+
+        for node in &self.moves {
+            game.move_piece(node.movement);
+        }
+
+        game
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_comments_with_commands() {
+        let comment = Comment::parse("[%clk\n0:03:00]");
+        assert_eq!(comment.text, "");
+        assert_eq!(comment.command("clk"), Some("0:03:00"));
+        assert_eq!(comment.to_string(), "{[%clk 0:03:00]}");
+
+        let comment = Comment::parse(" Only  some\ntext ");
+        assert!(comment.commands.is_empty());
+        assert_eq!(comment.to_string(), "{Only some text}");
+    }
+
+    #[test]
+    fn reads_the_clock_and_eval() {
+        let mut node = MoveNode::default();
+        node.comments_after
+            .push(Comment::parse("[%eval -1.25] [%clk 1:02:03.5]"));
+
+        assert_eq!(node.clock(), Some(Duration::from_secs_f64(3723.5)));
+        assert_eq!(node.eval(), Some(Evaluation::Centipawns(-125)));
+
+        node.comments_after[0] = Comment::parse("[%eval #-3]");
+        assert_eq!(node.eval(), Some(Evaluation::Mate(-3)));
+        assert_eq!(node.clock(), None);
+    }
+}
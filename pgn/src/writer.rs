@@ -0,0 +1,389 @@
+use common::{Board, Color, Game, GameResult};
+
+use crate::{start_position, GameTree, MoveNode, PgnError};
+
+/// The tags that must be in every game, they are always written first and in this order.
+///
+/// See: https://www.saremba.de/chessgml/standards/pgn/pgn-complete.htm#c8.1.1
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+const MAX_LINE_LENGTH: usize = 80;
+
+fn result_token(result: &GameResult) -> &'static str {
+    match result {
+        GameResult::WhiteWin => "1-0",
+        GameResult::BlackWin => "0-1",
+        GameResult::Draw => "1/2-1/2",
+        GameResult::InProgress => "*",
+    }
+}
+
+/// Joins the tokens with spaces, starting a new line before any token that would take the line
+/// over the max length.
+fn wrap(tokens: &[String]) -> String {
+    let mut output = String::new();
+    let mut line_length = 0;
+
+    for token in tokens {
+        if line_length > 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+            output.push('\n');
+            line_length = 0;
+        } else if line_length > 0 {
+            output.push(' ');
+            line_length += 1;
+        }
+
+        output.push_str(token);
+        line_length += token.len();
+    }
+
+    output
+}
+
+/// Writes the tokens for a line of moves, the board is the position before the first move.
+fn write_line(
+    tokens: &mut Vec<String>,
+    moves: &[MoveNode],
+    mut board: Board,
+    mut move_number: usize,
+) {
+    // Blacks moves need a number at the start of a line, or after anything that breaks up the
+    // moves like a comment or a variation
+    let mut needs_number = true;
+
+    for node in moves {
+        for comment in &node.comments_before {
+            tokens.push(comment.to_string());
+        }
+
+        match board.turn {
+            Color::White => tokens.push(format!("{move_number}.")),
+            Color::Black if needs_number => tokens.push(format!("{move_number}...")),
+            Color::Black => {}
+        }
+
+        tokens.push(node.movement.to_san(&board));
+        needs_number = false;
+
+        for nag in &node.nags {
+            tokens.push(format!("${nag}"));
+        }
+
+        for comment in &node.comments_after {
+            tokens.push(comment.to_string());
+            needs_number = true;
+        }
+
+        for variation in &node.variations {
+            let mut variation_tokens = Vec::new();
+            write_line(&mut variation_tokens, variation, board, move_number);
+
+            match variation_tokens.first_mut() {
+                Some(first) => first.insert(0, '('),
+                None => variation_tokens.push("(".to_string()),
+            }
+
+            if let Some(last) = variation_tokens.last_mut() {
+                last.push(')');
+            }
+
+            tokens.append(&mut variation_tokens);
+            needs_number = true;
+        }
+
+        if board.turn == Color::Black {
+            move_number += 1;
+        }
+
+        board.move_piece(node.movement);
+    }
+}
+
+/// Writes a game out as a PGN string in the export format. The seven tag roster is written first,
+/// with `?` for any tags that are missing, then the rest of the tags in alphabetical order. The
+/// moves are replayed from the `FEN` tag if there is one, otherwise the start position.
+///
+/// ```
+/// use common::{Game, GameResult};
+///
+/// let mut game = pgn::parse("1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0").unwrap().remove(0);
+/// game.metadata.insert("White".to_string(), "Scholar".to_string());
+///
+/// let output = pgn::write(&game).unwrap();
+/// assert!(output.starts_with("[Event \"?\"]\n"));
+/// assert!(output.contains("[White \"Scholar\"]\n"));
+/// assert!(output.ends_with("\n\n1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0\n"));
+/// ```
+pub fn write(game: &Game) -> Result<String, PgnError> {
+    write_tree(&GameTree {
+        metadata: game.metadata.clone(),
+        board: start_position(&game.metadata)?,
+        moves: game.history.iter().copied().map(MoveNode::new).collect(),
+        comments: Vec::new(),
+        result: game.result.clone(),
+    })
+}
+
+/// Writes a game out with all of its variations, comments and annotations. The tags are written
+/// the same way as [`write`], and the moves are written from the board in the tree. Any
+/// traditional annotations like `!?` are written as NAGs.
+///
+/// ```
+/// let input = "1. e4 {[%clk 0:03:00]} e5 (1... c5 $1 {Sicilian} (1... e6)) 2. Nf3 *";
+/// let output = pgn::write_tree(&pgn::parse_tree(input).unwrap()[0]).unwrap();
+///
+/// assert!(output.ends_with("\n\n1. e4 {[%clk 0:03:00]} 1... e5 (1... c5 $1 {Sicilian} (1... e6)) 2. Nf3 *\n"));
+/// ```
+pub fn write_tree(game: &GameTree) -> Result<String, PgnError> {
+    let mut output = String::new();
+
+    for (tag, default) in SEVEN_TAG_ROSTER {
+        let value = match tag {
+            "Result" => result_token(&game.result),
+            _ => game
+                .metadata
+                .get(tag)
+                .map(|v| v.as_str())
+                .unwrap_or(default),
+        };
+
+        output.push_str(&format!("[{tag} \"{}\"]\n", escape(value)));
+    }
+
+    let mut tags: Vec<(&String, &String)> = game
+        .metadata
+        .iter()
+        .filter(|(tag, _)| !SEVEN_TAG_ROSTER.iter().any(|(t, _)| t == tag))
+        .collect();
+    tags.sort();
+
+    for (tag, value) in tags {
+        output.push_str(&format!("[{tag} \"{}\"]\n", escape(value)));
+    }
+
+    output.push('\n');
+
+    let move_number = match game.metadata.get("FEN") {
+        Some(fen) if game.metadata.get("SetUp").is_none_or(|v| v == "1") => full_move_number(fen),
+        _ => 1,
+    };
+
+    let mut tokens: Vec<String> = game.comments.iter().map(|c| c.to_string()).collect();
+    write_line(&mut tokens, &game.moves, game.board, move_number);
+    tokens.push(result_token(&game.result).to_string());
+
+    output.push_str(&wrap(&tokens));
+    output.push('\n');
+
+    Ok(output)
+}
+
+fn full_move_number(fen: &str) -> usize {
+    fen.split_whitespace()
+        .nth(5)
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(1)
+}
+
+/// Quotes and backslashes need escaping inside a tag value.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, parse_tree};
+    use common::{Piece, ResolvedMovement};
+
+    fn read(file: &str) -> String {
+        let file_path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), file);
+        std::fs::read_to_string(file_path).unwrap()
+    }
+
+    #[test]
+    fn round_trips_all_the_files() {
+        let files = [
+            "data/first.pgn",
+            "data/second.pgn",
+            "data/third.pgn",
+            "data/fourth.pgn",
+            "data/fifth.pgn",
+            "data/sixth.pgn",
+        ];
+
+        for file in files {
+            let games = parse(&read(file)).unwrap();
+            for game in games {
+                let output = write(&game).unwrap();
+                let parsed = parse(&output).unwrap();
+
+                assert_eq!(parsed.len(), 1, "{file}");
+                let parsed = &parsed[0];
+
+                assert_eq!(parsed.metadata, game.metadata, "{file}");
+                assert_eq!(result_token(&parsed.result), result_token(&game.result));
+                assert_eq!(parsed.history.len(), game.history.len(), "{file}");
+                for (left, right) in parsed.history.iter().zip(game.history.iter()) {
+                    assert_eq!(left.uci(), right.uci(), "{file}");
+                }
+
+                // Writing the parsed game again should give us exactly the same output
+                assert_eq!(write(parsed).unwrap(), output, "{file}");
+
+                for line in output.lines() {
+                    assert!(line.len() <= MAX_LINE_LENGTH, "{file}: {line}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_the_trees_of_all_the_files() {
+        let files = [
+            "data/first.pgn",
+            "data/second.pgn",
+            "data/third.pgn",
+            "data/fourth.pgn",
+            "data/fifth.pgn",
+            "data/sixth.pgn",
+        ];
+
+        for file in files {
+            for game in parse_tree(&read(file)).unwrap() {
+                let output = write_tree(&game).unwrap();
+                let parsed = parse_tree(&output).unwrap().remove(0);
+
+                assert_eq!(parsed.metadata, game.metadata, "{file}");
+                assert_eq!(parsed.moves, game.moves, "{file}");
+                assert_eq!(write_tree(&parsed).unwrap(), output, "{file}");
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_variations_comments_and_nags() {
+        let input = r#"
+            [Event "Analysis"]
+
+            {The Ruy Lopez} 1. e4 e5 2. Nf3 $1 {[%eval 0.25] [%clk 0:02:58] Best} 2... Nc6
+            (2... Nf6 3. Nxe5 (3. d4 exd4 $6 ; Line comment
+            ) 3... d6) (2... d6?! {Philidor}) 3. Bb5 a6!? 4. Ba4 *
+        "#;
+
+        let game = parse_tree(input).unwrap().remove(0);
+        assert_eq!(game.moves[0].comments_before[0].text, "The Ruy Lopez");
+        assert_eq!(game.moves[2].nags, vec![1]);
+        assert_eq!(
+            game.moves[2].eval(),
+            Some(crate::Evaluation::Centipawns(25))
+        );
+        assert_eq!(game.moves[3].variations.len(), 2);
+        assert_eq!(
+            game.moves[3].variations[0][1].variations[0][1].nags,
+            vec![6]
+        );
+        assert_eq!(game.moves[3].variations[1][0].nags, vec![6]);
+        assert_eq!(game.moves[5].nags, vec![5]);
+
+        let output = write_tree(&game).unwrap();
+        assert!(output.ends_with(
+            "\n\n{The Ruy Lopez} 1. e4 e5 2. Nf3 $1 {[%eval 0.25] [%clk 0:02:58] Best} 2... Nc6\n\
+             (2... Nf6 3. Nxe5 (3. d4 exd4 $6 {Line comment}) 3... d6) (2... d6 $6\n\
+             {Philidor}) 3. Bb5 a6 $5 4. Ba4 *\n"
+        ));
+
+        let parsed = parse_tree(&output).unwrap().remove(0);
+        assert_eq!(parsed.moves, game.moves);
+        assert_eq!(parsed.to_game().history.len(), 7);
+    }
+
+    #[test]
+    fn writes_the_seven_tag_roster_in_order() {
+        let game = parse(&read("data/first.pgn")).unwrap().remove(0);
+        let output = write(&game).unwrap();
+
+        let tags: Vec<&str> = output
+            .lines()
+            .take_while(|line| line.starts_with('['))
+            .map(|line| line[1..].split(' ').next().unwrap())
+            .collect();
+
+        assert_eq!(
+            tags,
+            [
+                "Event",
+                "Site",
+                "Date",
+                "Round",
+                "White",
+                "Black",
+                "Result",
+                "BlackElo",
+                "TimeControl",
+                "WhiteElo"
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_check_mate_and_promotions() {
+        let game = parse(&read("data/first.pgn")).unwrap().remove(0);
+        let output = write(&game).unwrap();
+
+        assert!(output.contains("52. Ng5+ Kh6"));
+        assert!(output.contains("65. f8=Q b3"));
+        assert!(output.contains("73. Qdd4 1-0"));
+        assert!(output.contains("5. O-O Be7"));
+    }
+
+    #[test]
+    fn writes_black_to_move_from_a_fen() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 12";
+        let mut game = common::Game {
+            board: Board::from_fen_str(fen).unwrap(),
+            ..Default::default()
+        };
+
+        game.metadata.insert("FEN".to_string(), fen.to_string());
+        game.metadata.insert("SetUp".to_string(), "1".to_string());
+
+        for (piece, from, to) in [
+            (Piece::King, common::Square::E8, common::Square::D7),
+            (Piece::Pawn, common::Square::E2, common::Square::E4),
+        ] {
+            game.move_piece(ResolvedMovement {
+                piece,
+                from,
+                to,
+                capture: None,
+                promotion: None,
+            });
+        }
+
+        let output = write(&game).unwrap();
+        assert!(output.ends_with("\n\n12... Kd7 13. e4 *\n"));
+
+        let parsed = parse(&output).unwrap().remove(0);
+        assert_eq!(parsed.history, game.history);
+        assert_eq!(parsed.board.to_fen(), game.board.to_fen());
+    }
+
+    #[test]
+    fn escapes_tag_values() {
+        let mut game = common::Game::default();
+        game.metadata
+            .insert("Event".to_string(), "The \"Big\" One".to_string());
+
+        let output = write(&game).unwrap();
+        assert!(output.starts_with("[Event \"The \\\"Big\\\" One\"]\n"));
+    }
+}
@@ -0,0 +1,157 @@
+use common::{attacked_squares, is_in_check, Board, GameResult};
+use engine::evaluation::endgame::endgames;
+
+use std::io::BufReader;
+
+/// The number of moves at the start of each game that are skipped when loading positions from a
+/// PGN. These are most likely from an opening book and don't say much about the evaluation.
+const OPENING_PLIES: usize = 8;
+
+/// A quiet position labelled with the result of the game it came from.
+pub struct Position {
+    pub board: Board,
+    /// The result from whites point of view, 1.0 for a white win, 0.5 for a draw and 0.0 for a
+    /// black win.
+    pub result: f64,
+}
+
+/// Parses a result out of the rest of an EPD line. This supports the `c9 "1-0";` opcode and the
+/// `[1.0]` style used by most of the published tuning sets.
+fn parse_result(input: &str) -> Option<f64> {
+    if input.contains("1/2-1/2") || input.contains("[0.5]") {
+        Some(0.5)
+    } else if input.contains("1-0") || input.contains("[1.0]") {
+        Some(1.0)
+    } else if input.contains("0-1") || input.contains("[0.0]") {
+        Some(0.0)
+    } else {
+        None
+    }
+}
+
+/// Parses a single EPD line, the first four fields are the position and the result is somewhere
+/// in the operations after them.
+pub fn parse_epd_line(line: &str) -> Result<Position, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 5 {
+        return Err(format!("Invalid EPD line '{line}'"));
+    }
+
+    let board = Board::from_fen_str(&format!("{} 0 1", fields[0..4].join(" ")))?;
+    let result = match parse_result(&fields[4..].join(" ")) {
+        Some(result) => result,
+        None => return Err(format!("Missing the game result in '{line}'")),
+    };
+
+    Ok(Position { board, result })
+}
+
+/// Positions scored by one of the specialised end game evaluators don't use any of the
+/// parameters, so they would only add noise to the fit.
+fn uses_the_parameters(board: &Board) -> bool {
+    endgames().probe(board).is_none()
+}
+
+pub fn load_epd_file(path: &str) -> Result<Vec<Position>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+    let mut positions = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(parse_epd_line)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    positions.retain(|position| uses_the_parameters(&position.board));
+    Ok(positions)
+}
+
+/// Loads all the quiet positions from a PGN file. A position is quiet if the side to move is not
+/// in check and the move played from it is not a capture or a promotion.
+pub fn load_pgn_file(path: &str) -> Result<Vec<Position>, String> {
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+
+    let mut positions = Vec::new();
+    for game in pgn::Reader::new(BufReader::new(file)) {
+        let game = match game {
+            Ok(game) => game,
+            Err(err) => {
+                println!("Skipping invalid game in {path}\n    {err}");
+                continue;
+            }
+        };
+
+        let result = match game.result {
+            GameResult::WhiteWin => 1.0,
+            GameResult::BlackWin => 0.0,
+            GameResult::Draw => 0.5,
+            GameResult::InProgress => continue,
+        };
+
+        let mut board = pgn::start_position(&game.metadata).map_err(|e| e.to_string())?;
+        for (ply, movement) in game.history.iter().enumerate() {
+            let in_check = is_in_check(&board, &attacked_squares(&board, &board.turn.opposite()));
+            let is_quiet = movement.capture.is_none() && movement.promotion.is_none();
+
+            if ply >= OPENING_PLIES && is_quiet && !in_check && uses_the_parameters(&board) {
+                positions.push(Position { board, result });
+            }
+
+            board.move_piece(*movement);
+        }
+    }
+
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::Color;
+
+    #[test]
+    fn parses_epd_results() {
+        let position =
+            parse_epd_line("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - c9 \"1-0\";")
+                .unwrap();
+        assert_eq!(position.result, 1.0);
+        assert_eq!(position.board.turn, Color::Black);
+
+        let position = parse_epd_line("8/8/8/8/8/8/8/K1k5 w - - [0.5]").unwrap();
+        assert_eq!(position.result, 0.5);
+
+        let position = parse_epd_line("8/8/8/8/8/8/8/K1k5 w - - c9 \"0-1\";").unwrap();
+        assert_eq!(position.result, 0.0);
+    }
+
+    #[test]
+    fn errors_without_a_result() {
+        assert!(parse_epd_line("8/8/8/8/8/8/8/K1k5 w - - bm Kb2;").is_err());
+        assert!(parse_epd_line("8/8/8/8").is_err());
+    }
+
+    #[test]
+    fn skips_positions_with_an_endgame_evaluator() {
+        let path = std::env::temp_dir().join("tune-skips-endgames.epd");
+        std::fs::write(
+            &path,
+            "8/8/8/4k3/8/8/8/R3K3 w - - [1.0]\n\
+             8/8/8/4k3/8/8/8/K7 w - - [0.5]\n\
+             rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - [0.5]\n",
+        )
+        .unwrap();
+
+        let positions = load_epd_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].board.turn, Color::Black);
+    }
+
+    #[test]
+    fn loads_positions_from_a_pgn() {
+        let path = format!("{}/../pgn/data/first.pgn", env!("CARGO_MANIFEST_DIR"));
+        let positions = load_pgn_file(&path).unwrap();
+
+        assert!(!positions.is_empty());
+    }
+}
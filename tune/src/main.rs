@@ -0,0 +1,113 @@
+mod dataset;
+mod parameters;
+mod tuner;
+
+use cli::{deindent, ArgBuilder};
+use engine::evaluation::Parameters;
+
+struct Args {
+    pub files: Vec<String>,
+    pub output: Option<String>,
+    pub iterations: usize,
+    pub step: i32,
+}
+
+impl Args {
+    fn new() -> Result<Self, String> {
+        let args = ArgBuilder::new(std::env::args().collect());
+        if args.bool("-h", "--help") {
+            return Err(deindent(
+                r#"
+                    Usage: tune [OPTIONS]
+
+                        --help, -h                Display this help message
+                        --file, -f <file>         The EPD or PGN files to load the positions from
+                        --output, -o <file>       Write the tuned constants to a file instead of stdout
+                        --iterations, -i <count>  The maximum number of passes over the weights (default 100)
+                        --step, -s <size>         How much to change each weight by (default 1)
+                "#,
+            ));
+        }
+
+        let iterations = match args.string("-i", "--iterations") {
+            Ok(value) => value
+                .parse()
+                .map_err(|_| format!("Invalid iterations '{value}'"))?,
+            Err(_) => 100,
+        };
+
+        let step = match args.string("-s", "--step") {
+            Ok(value) => value
+                .parse()
+                .map_err(|_| format!("Invalid step '{value}'"))?,
+            Err(_) => 1,
+        };
+
+        Ok(Args {
+            files: args.string_list("-f", "--file")?,
+            output: args.string("-o", "--output").ok(),
+            iterations,
+            step,
+        })
+    }
+}
+
+fn main() {
+    let args = match Args::new() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
+    };
+
+    let mut positions = Vec::new();
+    for file in args.files.iter() {
+        let load_result = match std::path::Path::new(file).extension() {
+            Some(ext) if ext == "epd" => dataset::load_epd_file(file),
+            Some(ext) if ext == "pgn" => dataset::load_pgn_file(file),
+            Some(ext) => Err(format!("Invalid file extension {}", ext.to_string_lossy())),
+            None => Err(format!("Unable to determine the file type of {file}")),
+        };
+
+        match load_result {
+            Ok(mut loaded) => positions.append(&mut loaded),
+            Err(e) => {
+                println!("Error loading {file}\n    {e}");
+                return;
+            }
+        }
+    }
+
+    println!("Loaded {} positions", positions.len());
+
+    let params = Parameters::DEFAULT;
+    let k = tuner::optimise_k(&positions, &params);
+    println!(
+        "K = {k:.3}, starting error {:.6}",
+        tuner::mean_squared_error(&positions, &params, k)
+    );
+
+    let output = args.output.clone();
+    let tuned = tuner::local_search(
+        &positions,
+        &params,
+        k,
+        args.step,
+        args.iterations,
+        &mut |iteration, error, params| {
+            println!("Iteration {iteration}, error {error:.6}");
+
+            // Save the progress after every pass so a long run can be stopped at any time
+            if let Some(path) = &output {
+                if let Err(e) = std::fs::write(path, parameters::emit(params)) {
+                    println!("Unable to write {path}\n    {e}");
+                }
+            }
+        },
+    );
+
+    if output.is_none() {
+        println!("{}", parameters::emit(&tuned));
+    }
+}
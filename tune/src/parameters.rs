@@ -0,0 +1,204 @@
+use engine::evaluation::{Parameters, Score};
+
+/// Walks over every weight in the parameters in a fixed order. This is the one place that knows
+/// the layout so the parameters can be flattened into a vector for the tuner and put back again.
+fn visit(params: &mut Parameters, f: &mut dyn FnMut(&mut i32)) {
+    let score = |score: &mut Score, f: &mut dyn FnMut(&mut i32)| {
+        f(&mut score.mg);
+        f(&mut score.eg);
+    };
+
+    // The king is never counted in the material so its value is left out, the same goes for the
+    // king and pawn mobility
+    params.piece_value[1..].iter_mut().for_each(&mut *f);
+    for table in params.piece_square.iter_mut() {
+        table.iter_mut().for_each(&mut *f);
+    }
+
+    params.mobility_bonus.iter_mut().for_each(|s| score(s, f));
+    params.mobility_baseline[1..5].iter_mut().for_each(&mut *f);
+    params.king_attack_weight.iter_mut().for_each(&mut *f);
+    params.king_attack_penalty.iter_mut().for_each(&mut *f);
+    params.pawn_shield.iter_mut().for_each(&mut *f);
+    score(&mut params.doubled_pawn, f);
+    score(&mut params.isolated_pawn, f);
+    params.passed_pawn.iter_mut().for_each(|s| score(s, f));
+    score(&mut params.bishop_pair, f);
+    score(&mut params.rook_open_file, f);
+    score(&mut params.rook_semi_open_file, f);
+    score(&mut params.rook_on_seventh, f);
+    score(&mut params.knight_outpost, f);
+}
+
+pub fn to_vec(params: &Parameters) -> Vec<i32> {
+    let mut params = params.clone();
+    let mut values = Vec::new();
+    visit(&mut params, &mut |value| values.push(*value));
+
+    values
+}
+
+pub fn from_slice(values: &[i32]) -> Parameters {
+    let mut params = Parameters::DEFAULT;
+    let mut it = values.iter();
+    visit(&mut params, &mut |value| {
+        *value = *it.next().expect("Not enough values for the parameters")
+    });
+
+    params
+}
+
+fn ints(values: &[i32]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn scores(values: &[Score]) -> String {
+    values
+        .iter()
+        .map(|s| format!("    Score::new({}, {}),\n", s.mg, s.eg))
+        .collect()
+}
+
+fn table(name: &str, values: &[i32; 64]) -> String {
+    let mut output = format!("#[rustfmt::skip]\nconst {name}: [i32; 64] = [\n");
+    for rank in values.chunks(8) {
+        let row = rank.iter().map(|v| format!("{v:>4},")).collect::<String>();
+        output.push_str(&format!("   {row}\n"));
+    }
+
+    output.push_str("];\n");
+    output
+}
+
+/// Formats the parameters as the Rust constants in `engine/src/evaluation.rs` so they can be
+/// pasted back over the old values.
+pub fn emit(params: &Parameters) -> String {
+    let mut output = String::new();
+
+    let names = [
+        "KING_SCORE",
+        "QUEEN_SCORE",
+        "ROOK_SCORE",
+        "BISHOP_SCORE",
+        "KNIGHT_SCORE",
+        "PAWN_SCORE",
+    ];
+
+    for (name, values) in names.iter().zip(params.piece_square.iter()) {
+        output.push_str(&table(name, values));
+        output.push('\n');
+    }
+
+    output.push_str(&format!(
+        "const PIECE_VALUE: [i32; 6] = [{}];\n\n",
+        ints(&params.piece_value)
+    ));
+    output.push_str(&format!(
+        "const MOBILITY_BONUS: [Score; 6] = [\n{}];\n\n",
+        scores(&params.mobility_bonus)
+    ));
+    output.push_str(&format!(
+        "const MOBILITY_BASELINE: [i32; 6] = [{}];\n\n",
+        ints(&params.mobility_baseline)
+    ));
+    output.push_str(&format!(
+        "const KING_ATTACK_WEIGHT: [i32; 6] = [{}];\n\n",
+        ints(&params.king_attack_weight)
+    ));
+
+    output.push_str("#[rustfmt::skip]\nconst KING_ATTACK_PENALTY: [i32; 24] = [\n");
+    for row in params.king_attack_penalty.chunks(8) {
+        output.push_str(&format!("    {},\n", ints(row)));
+    }
+    output.push_str("];\n\n");
+
+    output.push_str(&format!(
+        "const PAWN_SHIELD: [i32; 2] = [{}];\n\n",
+        ints(&params.pawn_shield)
+    ));
+
+    let single = [
+        ("DOUBLED_PAWN", params.doubled_pawn),
+        ("ISOLATED_PAWN", params.isolated_pawn),
+    ];
+
+    for (name, score) in single {
+        output.push_str(&format!(
+            "const {name}: Score = Score::new({}, {});\n",
+            score.mg, score.eg
+        ));
+    }
+
+    output.push_str(&format!(
+        "\nconst PASSED_PAWN: [Score; 8] = [\n{}];\n\n",
+        scores(&params.passed_pawn)
+    ));
+
+    let single = [
+        ("BISHOP_PAIR", params.bishop_pair),
+        ("ROOK_OPEN_FILE", params.rook_open_file),
+        ("ROOK_SEMI_OPEN_FILE", params.rook_semi_open_file),
+        ("ROOK_ON_SEVENTH", params.rook_on_seventh),
+        ("KNIGHT_OUTPOST", params.knight_outpost),
+    ];
+
+    for (name, score) in single {
+        output.push_str(&format!(
+            "const {name}: Score = Score::new({}, {});\n",
+            score.mg, score.eg
+        ));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_the_parameters() {
+        let values = to_vec(&Parameters::DEFAULT);
+        assert_eq!(from_slice(&values), Parameters::DEFAULT);
+    }
+
+    #[test]
+    fn changes_a_single_weight() {
+        let mut values = to_vec(&Parameters::DEFAULT);
+        let last = values.len() - 1;
+        values[last] += 5;
+
+        let params = from_slice(&values);
+        assert_eq!(
+            params.knight_outpost.eg,
+            Parameters::DEFAULT.knight_outpost.eg + 5
+        );
+    }
+
+    #[test]
+    fn does_not_tune_the_king_value() {
+        let mut values = to_vec(&Parameters::DEFAULT);
+        values[0] += 5;
+
+        let params = from_slice(&values);
+        assert_eq!(params.piece_value[0], Parameters::DEFAULT.piece_value[0]);
+        assert_eq!(
+            params.piece_value[1],
+            Parameters::DEFAULT.piece_value[1] + 5
+        );
+    }
+
+    #[test]
+    fn emits_the_constants() {
+        let output = emit(&Parameters::DEFAULT);
+
+        assert!(output.contains("const PIECE_VALUE: [i32; 6] = [0, 900, 500, 300, 300, 100];"));
+        assert!(output.contains("const MOBILITY_BASELINE: [i32; 6] = [0, 13, 7, 7, 4, 0];"));
+        assert!(output.contains("const BISHOP_PAIR: Score = Score::new(30, 50);"));
+        assert!(output.contains("const PAWN_SCORE: [i32; 64] = ["));
+    }
+}
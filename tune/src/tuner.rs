@@ -0,0 +1,153 @@
+use common::Color;
+use engine::evaluation::{evaluate_with_parameters, NoTrace, Parameters};
+
+use crate::dataset::Position;
+use crate::parameters;
+
+/// Converts an evaluation in centipawns into the expected score of the game between zero and one.
+///
+/// See: https://www.chessprogramming.org/Texel%27s_Tuning_Method
+pub fn sigmoid(k: f64, eval: i32) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval as f64 / 400.0))
+}
+
+/// The static evaluation from whites point of view.
+fn white_eval(position: &Position, params: &Parameters) -> i32 {
+    let eval = evaluate_with_parameters(&position.board, params, &mut NoTrace);
+    match position.board.turn {
+        Color::White => eval,
+        Color::Black => -eval,
+    }
+}
+
+/// The mean squared error between the game results and the expected score from the evaluation.
+/// The positions are split up over all the available threads as this is where all of the time
+/// goes.
+pub fn mean_squared_error(positions: &[Position], params: &Parameters, k: f64) -> f64 {
+    if positions.is_empty() {
+        return 0.0;
+    }
+
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    let chunk_size = positions.len().div_ceil(threads);
+
+    let total: f64 = std::thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|p| (p.result - sigmoid(k, white_eval(p, params))).powi(2))
+                        .sum::<f64>()
+                })
+            })
+            .collect();
+
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    });
+
+    total / positions.len() as f64
+}
+
+/// Finds the scaling constant for the sigmoid that best fits the current evaluation. This needs
+/// to be done before tuning so the error is not just reduced by scaling all of the weights.
+pub fn optimise_k(positions: &[Position], params: &Parameters) -> f64 {
+    let mut best_k = 1.0;
+    let mut best_error = mean_squared_error(positions, params, best_k);
+
+    for step in [0.1, 0.01, 0.001] {
+        let start = best_k;
+        for i in -10..=10 {
+            let k = start + step * i as f64;
+            if k <= 0.0 {
+                continue;
+            }
+
+            let error = mean_squared_error(positions, params, k);
+            if error < best_error {
+                best_error = error;
+                best_k = k;
+            }
+        }
+    }
+
+    best_k
+}
+
+/// Texel's local search. Each weight is nudged up and down by the step size and the change is
+/// kept if it lowers the error. This keeps going until a full pass makes no improvement or we
+/// run out of iterations. The callback is run after every pass with the current best parameters
+/// so the progress can be saved.
+pub fn local_search(
+    positions: &[Position],
+    params: &Parameters,
+    k: f64,
+    step: i32,
+    iterations: usize,
+    on_iteration: &mut dyn FnMut(usize, f64, &Parameters),
+) -> Parameters {
+    let mut values = parameters::to_vec(params);
+    let mut best_error = mean_squared_error(positions, params, k);
+
+    for iteration in 1..=iterations {
+        let mut improved = false;
+
+        for index in 0..values.len() {
+            for delta in [step, -step] {
+                values[index] += delta;
+
+                let error = mean_squared_error(positions, &parameters::from_slice(&values), k);
+                if error < best_error {
+                    best_error = error;
+                    improved = true;
+                    break;
+                }
+
+                values[index] -= delta;
+            }
+        }
+
+        on_iteration(iteration, best_error, &parameters::from_slice(&values));
+
+        if !improved {
+            break;
+        }
+    }
+
+    parameters::from_slice(&values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::parse_epd_line;
+
+    #[test]
+    fn sigmoid_is_centered_on_zero() {
+        assert_eq!(sigmoid(1.0, 0), 0.5);
+        assert!(sigmoid(1.0, 400) > 0.9);
+        assert!(sigmoid(1.0, -400) < 0.1);
+    }
+
+    #[test]
+    fn lowers_the_error() {
+        let positions: Vec<Position> = [
+            "4k3/8/8/8/8/8/PPP5/4K3 w - - [1.0]",
+            "4k3/ppp5/8/8/8/8/8/4K3 w - - [0.0]",
+            "4k3/pp6/8/8/8/8/PP6/4K3 w - - [0.5]",
+        ]
+        .iter()
+        .map(|line| parse_epd_line(line).unwrap())
+        .collect();
+
+        let params = Parameters::DEFAULT;
+        let before = mean_squared_error(&positions, &params, 1.0);
+        let tuned = local_search(&positions, &params, 1.0, 5, 1, &mut |_, _, _| {});
+        let after = mean_squared_error(&positions, &tuned, 1.0);
+
+        assert!(after < before);
+    }
+}